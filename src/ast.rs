@@ -0,0 +1,145 @@
+use byte_string::ByteString;
+
+use crate::bytecode::FilePosition;
+
+// The syntax tree of a Glass program, as read by the parser and before any bytecode is
+// generated for it. Comments are kept so that the code can be written back out, and
+// names, numbers and strings keep how they were written as well as their values.
+
+// A range of code in a file, from its first character up to just past its last
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: FilePosition,
+
+    pub end: FilePosition,
+}
+
+impl Span {
+    // The position of the last character, for nodes that end with a single closing character
+    // like the ] of a method or the \ of a loop
+    pub fn last_char(&self) -> FilePosition {
+        FilePosition { line: self.end.line, col: self.end.col - 1 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub files: Vec<File>,
+}
+
+impl Program {
+    pub fn classes(&self) -> impl Iterator<Item = &Class> {
+        self.files.iter().flat_map(|file| file.classes())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct File {
+    pub name: String,
+
+    pub items: Vec<Item>,
+
+    pub span: Span,
+}
+
+impl File {
+    pub fn classes(&self) -> impl Iterator<Item = &Class> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Class(class) => Some(class),
+            Item::Comment(_) => None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Class(Class),
+    Comment(Comment),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    // The text between the quotes
+    pub text: ByteString,
+
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Class {
+    pub name: ByteString,
+
+    pub name_span: Span,
+
+    pub items: Vec<ClassItem>,
+
+    // From the opening brace to the closing one
+    pub span: Span,
+}
+
+impl Class {
+    pub fn methods(&self) -> impl Iterator<Item = &Method> {
+        self.items.iter().filter_map(|item| match item {
+            ClassItem::Method(method) => Some(method),
+            ClassItem::Comment(_) => None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClassItem {
+    Method(Method),
+    Comment(Comment),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Method {
+    pub name: ByteString,
+
+    pub name_span: Span,
+
+    pub body: Vec<Statement>,
+
+    // From the opening bracket to the closing one
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatementKind {
+    PushName(ByteString),
+    PushString { value: ByteString, text: ByteString },
+    PushNumber { value: f64, text: String },
+    Duplicate(u8),
+    Pop,
+    Return,
+    Load,
+    Store,
+    Call,
+    LoadFrom,
+    // $, which stores the current instance in a name
+    StoreSelf,
+    // !, which creates an instance of a class and stores it in a name
+    Instantiate,
+    Loop(Loop),
+    Comment(ByteString),
+}
+
+// The statement of a loop spans from its slash to the backslash that ends it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loop {
+    pub name: ByteString,
+
+    pub name_span: Span,
+
+    // Comments between the slash and the name, which belong with the loop's first line
+    pub name_comments: Vec<Comment>,
+
+    pub body: Vec<Statement>,
+}
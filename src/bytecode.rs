@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use byte_string::ByteString;
 
+use crate::json::JsonValue;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum OpCode {
     // Opcodes implementing the basic language
@@ -25,33 +29,52 @@ pub enum OpCode {
     StoreKeep,
 
     // Opcodes implementing standard library functions
-    Add,
-    Concat,
-    Divide,
-    Equal,
-    Floor,
-    GreaterEqual,
-    GreaterThan,
-    Index,
-    InputChar,
-    InputEof,
-    InputLine,
-    Length,
-    LessEqual,
-    LessThan,
-    Modulo,
-    Multiply,
-    NotEqual,
-    NumToString,
-    OutputNumber,
-    OutputString,
-    StringEqual,
-    StringReplace,
-    StringSplit,
-    StringToNum,
-    Subtract,
-    VarDelete,
-    VarNew,
+    CallNative,
+}
+
+// Every opcode, in the same order as they're declared, so they can be looked up by value
+const ALL_OPCODES: [OpCode; 19] = [
+    OpCode::Call,
+    OpCode::Construct,
+    OpCode::Duplicate,
+    OpCode::Instantiate,
+    OpCode::JumpIf,
+    OpCode::JumpIfNot,
+    OpCode::Load,
+    OpCode::LoadFrom,
+    OpCode::Pop,
+    OpCode::PushGlobal,
+    OpCode::PushLocal,
+    OpCode::PushMember,
+    OpCode::PushNumber,
+    OpCode::PushSelf,
+    OpCode::PushString,
+    OpCode::Return,
+    OpCode::Store,
+    OpCode::StoreKeep,
+    OpCode::CallNative,
+];
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        ALL_OPCODES.get(byte as usize).copied()
+    }
+
+    // The number of bytes of operands that follow the opcode in the instruction stream
+    pub fn operand_size(self) -> usize {
+        match self {
+            OpCode::Duplicate => 1,
+            OpCode::CallNative
+            | OpCode::JumpIf
+            | OpCode::JumpIfNot
+            | OpCode::PushGlobal
+            | OpCode::PushLocal
+            | OpCode::PushMember
+            | OpCode::PushNumber
+            | OpCode::PushString => 2,
+            _ => 0,
+        }
+    }
 }
 
 pub type LocalName = u16;
@@ -62,6 +85,9 @@ pub type GlobalName = u16;
 
 pub type OpcodeIndex = usize;
 
+pub const BUILTIN_FILE: &str = "<builtin>";
+
+#[derive(Clone, Default)]
 pub struct ClassDefinition {
     pub funcs: HashMap<MemberName, OpcodeIndex>,
 
@@ -74,13 +100,37 @@ impl ClassDefinition {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct FilePosition {
     pub line: usize,
 
     pub col: usize,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+
+    pub pos: FilePosition,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.pos.line, self.pos.col)
+    }
+}
+
+impl SourceLocation {
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::object(vec![
+            ("file", JsonValue::string(&self.file)),
+            ("line", self.pos.line.into()),
+            ("column", self.pos.col.into()),
+        ])
+    }
+}
+
+#[derive(Clone)]
 pub struct BytecodeProgram {
     pub class_names: Vec<GlobalName>,
 
@@ -99,4 +149,179 @@ pub struct BytecodeProgram {
     pub files: Vec<(OpcodeIndex, String)>,
 
     pub positions: Vec<(OpcodeIndex, FilePosition)>,
+
+    pub global_names: Vec<ByteString>,
+
+    pub member_names: Vec<ByteString>,
+
+    pub local_names: Vec<ByteString>,
+
+    // The class and method names of the native methods the CallNative opcode refers to
+    pub native_methods: Vec<(ByteString, ByteString)>,
+}
+
+// Gets how a name would be written in source code, with parentheses if needed
+pub(crate) fn name_spelling(name: &ByteString) -> String {
+    if name.len() == 1 && name[0].is_ascii_alphabetic() {
+        (name[0] as char).to_string()
+    }
+    else {
+        format!("({})", String::from_utf8_lossy(name))
+    }
+}
+
+impl BytecodeProgram {
+    pub fn source_location(&self, index: OpcodeIndex) -> SourceLocation {
+        // Both tables are sorted by index, so the entry that applies is the last one at or
+        // before the instruction
+        let file_entry = self.files.partition_point(|(file_index, _)| *file_index <= index);
+        let pos_entry = self.positions.partition_point(|(pos_index, _)| *pos_index <= index);
+
+        let file = &self.files[file_entry.saturating_sub(1)].1;
+        let pos = self.positions[pos_entry.saturating_sub(1)].1;
+
+        SourceLocation { file: file.clone(), pos }
+    }
+
+    pub fn global_name_str(&self, name: GlobalName) -> String {
+        name_spelling(&self.global_names[name as usize])
+    }
+
+    pub fn member_name_str(&self, name: MemberName) -> String {
+        name_spelling(&self.member_names[name as usize])
+    }
+
+    pub fn local_name_str(&self, name: LocalName) -> String {
+        name_spelling(&self.local_names[name as usize])
+    }
+
+    // Finds the class and function that the instruction at the given index belongs to
+    pub fn function_at(&self, index: OpcodeIndex) -> Option<(GlobalName, MemberName)> {
+        let mut found = None;
+
+        for (class_name, class) in self.class_names.iter().zip(self.classes.iter()) {
+            for (func_name, func_index) in class.funcs.iter() {
+                match found {
+                    Some((_, _, found_index)) if found_index >= *func_index => {},
+                    _ if *func_index <= index => found = Some((*class_name, *func_name, *func_index)),
+                    _ => {},
+                }
+            }
+        }
+
+        found.map(|(class_name, func_name, _)| (class_name, func_name))
+    }
+
+    // Gets the name of the function containing an instruction, written like "S.si"
+    pub fn function_name(&self, index: OpcodeIndex) -> Option<String> {
+        let (class_name, func_name) = self.function_at(index)?;
+
+        Some(format!(
+            "{}.{}",
+            String::from_utf8_lossy(&self.global_names[class_name as usize]),
+            String::from_utf8_lossy(&self.member_names[func_name as usize]),
+        ))
+    }
+
+    // Gets the name of the builtin function containing an instruction, if it's in one
+    pub fn builtin_name(&self, index: OpcodeIndex) -> Option<String> {
+        if self.source_location(index).file == BUILTIN_FILE {
+            self.function_name(index)
+        }
+        else {
+            None
+        }
+    }
+}
+
+// Describes the operand of the instruction at the given index, resolving any constants
+// and names it refers to
+fn format_operand(program: &BytecodeProgram, opcode: OpCode, index: OpcodeIndex) -> String {
+    let operand = match opcode.operand_size() {
+        1 => program.instructions[index + 1] as usize,
+        2 => ((program.instructions[index + 1] as usize) << 8) | program.instructions[index + 2] as usize,
+        _ => return String::new(),
+    };
+
+    let resolved = match opcode {
+        OpCode::CallNative => program.native_methods.get(operand).map(|(class_name, method_name)| {
+            format!("{}.{}", String::from_utf8_lossy(class_name), String::from_utf8_lossy(method_name))
+        }),
+        OpCode::Duplicate => return operand.to_string(),
+        OpCode::JumpIf => index.checked_add(3).and_then(|next| next.checked_sub(operand)).map(|target| format!("-> {:06}", target)),
+        OpCode::JumpIfNot => Some(format!("-> {:06}", index + 3 + operand)),
+        OpCode::PushGlobal => program.global_names.get(operand).map(|_| program.global_name_str(operand as GlobalName)),
+        OpCode::PushLocal => program.local_names.get(operand).map(|_| program.local_name_str(operand as LocalName)),
+        OpCode::PushMember => program.member_names.get(operand).map(|_| program.member_name_str(operand as MemberName)),
+        OpCode::PushNumber => program.numbers.get(operand).map(|num| format!("<{}>", num)),
+        OpCode::PushString => program.strings.get(operand).map(|string| format!("{:?}", String::from_utf8_lossy(string))),
+        _ => None,
+    };
+
+    match resolved {
+        Some(resolved) => resolved,
+        None => format!("<invalid operand {}>", operand),
+    }
+}
+
+// Renders the instruction at the given index as text, along with the index of the
+// instruction that follows it
+pub fn format_instruction(program: &BytecodeProgram, index: OpcodeIndex) -> (String, OpcodeIndex) {
+    let byte = program.instructions[index];
+
+    let opcode = match OpCode::from_byte(byte) {
+        Some(opcode) => opcode,
+        None => return (format!("<invalid opcode 0x{:02x}>", byte), index + 1),
+    };
+
+    let next_index = index + 1 + opcode.operand_size();
+    if next_index > program.instructions.len() {
+        return (format!("{:?} <truncated>", opcode), program.instructions.len());
+    }
+
+    let operand = format_operand(program, opcode, index);
+    if operand.is_empty() {
+        (format!("{:?}", opcode), next_index)
+    }
+    else {
+        (format!("{:<14}{}", format!("{:?}", opcode), operand), next_index)
+    }
+}
+
+// Renders the whole program as a listing with one instruction per line, labelled with
+// the functions they belong to and the source positions they came from
+pub fn disassemble(program: &BytecodeProgram) -> String {
+    let mut func_starts: HashSet<OpcodeIndex> = HashSet::new();
+    for class in program.classes.iter() {
+        func_starts.extend(class.funcs.values());
+    }
+
+    let mut listing = String::new();
+    let mut index = 0;
+    let mut file = "";
+    let mut files = program.files.iter().peekable();
+    let mut pos = FilePosition { line: 0, col: 0 };
+    let mut positions = program.positions.iter().peekable();
+
+    while index < program.instructions.len() {
+        while let Some((_, filename)) = files.next_if(|(file_index, _)| *file_index <= index) {
+            file = filename;
+        }
+        while let Some((_, file_pos)) = positions.next_if(|(pos_index, _)| *pos_index <= index) {
+            pos = *file_pos;
+        }
+
+        if func_starts.contains(&index) {
+            if let Some(func_name) = program.function_name(index) {
+                listing.push_str(&format!("{}:\n", func_name));
+            }
+        }
+
+        let (instruction, next_index) = format_instruction(program, index);
+        let location = SourceLocation { file: file.to_owned(), pos };
+        listing.push_str(&format!("  {:06}  {:<40}{}\n", index, instruction, location));
+        index = next_index;
+    }
+
+    listing
 }
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::bytecode::*;
+use crate::hook::{ExecutionHook, ExecutionState};
+use crate::interpreter::RuntimeError;
+
+// The size of a conditional jump, which is where execution carries on if it doesn't jump
+const JUMP_SIZE: usize = 3;
+
+// Where a loop's jump goes, and how often it went each way
+struct BranchCoverage {
+    index: OpcodeIndex,
+
+    pos: FilePosition,
+
+    // The jump at the start of a loop, rather than the one at the end
+    loop_start: bool,
+
+    jumped: u64,
+
+    fell_through: u64,
+}
+
+impl BranchCoverage {
+    fn branches_hit(&self) -> usize {
+        (self.jumped > 0) as usize + (self.fell_through > 0) as usize
+    }
+
+    // Describes the ways the jump never went
+    fn missed(&self) -> Vec<&'static str> {
+        if self.jumped == 0 && self.fell_through == 0 {
+            return vec![if self.loop_start { "loop never reached" } else { "loop end never reached" }];
+        }
+
+        let (jumped, fell_through) = if self.loop_start {
+            ("loop never skipped", "loop never entered")
+        }
+        else {
+            ("loop never repeated", "loop never left")
+        };
+
+        let mut missed = Vec::new();
+        if self.jumped == 0 {
+            missed.push(jumped);
+        }
+        if self.fell_through == 0 {
+            missed.push(fell_through);
+        }
+        missed
+    }
+}
+
+struct FunctionCoverage {
+    class_name: String,
+
+    name: String,
+
+    file: String,
+
+    first_line: usize,
+
+    calls: u64,
+
+    // How many times each line of the function was run
+    lines: BTreeMap<usize, u64>,
+
+    branches: Vec<BranchCoverage>,
+}
+
+// Totals for a file, class or function
+#[derive(Default)]
+struct Totals {
+    lines: usize,
+
+    lines_hit: usize,
+
+    branches: usize,
+
+    branches_hit: usize,
+
+    functions: usize,
+
+    functions_hit: usize,
+}
+
+impl Totals {
+    fn add_function(&mut self, function: &FunctionCoverage) {
+        self.branches += function.branches.len() * 2;
+        self.branches_hit += function.branches.iter().map(|branch| branch.branches_hit()).sum::<usize>();
+        self.functions += 1;
+        self.functions_hit += (function.calls > 0) as usize;
+    }
+
+    fn add_lines(&mut self, lines: &BTreeMap<usize, u64>) {
+        self.lines += lines.len();
+        self.lines_hit += lines.values().filter(|count| **count > 0).count();
+    }
+
+    fn describe(&self) -> String {
+        let percent = |hit: usize, total: usize| if total == 0 { 100.0 } else { hit as f64 * 100.0 / total as f64 };
+
+        let mut description = format!("{}/{} lines ({:.1}%)", self.lines_hit, self.lines, percent(self.lines_hit, self.lines));
+        if self.branches > 0 {
+            description.push_str(&format!(", {}/{} branches ({:.1}%)", self.branches_hit, self.branches, percent(self.branches_hit, self.branches)));
+        }
+        description
+    }
+}
+
+// Merges the lines of several functions, which can share a line
+fn merge_lines<'f>(functions: impl Iterator<Item = &'f FunctionCoverage>) -> BTreeMap<usize, u64> {
+    let mut lines = BTreeMap::new();
+    for function in functions {
+        for (line, count) in function.lines.iter() {
+            let merged = lines.entry(*line).or_insert(0);
+            *merged = (*merged).max(*count);
+        }
+    }
+    lines
+}
+
+// The files that functions are in, in the order they first appear
+fn files_of(functions: &[FunctionCoverage]) -> Vec<&str> {
+    let mut files: Vec<&str> = Vec::new();
+    for function in functions {
+        if !files.contains(&function.file.as_str()) {
+            files.push(&function.file);
+        }
+    }
+    files
+}
+
+// Records which instructions run and which way each loop's jumps go, to find the code a
+// program never reaches. Builtin code isn't included in the results.
+pub struct Coverage {
+    counts: Vec<u64>,
+
+    // How many times each conditional jump jumped, and how many times it didn't
+    jumps: BTreeMap<OpcodeIndex, (u64, u64)>,
+
+    // The conditional jump that's just run, if the last instruction was one
+    last_jump: Option<OpcodeIndex>,
+}
+
+impl Coverage {
+    pub fn new(program: &BytecodeProgram) -> Coverage {
+        Coverage {
+            counts: vec![0; program.instructions.len()],
+            jumps: BTreeMap::new(),
+            last_jump: None,
+        }
+    }
+
+    // Works out the coverage of every function in the program, in the order they appear
+    fn functions(&self, program: &BytecodeProgram) -> Vec<FunctionCoverage> {
+        let mut starts: Vec<(OpcodeIndex, GlobalName, MemberName)> = Vec::new();
+        for (class_name, class) in program.class_names.iter().zip(program.classes.iter()) {
+            for (func_name, func_index) in class.funcs.iter() {
+                starts.push((*func_index, *class_name, *func_name));
+            }
+        }
+        starts.sort();
+
+        let mut functions = Vec::new();
+        for (i, (start, class_name, func_name)) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(program.instructions.len(), |(next_start, _, _)| *next_start);
+
+            let file = program.source_location(*start).file;
+            if file == BUILTIN_FILE {
+                continue;
+            }
+
+            let mut function = FunctionCoverage {
+                class_name: program.global_name_str(*class_name),
+                name: format!("{}.{}", program.global_name_str(*class_name), program.member_name_str(*func_name)),
+                file,
+                first_line: program.source_location(*start).pos.line,
+                calls: self.counts.get(*start).copied().unwrap_or(0),
+                lines: BTreeMap::new(),
+                branches: Vec::new(),
+            };
+
+            let mut index = *start;
+            while index < end {
+                let opcode = OpCode::from_byte(program.instructions[index]).expect("verified program has only valid opcodes");
+                let pos = program.source_location(index).pos;
+                let count = self.counts.get(index).copied().unwrap_or(0);
+
+                let line_count = function.lines.entry(pos.line).or_insert(0);
+                *line_count = (*line_count).max(count);
+
+                if let OpCode::JumpIf | OpCode::JumpIfNot = opcode {
+                    let (jumped, fell_through) = self.jumps.get(&index).copied().unwrap_or((0, 0));
+                    function.branches.push(BranchCoverage { index, pos, loop_start: opcode == OpCode::JumpIfNot, jumped, fell_through });
+                }
+
+                index += 1 + opcode.operand_size();
+            }
+
+            functions.push(function);
+        }
+
+        functions
+    }
+
+    // Writes the coverage of each file, class and function, along with the lines and loops
+    // that never ran
+    pub fn write_summary(&self, program: &BytecodeProgram, output: &mut impl Write) -> std::io::Result<()> {
+        let functions = self.functions(program);
+
+        for file in files_of(&functions) {
+            let file_functions: Vec<_> = functions.iter().filter(|function| function.file == *file).collect();
+
+            let mut file_totals = Totals::default();
+            file_totals.add_lines(&merge_lines(file_functions.iter().copied()));
+            file_functions.iter().for_each(|function| file_totals.add_function(function));
+
+            writeln!(output, "{}: {}, {}/{} functions", file, file_totals.describe(), file_totals.functions_hit, file_totals.functions)?;
+
+            let mut class_names: Vec<&str> = Vec::new();
+            for function in file_functions.iter() {
+                if !class_names.contains(&function.class_name.as_str()) {
+                    class_names.push(&function.class_name);
+                }
+            }
+
+            for class_name in class_names {
+                let class_functions: Vec<_> = file_functions.iter().copied().filter(|function| function.class_name == class_name).collect();
+
+                let mut class_totals = Totals::default();
+                class_totals.add_lines(&merge_lines(class_functions.iter().copied()));
+                class_functions.iter().for_each(|function| class_totals.add_function(function));
+
+                writeln!(output, "  {}: {}, {}/{} functions", class_name, class_totals.describe(), class_totals.functions_hit, class_totals.functions)?;
+
+                for function in class_functions {
+                    if function.calls == 0 {
+                        writeln!(output, "    {}: never called", function.name)?;
+                        continue;
+                    }
+
+                    let mut totals = Totals::default();
+                    totals.add_lines(&function.lines);
+                    totals.add_function(function);
+
+                    let plural = if function.calls == 1 { "" } else { "s" };
+                    writeln!(output, "    {}: {}, called {} time{}", function.name, totals.describe(), function.calls, plural)?;
+
+                    let missed_lines: Vec<_> = function.lines.iter()
+                        .filter(|(_, count)| **count == 0)
+                        .map(|(line, _)| line.to_string())
+                        .collect();
+                    if !missed_lines.is_empty() {
+                        let plural = if missed_lines.len() == 1 { "" } else { "s" };
+                        writeln!(output, "      never run: line{} {}", plural, missed_lines.join(", "))?;
+                    }
+
+                    for branch in function.branches.iter() {
+                        for missed in branch.missed() {
+                            writeln!(output, "      {} at line {}, column {}", missed, branch.pos.line, branch.pos.col)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes the coverage in the lcov tracefile format
+    pub fn write_lcov(&self, program: &BytecodeProgram, output: &mut impl Write) -> std::io::Result<()> {
+        let functions = self.functions(program);
+
+        writeln!(output, "TN:")?;
+        for file in files_of(&functions) {
+            let file_functions: Vec<_> = functions.iter().filter(|function| function.file == file).collect();
+
+            writeln!(output, "SF:{}", file)?;
+
+            for function in file_functions.iter() {
+                writeln!(output, "FN:{},{}", function.first_line, function.name)?;
+            }
+            for function in file_functions.iter() {
+                writeln!(output, "FNDA:{},{}", function.calls, function.name)?;
+            }
+            writeln!(output, "FNF:{}", file_functions.len())?;
+            writeln!(output, "FNH:{}", file_functions.iter().filter(|function| function.calls > 0).count())?;
+
+            let mut branches = 0;
+            let mut branches_hit = 0;
+            for function in file_functions.iter() {
+                for branch in function.branches.iter() {
+                    let executed = branch.jumped + branch.fell_through > 0;
+                    for (number, taken) in [branch.jumped, branch.fell_through].into_iter().enumerate() {
+                        let taken = if executed { taken.to_string() } else { "-".to_owned() };
+                        writeln!(output, "BRDA:{},{},{},{}", branch.pos.line, branch.index, number, taken)?;
+                    }
+                    branches += 2;
+                    branches_hit += branch.branches_hit();
+                }
+            }
+            writeln!(output, "BRF:{}", branches)?;
+            writeln!(output, "BRH:{}", branches_hit)?;
+
+            let lines = merge_lines(file_functions.iter().copied());
+            for (line, count) in lines.iter() {
+                writeln!(output, "DA:{},{}", line, count)?;
+            }
+            writeln!(output, "LF:{}", lines.len())?;
+            writeln!(output, "LH:{}", lines.values().filter(|count| **count > 0).count())?;
+
+            writeln!(output, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ExecutionHook for Coverage {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        let index = state.opcode_index();
+
+        if let Some(jump) = self.last_jump.take() {
+            let (jumped, fell_through) = self.jumps.entry(jump).or_insert((0, 0));
+            if index == jump + JUMP_SIZE {
+                *fell_through += 1;
+            }
+            else {
+                *jumped += 1;
+            }
+        }
+
+        // Code can be added to the program while it's running
+        if index >= self.counts.len() {
+            self.counts.resize(state.program().instructions.len(), 0);
+        }
+        self.counts[index] += 1;
+
+        if let OpCode::JumpIf | OpCode::JumpIfNot = OpCode::from_byte(state.program().instructions[index]).expect("verified program has only valid opcodes") {
+            self.last_jump = Some(index);
+        }
+
+        Ok(())
+    }
+}
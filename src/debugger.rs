@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::bytecode::*;
+use crate::hook::{ExecutionHook, ExecutionState};
+use crate::interpreter::RuntimeError;
+
+const PROMPT: &[u8] = b"(debug) ";
+
+const HELP: &str = "\
+Commands:
+  break <file>:<line>    Stop when reaching a line
+  break <Class>.<func>   Stop when a function is called
+  delete <n>             Remove breakpoint n
+  breakpoints            List the breakpoints
+  step, s                Run until the next statement, including in called functions
+  next, n                Run until the next statement in this function or its callers
+  stepi, si              Run a single instruction
+  finish                 Run until the current function returns
+  continue, c            Run until a breakpoint is reached
+  stack                  Show the values on the stack
+  locals                 Show the current function's local variables
+  members                Show the current instance's member variables
+  globals                Show the global variables
+  backtrace, bt          Show the call stack
+  quit, q                Stop the program
+";
+
+enum Breakpoint {
+    Line { file: String, line: usize },
+    Function { name: String, start: OpcodeIndex },
+}
+
+impl Breakpoint {
+    fn describe(&self) -> String {
+        match self {
+            Breakpoint::Line { file, line } => format!("{}:{}", file, line),
+            Breakpoint::Function { name, .. } => name.clone(),
+        }
+    }
+}
+
+// What to run until before stopping again
+#[derive(Clone, Copy)]
+enum StepMode {
+    Continue,
+    Instruction,
+    Statement,
+    // Stepping over calls from the function at the given depth
+    Over(usize),
+    // Returning from the function at the given depth
+    Finish(usize),
+}
+
+// An interactive debugger, which writes to the program's output. Its commands are read
+// from their own stream, so that they don't get mixed up with what the program reads from
+// its input. It stops before the first instruction, so that breakpoints can be set.
+pub struct Debugger {
+    commands: Box<dyn BufRead>,
+
+    // The source location of each instruction that starts a statement, outside of builtins
+    statements: HashMap<OpcodeIndex, SourceLocation>,
+
+    breakpoints: Vec<Option<Breakpoint>>,
+
+    mode: StepMode,
+
+    // The line and index of the last statement run at each call depth, so that a line
+    // breakpoint only stops once each time a line is reached. Going back to an earlier
+    // statement, like at the end of a loop, counts as reaching the line again.
+    frame_lines: Vec<Option<(String, usize, OpcodeIndex)>>,
+
+    last_depth: usize,
+}
+
+impl Debugger {
+    pub fn new(program: &BytecodeProgram, commands: impl BufRead + 'static) -> Debugger {
+        let mut statements = HashMap::new();
+        for (index, _) in program.positions.iter() {
+            let location = program.source_location(*index);
+            if location.file != BUILTIN_FILE {
+                statements.insert(*index, location);
+            }
+        }
+
+        Debugger {
+            commands: Box::new(commands),
+            statements,
+            breakpoints: Vec::new(),
+            mode: StepMode::Instruction,
+            frame_lines: Vec::new(),
+            last_depth: 0,
+        }
+    }
+
+    fn hit_breakpoint(&self, index: OpcodeIndex, depth: usize) -> Option<usize> {
+        let statement = self.statements.get(&index);
+
+        self.breakpoints.iter().position(|breakpoint| match (breakpoint, statement) {
+            (Some(Breakpoint::Function { start, .. }), _) => *start == index,
+            (Some(Breakpoint::Line { file, line }), Some(location)) => {
+                let still_on_line = match self.frame_lines.get(depth) {
+                    Some(Some((last_file, last_line, last_index))) => last_file == file && last_line == line && *last_index < index,
+                    _ => false,
+                };
+                location.file == *file && location.pos.line == *line && !still_on_line
+            },
+            _ => false,
+        })
+    }
+
+    fn add_breakpoint(&mut self, program: &BytecodeProgram, spec: &str) -> Result<Breakpoint, String> {
+        if let Some((file, line)) = spec.rsplit_once(':') {
+            let line = line.parse().map_err(|_| format!("Invalid line number {}", line))?;
+
+            // Files can be given by their full path or just their name
+            let location = self.statements.values().find(|location| {
+                location.pos.line == line && (location.file == file || location.file.ends_with(&format!("/{}", file)))
+            });
+
+            return match location {
+                Some(location) => Ok(Breakpoint::Line { file: location.file.clone(), line }),
+                None => Err(format!("No code on line {} of {}", line, file)),
+            };
+        }
+
+        if let Some((class_name, func_name)) = spec.split_once('.') {
+            for (name, class) in program.class_names.iter().zip(program.classes.iter()) {
+                if program.global_name_str(*name) != class_name && program.global_names[*name as usize].as_slice() != class_name.as_bytes() {
+                    continue;
+                }
+
+                for (func, start) in class.funcs.iter() {
+                    if program.member_name_str(*func) == func_name || program.member_names[*func as usize].as_slice() == func_name.as_bytes() {
+                        return Ok(Breakpoint::Function { name: spec.to_owned(), start: *start });
+                    }
+                }
+            }
+
+            return Err(format!("No function {}", spec));
+        }
+
+        Err(format!("Invalid breakpoint {}, expected <file>:<line> or <Class>.<func>", spec))
+    }
+
+    fn write_location(&self, state: &mut ExecutionState) -> std::io::Result<()> {
+        let index = state.opcode_index();
+        let program = state.program();
+        let location = program.source_location(index);
+        let func_name = program.function_name(index).unwrap_or_else(|| "<unknown>".to_owned());
+        let (instruction, _) = format_instruction(program, index);
+
+        let message = format!("{}:{}:{} in {}\n  {:06}  {}\n", location.file, location.pos.line, location.pos.col, func_name, index, instruction);
+        state.output().write_all(message.as_bytes())
+    }
+
+    fn write_names(output: &mut dyn Write, names: Vec<(String, String)>, empty_message: &str) -> std::io::Result<()> {
+        if names.is_empty() {
+            return writeln!(output, "{}", empty_message);
+        }
+
+        for (name, value) in names {
+            writeln!(output, "  {} = {}", name, value)?;
+        }
+        Ok(())
+    }
+
+    // Runs commands until one of them resumes the program. Returns false to stop the program.
+    fn pause(&mut self, state: &mut ExecutionState) -> Result<bool, std::io::Error> {
+        self.write_location(state)?;
+
+        loop {
+            state.output().write_all(PROMPT)?;
+            state.output().flush()?;
+
+            let mut line = Vec::new();
+            if self.commands.read_until(b'\n', &mut line)? == 0 {
+                state.output().write_all(b"\n")?;
+                return Ok(false);
+            }
+
+            let line = String::from_utf8_lossy(&line).into_owned();
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next();
+
+            match (command, argument) {
+                ("", _) => {},
+                ("break" | "b", Some(spec)) => match self.add_breakpoint(state.program(), spec) {
+                    Ok(breakpoint) => {
+                        self.breakpoints.push(Some(breakpoint));
+                        writeln!(state.output(), "Breakpoint {} at {}", self.breakpoints.len(), spec)?;
+                    },
+                    Err(message) => writeln!(state.output(), "{}", message)?,
+                },
+                ("delete" | "d", Some(number)) => {
+                    match number.parse::<usize>().ok().and_then(|number| self.breakpoints.get_mut(number.wrapping_sub(1))) {
+                        Some(breakpoint @ Some(_)) => *breakpoint = None,
+                        _ => writeln!(state.output(), "No breakpoint {}", number)?,
+                    }
+                },
+                ("breakpoints", None) => {
+                    let mut any = false;
+                    for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                        if let Some(breakpoint) = breakpoint {
+                            writeln!(state.output(), "  {}: {}", i + 1, breakpoint.describe())?;
+                            any = true;
+                        }
+                    }
+                    if !any {
+                        writeln!(state.output(), "No breakpoints")?;
+                    }
+                },
+                ("step" | "s", None) => {
+                    self.mode = StepMode::Statement;
+                    return Ok(true);
+                },
+                ("next" | "n", None) => {
+                    self.mode = StepMode::Over(state.call_depth());
+                    return Ok(true);
+                },
+                ("stepi" | "si", None) => {
+                    self.mode = StepMode::Instruction;
+                    return Ok(true);
+                },
+                ("finish", None) => {
+                    self.mode = StepMode::Finish(state.call_depth());
+                    return Ok(true);
+                },
+                ("continue" | "c", None) => {
+                    self.mode = StepMode::Continue;
+                    return Ok(true);
+                },
+                ("stack", None) => {
+                    let stack = state.stack();
+                    if stack.is_empty() {
+                        writeln!(state.output(), "Stack is empty")?;
+                    }
+                    for (i, value) in stack.iter().enumerate().rev() {
+                        writeln!(state.output(), "  [{}] {}", stack.len() - 1 - i, value)?;
+                    }
+                },
+                ("locals", None) => {
+                    let names = state.locals();
+                    Self::write_names(state.output(), names, "No local variables")?;
+                },
+                ("members", None) => {
+                    let names = state.members();
+                    Self::write_names(state.output(), names, "No member variables")?;
+                },
+                ("globals", None) => {
+                    let names = state.globals();
+                    Self::write_names(state.output(), names, "No global variables")?;
+                },
+                ("backtrace" | "bt", None) => {
+                    let call_stack = state.call_stack();
+                    for (i, index) in call_stack.iter().rev().enumerate() {
+                        let program = state.program();
+                        let location = program.source_location(*index);
+                        let func_name = program.function_name(*index).unwrap_or_else(|| "<unknown>".to_owned());
+                        let frame = format!("  #{} {} at {}:{}:{}\n", i, func_name, location.file, location.pos.line, location.pos.col);
+                        state.output().write_all(frame.as_bytes())?;
+                    }
+                },
+                ("quit" | "q", None) => return Ok(false),
+                ("help" | "h", None) => state.output().write_all(HELP.as_bytes())?,
+                _ => writeln!(state.output(), "Unknown command {}, try help", line.trim())?,
+            }
+        }
+    }
+}
+
+impl ExecutionHook for Debugger {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        let index = state.opcode_index();
+        let depth = state.call_depth();
+
+        // A deeper call is a new function, so forget what ran at that depth before
+        if depth > self.last_depth {
+            self.frame_lines.truncate(self.last_depth + 1);
+        }
+        self.last_depth = depth;
+
+        let is_statement = self.statements.contains_key(&index);
+        let should_stop = match self.mode {
+            StepMode::Continue => false,
+            StepMode::Instruction => true,
+            StepMode::Statement => is_statement,
+            StepMode::Over(max_depth) => is_statement && depth <= max_depth,
+            StepMode::Finish(call_depth) => depth < call_depth,
+        };
+        let breakpoint = self.hit_breakpoint(index, depth);
+
+        if let Some(location) = self.statements.get(&index) {
+            if self.frame_lines.len() <= depth {
+                self.frame_lines.resize(depth + 1, None);
+            }
+            self.frame_lines[depth] = Some((location.file.clone(), location.pos.line, index));
+        }
+
+        if !should_stop && breakpoint.is_none() {
+            return Ok(());
+        }
+
+        if let Some(number) = breakpoint {
+            write!(state.output(), "Breakpoint {}, ", number + 1).map_err(|err| RuntimeError::IOError(err.to_string()))?;
+        }
+
+        match self.pause(state) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(RuntimeError::Stopped),
+            Err(err) => Err(RuntimeError::IOError(err.to_string())),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use byte_string::ByteString;
+
+use crate::ast::*;
+use crate::parser::{parse_file_ast, ParseError};
+
+const INDENT: &[u8] = b"  ";
+
+// The longest function that's kept on the same line as its name
+const MAX_ONE_LINE_FUNCTION: usize = 60;
+
+// How long a line of calls can get before it's split
+const MAX_LINE: usize = 60;
+
+// A line of formatted code, along with the last source line it has code from
+struct Line {
+    indent: usize,
+
+    text: Vec<u8>,
+
+    source_line: usize,
+}
+
+fn name_text(name: &ByteString) -> Vec<u8> {
+    if name.len() == 1 && name[0].is_ascii_alphabetic() {
+        name.to_vec()
+    }
+    else {
+        [b"(", name.as_slice(), b")"].concat()
+    }
+}
+
+fn comment_text(text: &ByteString) -> Vec<u8> {
+    [b"'", text.as_slice(), b"'"].concat()
+}
+
+fn statement_text(kind: &StatementKind) -> Vec<u8> {
+    match kind {
+        StatementKind::PushName(name) => name_text(name),
+        StatementKind::PushString { text, .. } => [b"\"", text.as_slice(), b"\""].concat(),
+        StatementKind::PushNumber { text, .. } => format!("<{}>", text).into_bytes(),
+        StatementKind::Duplicate(index) if *index < 10 => index.to_string().into_bytes(),
+        StatementKind::Duplicate(index) => format!("({})", index).into_bytes(),
+        StatementKind::Pop => b",".to_vec(),
+        StatementKind::Return => b"^".to_vec(),
+        StatementKind::Load => b"*".to_vec(),
+        StatementKind::Store => b"=".to_vec(),
+        StatementKind::Call => b"?".to_vec(),
+        StatementKind::LoadFrom => b".".to_vec(),
+        StatementKind::StoreSelf => b"$".to_vec(),
+        StatementKind::Instantiate => b"!".to_vec(),
+        StatementKind::Loop(_) | StatementKind::Comment(_) => unreachable!(),
+    }
+}
+
+// Whether a command finishes what's been built up on the stack, so it ends a line
+fn ends_line(kind: &StatementKind) -> bool {
+    matches!(kind, StatementKind::Store | StatementKind::Pop | StatementKind::Return | StatementKind::StoreSelf | StatementKind::Instantiate)
+}
+
+// Lays out the commands of a function or loop, putting each group of commands that ends
+// in a store, pop, return or instantiation on its own line, and the body of each loop on
+// lines of its own with more indentation
+fn format_statements(statements: &[Statement], indent: usize, lines: &mut Vec<Line>) {
+    let mut current = Line { indent, text: Vec::new(), source_line: 0 };
+    let mut after_store = false;
+
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Loop(body) => {
+                if !current.text.is_empty() {
+                    lines.push(std::mem::replace(&mut current, Line { indent, text: Vec::new(), source_line: 0 }));
+                }
+
+                // Comments before the name, or after it on the same line, are kept with it
+                let name_line = body.name_span.end.line;
+                let same_line = body.body.iter()
+                    .take_while(|statement| matches!(statement.kind, StatementKind::Comment(_)) && statement.span.start.line == name_line)
+                    .count();
+
+                let mut text = [b"/", name_text(&body.name).as_slice()].concat();
+                for comment in &body.name_comments {
+                    text.push(b' ');
+                    text.extend(comment_text(&comment.text));
+                }
+                for statement in &body.body[..same_line] {
+                    if let StatementKind::Comment(comment) = &statement.kind {
+                        text.push(b' ');
+                        text.extend(comment_text(comment));
+                    }
+                }
+
+                lines.push(Line { indent, text, source_line: name_line });
+                format_statements(&body.body[same_line..], indent + 1, lines);
+                lines.push(Line { indent, text: b"\\".to_vec(), source_line: statement.span.end.line });
+            },
+            StatementKind::Comment(text) => {
+                // Comments stay on the same line as the code they were next to
+                let previous = lines.last_mut().filter(|line| line.indent == indent && line.source_line == statement.span.start.line);
+                match previous {
+                    _ if !current.text.is_empty() => {
+                        current.text.push(b' ');
+                        current.text.extend(comment_text(text));
+                        current.text.push(b' ');
+                    },
+                    Some(line) => {
+                        line.text.push(b' ');
+                        line.text.extend(comment_text(text));
+                    },
+                    None => lines.push(Line { indent, text: comment_text(text), source_line: statement.span.start.line }),
+                }
+            },
+            // A pop straight after a store belongs with it
+            StatementKind::Pop if current.text.is_empty() && after_store => {
+                let line = lines.last_mut().expect("a store with no line");
+                line.text.extend_from_slice(b",");
+                line.source_line = statement.span.start.line;
+            },
+            kind => {
+                current.text.extend(statement_text(kind));
+                current.source_line = statement.span.start.line;
+
+                // Long chains of calls are split after a call
+                if ends_line(kind) || (matches!(kind, StatementKind::Call) && current.text.len() > MAX_LINE) {
+                    lines.push(std::mem::replace(&mut current, Line { indent, text: Vec::new(), source_line: 0 }));
+                }
+            },
+        }
+
+        after_store = matches!(statement.kind, StatementKind::Store);
+    }
+
+    if !current.text.is_empty() {
+        lines.push(current);
+    }
+}
+
+// Every command ends in something other than a space, so this only takes off the spaces
+// left after a comment
+fn trim_end(text: &[u8]) -> &[u8] {
+    let end = text.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+    &text[..end]
+}
+
+fn write_lines(output: &mut Vec<u8>, lines: &[Line]) {
+    for line in lines {
+        for _ in 0..line.indent {
+            output.extend_from_slice(INDENT);
+        }
+        output.extend_from_slice(trim_end(&line.text));
+        output.push(b'\n');
+    }
+}
+
+// Writes a function, on one line if it's short enough, and gives whether it took more than one
+fn format_method(output: &mut Vec<u8>, method: &Method) -> bool {
+    let mut lines = Vec::new();
+    format_statements(&method.body, 2, &mut lines);
+
+    let name = name_text(&method.name);
+    if lines.is_empty() {
+        output.extend_from_slice(INDENT);
+        output.extend([b"[", name.as_slice(), b"]\n"].concat());
+        return false;
+    }
+
+    let one_line = lines.len() == 1 && !lines[0].text.contains(&b'\n')
+        && lines[0].text.len() + name.len() < MAX_ONE_LINE_FUNCTION;
+
+    output.extend_from_slice(INDENT);
+    output.push(b'[');
+    output.extend_from_slice(&name);
+
+    if one_line {
+        output.push(b' ');
+        output.extend_from_slice(trim_end(&lines[0].text));
+        output.extend_from_slice(b"]\n");
+        return false;
+    }
+
+    output.push(b'\n');
+    write_lines(output, &lines);
+    output.extend_from_slice(INDENT);
+    output.extend_from_slice(b"]\n");
+    true
+}
+
+fn format_class(output: &mut Vec<u8>, class: &Class) {
+    output.push(b'{');
+    output.extend(name_text(&class.name));
+
+    if class.items.is_empty() {
+        output.extend_from_slice(b"}\n");
+        return;
+    }
+
+    output.push(b'\n');
+
+    // Functions that take up more than one line are kept apart by blank lines
+    let mut last_multi_line = false;
+    let mut after_comment = false;
+    for (i, item) in class.items.iter().enumerate() {
+        match item {
+            ClassItem::Comment(comment) => {
+                if i > 0 && last_multi_line && !after_comment {
+                    output.push(b'\n');
+                }
+                output.extend_from_slice(INDENT);
+                output.extend(comment_text(&comment.text));
+                output.push(b'\n');
+                after_comment = true;
+            },
+            ClassItem::Method(method) => {
+                let mut method_output = Vec::new();
+                let multi_line = format_method(&mut method_output, method);
+
+                if i > 0 && (multi_line || last_multi_line) && !after_comment {
+                    output.push(b'\n');
+                }
+                output.extend(method_output);
+
+                last_multi_line = multi_line;
+                after_comment = false;
+            },
+        }
+    }
+
+    output.extend_from_slice(b"}\n");
+}
+
+// Formats a file of Glass code with a consistent layout, keeping its comments. The result
+// compiles to the same bytecode as the original.
+pub fn format_code(filename: &str, code: &[u8]) -> Result<Vec<u8>, Vec<ParseError>> {
+    let tree = parse_file_ast(filename, code)?;
+    let mut output = Vec::new();
+
+    let mut after_class = false;
+    for item in tree.items.iter() {
+        if after_class {
+            output.push(b'\n');
+        }
+
+        match item {
+            Item::Comment(comment) => {
+                output.extend(comment_text(&comment.text));
+                output.push(b'\n');
+                after_class = false;
+            },
+            Item::Class(class) => {
+                format_class(&mut output, class);
+                after_class = true;
+            },
+        }
+    }
+
+    Ok(output)
+}
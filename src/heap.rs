@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use byte_string::ByteString;
+
+use crate::bytecode::*;
+
+pub(crate) type ClassIndex = usize;
+
+pub(crate) type StringIndex = usize;
+
+pub(crate) type InstanceIndex = usize;
+
+pub(crate) type AutoGeneratedName = usize;
+
+// The smallest number of allocations between garbage collections
+const MIN_COLLECTION_THRESHOLD: usize = 1024;
+
+#[derive(Clone, Copy)]
+pub(crate) enum GlassValue {
+    AutoGeneratedName(AutoGeneratedName),
+    Class(ClassIndex),
+    Function(InstanceIndex, OpcodeIndex),
+    GlobalName(GlobalName),
+    Instance(InstanceIndex),
+    LocalName(LocalName),
+    MemberName(MemberName),
+    Number(f64),
+    String(StringIndex),
+}
+
+pub(crate) struct GlassInstance {
+    pub class: ClassIndex,
+
+    pub variables: HashMap<MemberName, GlassValue>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub collections: usize,
+
+    pub instances_allocated: usize,
+
+    pub strings_allocated: usize,
+
+    pub instances_freed: usize,
+
+    pub strings_freed: usize,
+
+    pub live_instances: usize,
+
+    pub live_strings: usize,
+
+    pub live_string_bytes: usize,
+
+    pub peak_instances: usize,
+
+    pub peak_strings: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Garbage collections: {}", self.collections)?;
+        writeln!(f, "Instances: {} allocated, {} freed, {} live, {} peak",
+            self.instances_allocated, self.instances_freed, self.live_instances, self.peak_instances)?;
+        write!(f, "Strings: {} allocated, {} freed, {} live ({} bytes), {} peak",
+            self.strings_allocated, self.strings_freed, self.live_strings, self.live_string_bytes, self.peak_strings)
+    }
+}
+
+// Storage for the instances and strings a program creates. Slots of unreachable values
+// are freed by the garbage collector and reused by later allocations.
+pub(crate) struct Heap {
+    instances: Vec<Option<GlassInstance>>,
+
+    strings: Vec<Option<ByteString>>,
+
+    free_instances: Vec<InstanceIndex>,
+
+    free_strings: Vec<StringIndex>,
+
+    // The slot of each of the program's constant strings, which are never freed
+    constants: Vec<StringIndex>,
+
+    allocations_since_collection: usize,
+
+    collection_threshold: usize,
+
+    stats: HeapStats,
+}
+
+impl Heap {
+    pub fn new(program: &BytecodeProgram) -> Heap {
+        let strings: Vec<_> = program.strings.iter().map(|string| Some(string.clone())).collect();
+
+        Heap {
+            constants: (0..strings.len()).collect(),
+            instances: Vec::new(),
+            strings,
+            free_instances: Vec::new(),
+            free_strings: Vec::new(),
+            allocations_since_collection: 0,
+            collection_threshold: MIN_COLLECTION_THRESHOLD,
+            stats: HeapStats::default(),
+        }
+    }
+
+    pub fn instance(&self, index: InstanceIndex) -> &GlassInstance {
+        self.instances[index].as_ref().expect("use of a freed instance")
+    }
+
+    pub fn instance_mut(&mut self, index: InstanceIndex) -> &mut GlassInstance {
+        self.instances[index].as_mut().expect("use of a freed instance")
+    }
+
+    pub fn string(&self, index: StringIndex) -> &ByteString {
+        self.strings[index].as_ref().expect("use of a freed string")
+    }
+
+    // The slot of one of the program's constant strings
+    pub fn constant(&self, index: usize) -> StringIndex {
+        self.constants[index]
+    }
+
+    // Adds slots for any constant strings the program has gained since it was last seen
+    pub fn add_constants(&mut self, program: &BytecodeProgram) {
+        for string in program.strings.iter().skip(self.constants.len()) {
+            self.strings.push(Some(string.clone()));
+            self.constants.push(self.strings.len() - 1);
+        }
+    }
+
+    pub fn alloc_instance(&mut self, class: ClassIndex) -> InstanceIndex {
+        let instance = GlassInstance { class, variables: HashMap::new() };
+
+        self.allocations_since_collection += 1;
+        self.stats.instances_allocated += 1;
+        self.stats.live_instances += 1;
+        self.stats.peak_instances = self.stats.peak_instances.max(self.stats.live_instances);
+
+        match self.free_instances.pop() {
+            Some(index) => {
+                self.instances[index] = Some(instance);
+                index
+            },
+            None => {
+                self.instances.push(Some(instance));
+                self.instances.len() - 1
+            },
+        }
+    }
+
+    pub fn alloc_string(&mut self, string: ByteString) -> StringIndex {
+        self.allocations_since_collection += 1;
+        self.stats.strings_allocated += 1;
+        self.stats.live_strings += 1;
+        self.stats.live_string_bytes += string.len();
+        self.stats.peak_strings = self.stats.peak_strings.max(self.stats.live_strings);
+
+        match self.free_strings.pop() {
+            Some(index) => {
+                self.strings[index] = Some(string);
+                index
+            },
+            None => {
+                self.strings.push(Some(string));
+                self.strings.len() - 1
+            },
+        }
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.allocations_since_collection >= self.collection_threshold
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    // Frees every instance and string that can't be reached from the given roots, giving the
+    // instances that were freed along with their classes
+    pub fn collect(&mut self, roots: impl Iterator<Item = GlassValue>) -> Vec<(InstanceIndex, ClassIndex)> {
+        let mut marked_instances = vec![false; self.instances.len()];
+        let mut marked_strings = vec![false; self.strings.len()];
+        for index in self.constants.iter() {
+            marked_strings[*index] = true;
+        }
+        let mut pending: Vec<GlassValue> = roots.collect();
+
+        while let Some(value) = pending.pop() {
+            match value {
+                GlassValue::String(index) => marked_strings[index] = true,
+                GlassValue::Instance(index) | GlassValue::Function(index, _) if !marked_instances[index] => {
+                    marked_instances[index] = true;
+                    pending.extend(self.instance(index).variables.values());
+                },
+                _ => {},
+            }
+        }
+
+        let mut freed_instances = Vec::new();
+        for (index, marked) in marked_instances.into_iter().enumerate() {
+            if marked {
+                continue;
+            }
+
+            if let Some(instance) = self.instances[index].take() {
+                freed_instances.push((index, instance.class));
+                self.free_instances.push(index);
+                self.stats.instances_freed += 1;
+                self.stats.live_instances -= 1;
+            }
+        }
+
+        for (index, marked) in marked_strings.into_iter().enumerate() {
+            if marked {
+                continue;
+            }
+
+            if let Some(string) = self.strings[index].take() {
+                self.free_strings.push(index);
+                self.stats.strings_freed += 1;
+                self.stats.live_strings -= 1;
+                self.stats.live_string_bytes -= string.len();
+            }
+        }
+
+        self.stats.collections += 1;
+        self.allocations_since_collection = 0;
+        self.collection_threshold = MIN_COLLECTION_THRESHOLD.max(self.stats.live_instances + self.stats.live_strings);
+
+        freed_instances
+    }
+}
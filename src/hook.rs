@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::bytecode::*;
+use crate::heap::*;
+use crate::interpreter::{describe_value, flush_output, Frame, RuntimeError};
+
+// Something that watches a program as it runs. The interpreter is generic over its hook,
+// so the calls compile away entirely when there isn't one.
+pub trait ExecutionHook {
+    // Called before each instruction is run. Returning an error stops the program.
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError>;
+}
+
+// Lets several hooks watch the same program, in order
+impl<A: ExecutionHook, B: ExecutionHook> ExecutionHook for (A, B) {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        self.0.before_instruction(state)?;
+        self.1.before_instruction(state)
+    }
+}
+
+// A hook that's only sometimes enabled
+impl<H: ExecutionHook> ExecutionHook for Option<H> {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        match self {
+            Some(hook) => hook.before_instruction(state),
+            None => Ok(()),
+        }
+    }
+}
+
+// The hook used when nothing is watching
+pub struct NoHook;
+
+impl ExecutionHook for NoHook {
+    #[inline(always)]
+    fn before_instruction(&mut self, _state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+// A view of a running program in between instructions
+pub struct ExecutionState<'s> {
+    pub(crate) program: &'s BytecodeProgram,
+
+    pub(crate) heap: &'s Heap,
+
+    pub(crate) globals: &'s HashMap<GlobalName, GlassValue>,
+
+    pub(crate) value_stack: &'s [GlassValue],
+
+    pub(crate) func_stack: &'s [Frame],
+
+    pub(crate) locals: &'s HashMap<LocalName, GlassValue>,
+
+    pub(crate) object: InstanceIndex,
+
+    pub(crate) opcode_index: OpcodeIndex,
+
+    pub(crate) input: &'s mut dyn BufRead,
+
+    pub(crate) output: &'s mut dyn Write,
+}
+
+impl<'s> ExecutionState<'s> {
+    pub fn program(&self) -> &BytecodeProgram {
+        self.program
+    }
+
+    // The index of the instruction that's about to run
+    pub fn opcode_index(&self) -> OpcodeIndex {
+        self.opcode_index
+    }
+
+    // How many function calls the current function is nested in
+    pub fn call_depth(&self) -> usize {
+        self.func_stack.len()
+    }
+
+    // Where each function call on the call stack is, from the outermost call to the
+    // instruction that's about to run, like the stack trace of an error
+    pub fn call_stack(&self) -> Vec<OpcodeIndex> {
+        self.func_stack.iter()
+            .map(|(_, opcode_index, _)| *opcode_index)
+            .chain(std::iter::once(self.opcode_index))
+            .collect()
+    }
+
+    // Describes the values on the stack, from the bottom up
+    pub fn stack(&self) -> Vec<String> {
+        self.value_stack.iter().map(|value| self.describe(value)).collect()
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.value_stack.len()
+    }
+
+    // Describes up to the given number of values from the top of the stack, from the
+    // bottom up
+    pub fn stack_top(&self, count: usize) -> Vec<String> {
+        let start = self.value_stack.len().saturating_sub(count);
+        self.value_stack[start..].iter().map(|value| self.describe(value)).collect()
+    }
+
+    // The current function's local variables and descriptions of their values
+    pub fn locals(&self) -> Vec<(String, String)> {
+        self.describe_names(self.locals.iter().map(|(name, value)| (self.program.local_name_str(*name), value)))
+    }
+
+    // The member variables of the instance the current function was called on
+    pub fn members(&self) -> Vec<(String, String)> {
+        let variables = &self.heap.instance(self.object).variables;
+        self.describe_names(variables.iter().map(|(name, value)| (self.program.member_name_str(*name), value)))
+    }
+
+    // The global variables, leaving out the ones that just hold the class of the same name
+    pub fn globals(&self) -> Vec<(String, String)> {
+        let globals = self.globals.iter().filter(|(name, value)| match value {
+            GlassValue::Class(class) => self.program.class_names[*class] != **name,
+            _ => true,
+        });
+
+        self.describe_names(globals.map(|(name, value)| (self.program.global_name_str(*name), value)))
+    }
+
+    // The program's input. Output is flushed first, in case something is prompting for it.
+    pub fn input(&mut self) -> Result<&mut dyn BufRead, RuntimeError> {
+        flush_output(self.output)?;
+        Ok(&mut *self.input)
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    fn describe(&self, value: &GlassValue) -> String {
+        describe_value(self.program, self.heap, value)
+    }
+
+    fn describe_names<'v>(&self, names: impl Iterator<Item = (String, &'v GlassValue)>) -> Vec<(String, String)> {
+        let mut described: Vec<_> = names.map(|(name, value)| (name, self.describe(value))).collect();
+        described.sort();
+        described
+    }
+}
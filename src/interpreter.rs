@@ -1,46 +1,27 @@
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::fmt;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
 use byte_string::ByteString;
 
 use crate::bytecode::*;
+use crate::heap::*;
+use crate::hook::{ExecutionHook, ExecutionState, NoHook};
+use crate::json::JsonValue;
+use crate::native::{NativeClasses, NativeContext};
+use crate::parser::{parse_classes_into, parse_snippet_into, ParseError};
 
-type ClassIndex = usize;
+pub use crate::heap::HeapStats;
 
-type StringIndex = usize;
-
-type InstanceIndex = usize;
-
-type AutoGeneratedName = usize;
-
-const OPCODE_ADD: u8 = OpCode::Add as u8;
 const OPCODE_CALL: u8 = OpCode::Call as u8;
-const OPCODE_CONCAT: u8 = OpCode::Concat as u8;
+const OPCODE_CALL_NATIVE: u8 = OpCode::CallNative as u8;
 const OPCODE_CONSTRUCT: u8 = OpCode::Construct as u8;
-const OPCODE_DIVIDE: u8 = OpCode::Divide as u8;
 const OPCODE_DUPLICATE: u8 = OpCode::Duplicate as u8;
-const OPCODE_EQUAL: u8 = OpCode::Equal as u8;
-const OPCODE_FLOOR: u8 = OpCode::Floor as u8;
-const OPCODE_GREATER: u8 = OpCode::GreaterThan as u8;
-const OPCODE_GREATER_EQUAL: u8 = OpCode::GreaterEqual as u8;
-const OPCODE_INDEX: u8 = OpCode::Index as u8;
-const OPCODE_INPUT_CHAR: u8 = OpCode::InputChar as u8;
-const OPCODE_INPUT_EOF: u8 = OpCode::InputEof as u8;
-const OPCODE_INPUT_LINE: u8 = OpCode::InputLine as u8;
 const OPCODE_INSTANTIATE: u8 = OpCode::Instantiate as u8;
 const OPCODE_JUMP_IF: u8 = OpCode::JumpIf as u8;
 const OPCODE_JUMP_IF_NOT: u8 = OpCode::JumpIfNot as u8;
-const OPCODE_LENGTH: u8 = OpCode::Length as u8;
-const OPCODE_LESS: u8 = OpCode::LessThan as u8;
-const OPCODE_LESS_EQUAL: u8 = OpCode::LessEqual as u8;
 const OPCODE_LOAD: u8 = OpCode::Load as u8;
 const OPCODE_LOAD_FROM: u8 = OpCode::LoadFrom as u8;
-const OPCODE_MODULO: u8 = OpCode::Modulo as u8;
-const OPCODE_MULTIPLY: u8 = OpCode::Multiply as u8;
-const OPCODE_NOT_EQUAL: u8 = OpCode::NotEqual as u8;
-const OPCODE_NUM_TO_STRING: u8 = OpCode::NumToString as u8;
-const OPCODE_OUTPUT_NUMBER: u8 = OpCode::OutputNumber as u8;
-const OPCODE_OUTPUT_STRING: u8 = OpCode::OutputString as u8;
 const OPCODE_POP: u8 = OpCode::Pop as u8;
 const OPCODE_PUSH_LOCAL: u8 = OpCode::PushLocal as u8;
 const OPCODE_PUSH_NUMBER: u8 = OpCode::PushNumber as u8;
@@ -51,155 +32,379 @@ const OPCODE_PUSH_STRING: u8 = OpCode::PushString as u8;
 const OPCODE_RETURN: u8 = OpCode::Return as u8;
 const OPCODE_STORE: u8 = OpCode::Store as u8;
 const OPCODE_STORE_KEEP: u8 = OpCode::StoreKeep as u8;
-const OPCODE_STRING_EQUAL: u8 = OpCode::StringEqual as u8;
-const OPCODE_STRING_REPLACE: u8 = OpCode::StringReplace as u8;
-const OPCODE_STRING_SPLIT: u8 = OpCode::StringSplit as u8;
-const OPCODE_STRING_TO_NUM: u8 = OpCode::StringToNum as u8;
-const OPCODE_SUBTRACT: u8 = OpCode::Subtract as u8;
-const OPCODE_VAR_DELETE: u8 = OpCode::VarDelete as u8;
-const OPCODE_VAR_NEW: u8 = OpCode::VarNew as u8;
-
-#[derive(Clone, Copy)]
-enum GlassValue {
-    AutoGeneratedName(AutoGeneratedName),
-    Class(ClassIndex),
-    Function(InstanceIndex, OpcodeIndex),
-    GlobalName(GlobalName),
-    Instance(InstanceIndex),
-    LocalName(LocalName),
-    MemberName(MemberName),
-    Number(f64),
-    String(StringIndex),
-}
-
-struct GlassInstance<'a> {
-    class: &'a ClassDefinition,
-
-    variables: HashMap<MemberName, GlassValue>,
-}
 
 #[derive(Debug)]
 pub enum RuntimeError {
+    CallDepthLimit(usize),
     EmptyStack,
-    InvalidIndex,
-    IOError,
-    UnsetName,
-    WrongType,
+    FileError(String),
+    FilesDisabled,
+    InstanceLimit(usize),
+    InstructionLimit(u64),
+    InvalidIndex { index: f64, length: usize },
+    IOError(String),
+    MissingArg { index: f64, count: usize },
+    MissingMember { class: String, name: String },
+    MissingNative(String),
+    ReleasedInstance,
+    StackLimit(usize),
+    Stopped,
+    StringLimit(usize),
+    UnknownClass(String),
+    UnsetName(String),
+    WrongType { expected: &'static str, found: String },
 }
 
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::CallDepthLimit(max) => write!(f, "exceeded the limit of {} nested function calls", max),
+            RuntimeError::EmptyStack => write!(f, "not enough values on the stack"),
+            RuntimeError::FileError(message) => write!(f, "file error: {}", message),
+            RuntimeError::FilesDisabled => write!(f, "opening files is disabled"),
+            RuntimeError::InstanceLimit(max) => write!(f, "exceeded the limit of {} live instances", max),
+            RuntimeError::InstructionLimit(max) => write!(f, "exceeded the limit of {} executed instructions", max),
+            RuntimeError::InvalidIndex { index, length } => {
+                write!(f, "index {} is out of range for a string of length {}", index, length)
+            },
+            RuntimeError::IOError(message) => write!(f, "I/O error: {}", message),
+            RuntimeError::MissingArg { index, count } => {
+                write!(f, "there's no argument at index {}, the program was given {} arguments", index, count)
+            },
+            RuntimeError::MissingMember { class, name } => {
+                write!(f, "instance of class {} has no member or method {}", class, name)
+            },
+            RuntimeError::MissingNative(name) => write!(f, "native method {} isn't registered", name),
+            RuntimeError::ReleasedInstance => write!(f, "instance handle has already been released"),
+            RuntimeError::StackLimit(max) => write!(f, "exceeded the limit of {} values on the stack", max),
+            RuntimeError::Stopped => write!(f, "execution was stopped"),
+            RuntimeError::StringLimit(max) => write!(f, "exceeded the limit of {} bytes of strings", max),
+            RuntimeError::UnknownClass(name) => write!(f, "class {} doesn't exist", name),
+            RuntimeError::UnsetName(name) => write!(f, "name {} has not been set", name),
+            RuntimeError::WrongType { expected, found } => write!(f, "expected {}, got {}", expected, found),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ExecutionError {
     pub error: RuntimeError,
 
     pub stack_trace: Vec<OpcodeIndex>,
+
+    pub builtin: Option<String>,
 }
 
-fn instantiate<'a>(instances: &mut Vec<GlassInstance<'a>>, class: &'a ClassDefinition) -> InstanceIndex {
-    instances.push(GlassInstance {
-        class: &class,
-        variables: HashMap::new(),
-    });
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.builtin {
+            Some(builtin) => write!(f, "in builtin {}: {}", builtin, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
 
-    instances.len() - 1
+// Where a function in a stack trace was when the error happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TracebackEntry {
+    Builtin(String),
+    Source(SourceLocation),
 }
 
-fn read_short(instructions: &Vec<u8>, index: &mut usize) -> u16 {
+impl fmt::Display for TracebackEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TracebackEntry::Builtin(builtin) => write!(f, "In builtin {}", builtin),
+            TracebackEntry::Source(location) => write!(f, "In file {} on line {}, column {}", location.file, location.pos.line, location.pos.col),
+        }
+    }
+}
+
+impl TracebackEntry {
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            TracebackEntry::Builtin(builtin) => JsonValue::object(vec![("builtin", JsonValue::string(builtin))]),
+            TracebackEntry::Source(location) => location.to_json(),
+        }
+    }
+}
+
+impl ExecutionError {
+    // Resolves where each function in the stack trace was, most recent call first
+    pub fn traceback(&self, program: &BytecodeProgram) -> Vec<TracebackEntry> {
+        self.stack_trace.iter().rev().map(|index| {
+            match program.builtin_name(*index) {
+                Some(builtin) => TracebackEntry::Builtin(builtin),
+                None => TracebackEntry::Source(program.source_location(*index)),
+            }
+        }).collect()
+    }
+
+    // Describes the error and its traceback as a single JSON object
+    pub fn to_json(&self, program: &BytecodeProgram) -> JsonValue {
+        JsonValue::object(vec![
+            ("type", JsonValue::string("runtime_error")),
+            ("message", JsonValue::string(self.to_string())),
+            ("builtin", self.builtin.clone().map(JsonValue::String).into()),
+            ("traceback", JsonValue::Array(self.traceback(program).iter().map(|entry| entry.to_json()).collect())),
+        ])
+    }
+}
+
+// An error from running code that's added to a program while it's running
+#[derive(Debug)]
+pub enum EvalError {
+    Parse(Vec<ParseError>),
+    Execution(ExecutionError),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Parse(errors) => {
+                let messages: Vec<_> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            },
+            EvalError::Execution(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub(crate) fn describe_value(program: &BytecodeProgram, heap: &Heap, value: &GlassValue) -> String {
+    match value {
+        GlassValue::AutoGeneratedName(_) => "auto-generated name".to_owned(),
+        GlassValue::Class(class) => format!("class {}", program.global_name_str(program.class_names[*class])),
+        GlassValue::Function(_, func_index) => match program.function_name(*func_index) {
+            Some(func_name) => format!("function {}", func_name),
+            None => "function".to_owned(),
+        },
+        GlassValue::GlobalName(name) => format!("name {}", program.global_name_str(*name)),
+        GlassValue::Instance(inst) => {
+            let class_name = program.class_names[heap.instance(*inst).class];
+            format!("instance of class {}", program.global_name_str(class_name))
+        },
+        GlassValue::LocalName(name) => format!("name {}", program.local_name_str(*name)),
+        GlassValue::MemberName(name) => format!("name {}", program.member_name_str(*name)),
+        GlassValue::Number(num) => format!("number {}", num),
+        GlassValue::String(index) => {
+            let string = heap.string(*index);
+            if string.len() <= 20 {
+                format!("string {:?}", String::from_utf8_lossy(string))
+            }
+            else {
+                format!("string of length {}", string.len())
+            }
+        },
+    }
+}
+
+pub(crate) fn wrong_type(program: &BytecodeProgram, heap: &Heap, expected: &'static str, value: &GlassValue) -> RuntimeError {
+    RuntimeError::WrongType { expected, found: describe_value(program, heap, value) }
+}
+
+fn read_short(instructions: &[u8], index: &mut usize) -> u16 {
     let hi_byte = instructions[*index + 1] as u16;
     let lo_byte = instructions[*index + 2] as u16;
     *index += 2;
     (hi_byte << 8) | lo_byte
 }
 
-fn pop_number(value_stack: &mut Vec<GlassValue>) -> Result<f64, RuntimeError> {
+pub(crate) fn pop_number(value_stack: &mut Vec<GlassValue>, program: &BytecodeProgram, heap: &Heap) -> Result<f64, RuntimeError> {
     match value_stack.pop() {
         Some(GlassValue::Number(num)) => Ok(num),
-        Some(_) => return Err(RuntimeError::WrongType),
-        None => return Err(RuntimeError::EmptyStack),
+        Some(val) => Err(wrong_type(program, heap, "number", &val)),
+        None => Err(RuntimeError::EmptyStack),
     }
 }
 
-fn pop_string(value_stack: &mut Vec<GlassValue>) -> Result<StringIndex, RuntimeError> {
+pub(crate) fn pop_string(value_stack: &mut Vec<GlassValue>, program: &BytecodeProgram, heap: &Heap) -> Result<StringIndex, RuntimeError> {
     match value_stack.pop() {
         Some(GlassValue::String(index)) => Ok(index),
-        Some(_) => return Err(RuntimeError::WrongType),
-        None => return Err(RuntimeError::EmptyStack),
+        Some(val) => Err(wrong_type(program, heap, "string", &val)),
+        None => Err(RuntimeError::EmptyStack),
     }
 }
 
-fn get_index(string: &ByteString, num: f64) -> Result<usize, RuntimeError> {
-    if num != num.floor() || num < 0.0 {
-        Err(RuntimeError::InvalidIndex)
+fn unset_name(program: &BytecodeProgram, name: &GlassValue) -> RuntimeError {
+    match name {
+        GlassValue::GlobalName(name) => RuntimeError::UnsetName(program.global_name_str(*name)),
+        GlassValue::LocalName(name) => RuntimeError::UnsetName(program.local_name_str(*name)),
+        GlassValue::MemberName(name) => RuntimeError::UnsetName(program.member_name_str(*name)),
+        _ => RuntimeError::UnsetName("auto-generated name".to_owned()),
     }
-    else {
-        let index = num as usize;
-        if index >= string.len() {
-            Err(RuntimeError::InvalidIndex)
-        }
-        else {
-            Ok(index)
-        }
+}
+
+// Looks up the value a name refers to. If the name is unset, this fails with an error
+// describing it, or if the value isn't a name at all, this fails with None.
+fn load_name(
+    program: &BytecodeProgram,
+    name: &GlassValue,
+    auto_generated_vars: &HashMap<AutoGeneratedName, GlassValue>,
+    globals: &HashMap<GlobalName, GlassValue>,
+    locals: &HashMap<LocalName, GlassValue>,
+    members: &HashMap<MemberName, GlassValue>,
+) -> Result<GlassValue, Option<RuntimeError>> {
+    let value = match name {
+        GlassValue::AutoGeneratedName(auto_index) => auto_generated_vars.get(auto_index),
+        GlassValue::GlobalName(global_index) => globals.get(global_index),
+        GlassValue::LocalName(local_index) => locals.get(local_index),
+        GlassValue::MemberName(member_index) => members.get(member_index),
+        _ => return Err(None),
+    };
+
+    match value {
+        Some(val) => Ok(*val),
+        None => Err(Some(unset_name(program, name))),
     }
 }
 
-fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack: &mut Vec<(InstanceIndex, OpcodeIndex, HashMap<LocalName, GlassValue>)>) -> Result<(), RuntimeError> {
-    let mut instances = Vec::new();
-    let mut strings = Vec::new();
-    let mut value_stack = Vec::new();
-    let mut globals = HashMap::new();
-    let mut cur_object = 0 as InstanceIndex;
-    let mut locals = HashMap::new();
-    let mut input_eof = false;
-    let mut auto_generated_vars = HashMap::new();
+// Output is buffered, so it has to be flushed before waiting on input in case the
+// program is prompting for it
+pub(crate) fn flush_output(output: &mut dyn Write) -> Result<(), RuntimeError> {
+    output.flush().map_err(|err| RuntimeError::IOError(err.to_string()))
+}
+
+// Limits on the resources a program can use, for running untrusted programs. Every limit
+// is off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    // Counted over the whole lifetime of the interpreter, not per call
+    pub max_instructions: Option<u64>,
 
-    // Populate globals with class definitions
-    for i in 0..program.classes.len() {
-        let class_name = program.class_names[i];
-        globals.insert(program.class_names[i], GlassValue::Class(i as ClassIndex));
+    pub max_stack_depth: Option<usize>,
 
-        if class_name == program.main_class {
-            instantiate(&mut instances, &program.classes[i]);
+    pub max_call_depth: Option<usize>,
+
+    pub max_instances: Option<usize>,
+
+    // Only counts strings created while running, not the program's string constants
+    pub max_string_bytes: Option<usize>,
+
+    // Stops the File class from opening any files
+    pub no_files: bool,
+}
+
+impl Limits {
+    fn check_heap(&self, stats: &HeapStats) -> Result<(), RuntimeError> {
+        match (self.max_instances, self.max_string_bytes) {
+            (Some(max), _) if stats.live_instances > max => Err(RuntimeError::InstanceLimit(max)),
+            (_, Some(max)) if stats.live_string_bytes > max => Err(RuntimeError::StringLimit(max)),
+            _ => Ok(()),
         }
     }
 
-    // Fill out the global string array
-    for string in program.strings.iter() {
-        strings.push(string.clone());
+    // Called before entering a function, when the caller is already on the function stack
+    fn check_call_depth(&self, func_stack: &[Frame]) -> Result<(), RuntimeError> {
+        match self.max_call_depth {
+            Some(max) if func_stack.len() + 1 >= max => Err(RuntimeError::CallDepthLimit(max)),
+            _ => Ok(()),
+        }
     }
+}
+
+// A Glass function running on the interpreter: the object it's called on, where it was
+// called from, and its local variables
+pub(crate) type Frame = (InstanceIndex, OpcodeIndex, HashMap<LocalName, GlassValue>);
 
-    *opcode_index = instances[cur_object as usize].class.funcs[&program.main_func] as usize;
+// Runs a function until it returns, leaving its results on the value stack
+fn run_function<H: ExecutionHook>(interpreter: &mut Interpreter, object: InstanceIndex, opcode_index: &mut usize, func_stack: &mut Vec<Frame>, hook: &mut H) -> Result<(), RuntimeError> {
+    let Interpreter { program, heap, globals, auto_generated_vars, value_stack, host_roots, natives, native_bindings, limits, instructions_executed, args, env_vars, input, output, error_output } = interpreter;
+    let program = &*program;
+    let mut cur_object = object;
+    let mut locals = HashMap::new();
+    let heap_limited = limits.max_instances.is_some() || limits.max_string_bytes.is_some();
 
     loop {
+        if let Some(max) = limits.max_instructions {
+            if *instructions_executed >= max {
+                return Err(RuntimeError::InstructionLimit(max));
+            }
+        }
+        *instructions_executed += 1;
+
+        if let Some(max) = limits.max_stack_depth {
+            if value_stack.len() > max {
+                return Err(RuntimeError::StackLimit(max));
+            }
+        }
+
+        // Every live value is reachable from these roots in between instructions. Going over
+        // a heap limit forces a collection, as the limit might only be exceeded by garbage.
+        let over_heap_limits = heap_limited && limits.check_heap(&heap.stats()).is_err();
+        if heap.should_collect() || over_heap_limits {
+            let func_stack_values = func_stack.iter().flat_map(|(call_inst, _, local_vars)| {
+                std::iter::once(GlassValue::Instance(*call_inst)).chain(local_vars.values().copied())
+            });
+
+            let freed = heap.collect(value_stack.iter().copied()
+                .chain(func_stack_values)
+                .chain(globals.values().copied())
+                .chain(locals.values().copied())
+                .chain(auto_generated_vars.values().copied())
+                .chain(host_roots.keys().map(|inst| GlassValue::Instance(*inst)))
+                .chain(std::iter::once(GlassValue::Instance(cur_object))));
+
+            natives.finalize(program, &freed)?;
+            limits.check_heap(&heap.stats())?;
+        }
+
+        hook.before_instruction(&mut ExecutionState {
+            program,
+            heap,
+            globals,
+            value_stack,
+            func_stack,
+            locals: &locals,
+            object: cur_object,
+            opcode_index: *opcode_index,
+            input: &mut *input,
+            output: &mut *output,
+        })?;
+
         match program.instructions[*opcode_index] {
-            OPCODE_ADD => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num1 + num2));
-            },
             OPCODE_CALL => {
                 match value_stack.pop() {
                     Some(GlassValue::Function(call_inst, call_op)) => {
+                        limits.check_call_depth(func_stack)?;
                         func_stack.push((cur_object, *opcode_index, locals));
                         locals = HashMap::new();
                         cur_object = call_inst;
-                        *opcode_index = call_op as usize;
+                        *opcode_index = call_op;
                         continue;
                     },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "function", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 }
             },
-            OPCODE_CONCAT => {
-                let mut str1 = strings[pop_string(&mut value_stack)?].clone();
-                let mut str2 = strings[pop_string(&mut value_stack)?].clone();
-                str2.append(&mut str1);
-                strings.push(str2);
-                value_stack.push(GlassValue::String(strings.len() - 1));
+            OPCODE_CALL_NATIVE => {
+                let native_index = read_short(&program.instructions, opcode_index) as usize;
+                let binding = native_bindings[native_index].ok_or_else(|| {
+                    let (class_name, method_name) = &program.native_methods[native_index];
+                    RuntimeError::MissingNative(format!("{}.{}", String::from_utf8_lossy(class_name), String::from_utf8_lossy(method_name)))
+                })?;
+
+                let mut context = NativeContext {
+                    program,
+                    heap: &mut *heap,
+                    value_stack: &mut *value_stack,
+                    auto_generated_vars: &*auto_generated_vars,
+                    input: &mut *input,
+                    output: &mut *output,
+                    error_output: &mut **error_output,
+                    args,
+                    env_vars,
+                    instance: cur_object,
+                    limits,
+                };
+                natives.call(binding, &mut context)?;
             },
             OPCODE_CONSTRUCT => {
                 match value_stack.pop() {
                     Some(GlassValue::Instance(inst_index)) => {
-                        match instances[inst_index].class.constructor {
+                        match program.classes[heap.instance(inst_index).class].constructor {
                             None => (),
                             Some(ctor_index) => {
+                                limits.check_call_depth(func_stack)?;
                                 func_stack.push((cur_object, *opcode_index, locals));
                                 locals = HashMap::new();
                                 cur_object = inst_index;
@@ -208,15 +413,10 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
                             },
                         }
                     },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "instance", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 }
             },
-            OPCODE_DIVIDE => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num2 / num1));
-            },
             OPCODE_DUPLICATE => {
                 let dup_index = program.instructions[*opcode_index + 1];
                 if dup_index as usize >= value_stack.len() {
@@ -225,80 +425,20 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
                 value_stack.push(value_stack[value_stack.len() - (dup_index as usize) - 1]);
                 *opcode_index += 1;
             },
-            OPCODE_EQUAL => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 == num2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_FLOOR => {
-                let num = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num.floor()));
-            },
-            OPCODE_GREATER => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 < num2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_GREATER_EQUAL => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 <= num2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_INDEX => {
-                let num = pop_number(&mut value_stack)?;
-                let string = &strings[pop_string(&mut value_stack)?];
-                let index = get_index(&string, num)?;
-                strings.push(ByteString(vec![string[index]]));
-                value_stack.push(GlassValue::String(strings.len() - 1));
-            },
-            OPCODE_INPUT_CHAR => {
-                let mut input_bytes = [ 0 ];
-                match std::io::stdin().read(&mut input_bytes) {
-                    Ok(amount_read) => {
-                        input_eof = amount_read == 0;
-                        strings.push(ByteString::new(Vec::from(input_bytes)));
-                        value_stack.push(GlassValue::String(strings.len() - 1));
-                    },
-                    Err(_) => return Err(RuntimeError::IOError),
-                }
-            },
-            OPCODE_INPUT_EOF => {
-                value_stack.push(GlassValue::Number(if input_eof { 1.0 } else { 0.0 }));
-            },
-            OPCODE_INPUT_LINE => {
-                let mut line = Vec::new();
-                let mut input_bytes = [ 0 ];
-
-                loop {
-                    match std::io::stdin().read(&mut input_bytes) {
-                        Ok(0) => break,
-                        Ok(_) => {
-                            line.push(input_bytes[0]);
-                            if input_bytes[0] == b'\n' {
-                                break;
-                            }
-                        },
-                        Err(_) => return Err(RuntimeError::IOError),
-                    }
-                }
-
-                strings.push(ByteString::new(line));
-                value_stack.push(GlassValue::String(strings.len() - 1));
-            },
             OPCODE_INSTANTIATE => {
                 match value_stack.pop() {
                     Some(GlassValue::Class(class_index)) => {
-                        let inst_index = instantiate(&mut instances, &program.classes[class_index as usize]);
+                        let inst_index = heap.alloc_instance(class_index);
                         value_stack.push(GlassValue::Instance(inst_index));
                     },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "class", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 }
             },
             OPCODE_JUMP_IF => {
                 let should_jump = match value_stack.pop() {
                     Some(GlassValue::Number(num)) => num != 0.0,
-                    Some(GlassValue::String(index)) => !strings[index].is_empty(),
+                    Some(GlassValue::String(index)) => !heap.string(index).is_empty(),
                     Some(_) => false,
                     None => return Err(RuntimeError::EmptyStack),
                 };
@@ -311,7 +451,7 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
             OPCODE_JUMP_IF_NOT => {
                 let should_jump = match value_stack.pop() {
                     Some(GlassValue::Number(num)) => num == 0.0,
-                    Some(GlassValue::String(index)) => strings[index].is_empty(),
+                    Some(GlassValue::String(index)) => heap.string(index).is_empty(),
                     Some(_) => true,
                     None => return Err(RuntimeError::EmptyStack),
                 };
@@ -321,152 +461,53 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
                     *opcode_index += jump_amount as usize;
                 }
             },
-            OPCODE_LENGTH => {
-                let string = &strings[pop_string(&mut value_stack)?];
-                value_stack.push(GlassValue::Number(string.len() as f64));
-            },
-            OPCODE_LESS => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 > num2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_LESS_EQUAL => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 >= num2 { 1.0 } else { 0.0 }));
-            },
             OPCODE_LOAD => {
-                match value_stack.pop() {
-                    Some(GlassValue::AutoGeneratedName(auto_index)) => {
-                        match auto_generated_vars.get(&auto_index) {
-                            Some(val) => value_stack.push(*val),
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::GlobalName(global_index)) => {
-                        match globals.get(&global_index) {
-                            Some(val) => value_stack.push(*val),
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::LocalName(local_index)) => {
-                        match locals.get(&local_index) {
-                            Some(val) => value_stack.push(*val),
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::MemberName(member_index)) => {
-                        let instance = &instances[cur_object];
-                        match instance.variables.get(&member_index) {
-                            Some(val) => value_stack.push(*val),
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                let name = match value_stack.pop() {
+                    Some(name) => name,
                     None => return Err(RuntimeError::EmptyStack),
-                }
+                };
+
+                let value = load_name(program, &name, auto_generated_vars, globals, &locals, &heap.instance(cur_object).variables)
+                    .map_err(|err| err.unwrap_or_else(|| wrong_type(program, heap, "name", &name)))?;
+                value_stack.push(value);
             },
             OPCODE_LOAD_FROM => {
                 let name = match value_stack.pop() {
                     Some(GlassValue::MemberName(name)) => name,
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "member name", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 };
 
-                // TODO: DRY this up. The logic here is the same as the OPCODE_LOAD case
-                let loaded_value = match value_stack.pop() {
-                    Some(GlassValue::AutoGeneratedName(auto_index)) => {
-                        match auto_generated_vars.get(&auto_index) {
-                            Some(val) => val,
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::GlobalName(global_index)) => {
-                        match globals.get(&global_index) {
-                            Some(val) => val,
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::LocalName(local_index)) => {
-                        match locals.get(&local_index) {
-                            Some(val) => val,
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(GlassValue::MemberName(member_index)) => {
-                        let instance = &instances[cur_object];
-                        match instance.variables.get(&member_index) {
-                            Some(val) => val,
-                            None => return Err(RuntimeError::UnsetName),
-                        }
-                    },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                let inst_name = match value_stack.pop() {
+                    Some(inst_name) => inst_name,
                     None => return Err(RuntimeError::EmptyStack),
                 };
 
+                let loaded_value = load_name(program, &inst_name, auto_generated_vars, globals, &locals, &heap.instance(cur_object).variables)
+                    .map_err(|err| err.unwrap_or_else(|| wrong_type(program, heap, "name", &inst_name)))?;
+
                 let inst_index = match loaded_value {
                     GlassValue::Instance(inst_index) => inst_index,
-                    _ => return Err(RuntimeError::WrongType),
+                    val => return Err(wrong_type(program, heap, "instance", &val)),
                 };
 
-                let instance = &instances[*inst_index as usize];
+                let instance = heap.instance(inst_index);
 
                 match instance.variables.get(&name) {
                     Some(val) => value_stack.push(*val),
                     None => {
-                        match instance.class.funcs.get(&name) {
-                            Some(op_index) => value_stack.push(GlassValue::Function(*inst_index, *op_index)),
-                            None => return Err(RuntimeError::UnsetName),
+                        match program.classes[instance.class].funcs.get(&name) {
+                            Some(op_index) => value_stack.push(GlassValue::Function(inst_index, *op_index)),
+                            None => return Err(RuntimeError::MissingMember {
+                                class: program.global_name_str(program.class_names[instance.class]),
+                                name: program.member_name_str(name),
+                            }),
                         }
                     },
                 }
             },
-            OPCODE_MODULO => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num2 % num1));
-            },
-            OPCODE_MULTIPLY => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num1 * num2));
-            },
-            OPCODE_NOT_EQUAL => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(if num1 != num2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_NUM_TO_STRING => {
-                let num = pop_number(&mut value_stack)?;
-                if num.floor() != num || num < 0.0 || num > 255.0 {
-                    return Err(RuntimeError::WrongType);
-                }
-                let string = ByteString(vec![num as u8]);
-                strings.push(string);
-                value_stack.push(GlassValue::String(strings.len() - 1));
-            },
-            OPCODE_OUTPUT_NUMBER => {
-                match value_stack.pop() {
-                    Some(GlassValue::Number(num)) => {
-                        print!("{}", num);
-                    },
-                    Some(_) => return Err(RuntimeError::WrongType),
-                    None => return Err(RuntimeError::EmptyStack),
-                }
-            },
-            OPCODE_OUTPUT_STRING => {
-                match value_stack.pop() {
-                    Some(GlassValue::String(str_index)) => {
-                        if let Err(_) = std::io::stdout().write_all(&strings[str_index]) {
-                            return Err(RuntimeError::IOError);
-                        }
-                    },
-                    Some(_) => return Err(RuntimeError::WrongType),
-                    None => return Err(RuntimeError::EmptyStack),
-                }
-            },
             OPCODE_POP => {
-                if let None = value_stack.pop() {
+                if value_stack.pop().is_none() {
                     return Err(RuntimeError::EmptyStack);
                 }
             },
@@ -491,7 +532,7 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
             },
             OPCODE_PUSH_STRING => {
                 let str_index = read_short(&program.instructions, opcode_index);
-                value_stack.push(GlassValue::String(str_index as StringIndex));
+                value_stack.push(GlassValue::String(heap.constant(str_index as usize)));
             },
             OPCODE_RETURN => {
                 match func_stack.pop() {
@@ -522,9 +563,9 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
                         locals.insert(name, value);
                     },
                     Some(GlassValue::MemberName(name)) => {
-                        instances[cur_object as usize].variables.insert(name, value);
+                        heap.instance_mut(cur_object).variables.insert(name, value);
                     },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "name", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 }
             },
@@ -545,92 +586,366 @@ fn run_program(program: &BytecodeProgram, opcode_index: &mut usize, func_stack:
                         locals.insert(name, value);
                     },
                     Some(GlassValue::MemberName(name)) => {
-                        instances[cur_object as usize].variables.insert(name, value);
+                        heap.instance_mut(cur_object).variables.insert(name, value);
                     },
-                    Some(_) => return Err(RuntimeError::WrongType),
+                    Some(val) => return Err(wrong_type(program, heap, "name", &val)),
                     None => return Err(RuntimeError::EmptyStack),
                 }
 
                 value_stack.push(value);
             },
-            OPCODE_STRING_EQUAL => {
-                let str1 = &strings[pop_string(&mut value_stack)?];
-                let str2 = &strings[pop_string(&mut value_stack)?];
-                value_stack.push(GlassValue::Number(if str1 == str2 { 1.0 } else { 0.0 }));
-            },
-            OPCODE_STRING_REPLACE => {
-                let char_str = &strings[pop_string(&mut value_stack)?];
-                let index_float = pop_number(&mut value_stack)?;
-                let mut string = strings[pop_string(&mut value_stack)?].clone();
-
-                let index = get_index(&string, index_float)?;
-                if char_str.len() != 1 {
-                    return Err(RuntimeError::WrongType);
-                }
+            _ => unreachable!(),
+        }
 
-                string[index] = char_str[0];
-                strings.push(string);
-                value_stack.push(GlassValue::String(strings.len() - 1));
-            },
-            OPCODE_STRING_SPLIT => {
-                let index_float = pop_number(&mut value_stack)?;
-                let string = &strings[pop_string(&mut value_stack)?];
+        *opcode_index += 1;
+    }
+}
 
-                if index_float.floor() != index_float || index_float < 0.0 || index_float > string.len() as f64 {
-                    return Err(RuntimeError::InvalidIndex);
-                }
+// A value passed between Rust code and a Glass program
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    String(ByteString),
+    Instance(InstanceHandle),
+}
 
-                let index = index_float as usize;
-                let str1 = ByteString::new(Vec::from(&string[..index]));
-                let str2 = ByteString::new(Vec::from(&string[index..]));
+// Refers to an instance living in an interpreter's heap. Instances handed out to Rust code
+// are kept alive until they're released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceHandle(InstanceIndex);
 
-                strings.push(str1);
-                value_stack.push(GlassValue::String(strings.len() - 1));
-                strings.push(str2);
-                value_stack.push(GlassValue::String(strings.len() - 1));
-            },
-            OPCODE_STRING_TO_NUM => {
-                let string = &strings[pop_string(&mut value_stack)?];
-                if string.len() != 1 {
-                    return Err(RuntimeError::WrongType);
-                }
-                value_stack.push(GlassValue::Number(string[0] as f64));
-            },
-            OPCODE_SUBTRACT => {
-                let num1 = pop_number(&mut value_stack)?;
-                let num2 = pop_number(&mut value_stack)?;
-                value_stack.push(GlassValue::Number(num2 - num1));
-            },
-            OPCODE_VAR_DELETE => {
-                match value_stack.pop() {
-                    Some(GlassValue::AutoGeneratedName(_)) => {},
-                    Some(_) => return Err(RuntimeError::WrongType),
-                    None => return Err(RuntimeError::EmptyStack),
-                }
-            },
-            OPCODE_VAR_NEW => {
-                value_stack.push(GlassValue::AutoGeneratedName(auto_generated_vars.len()));
-            },
-            _ => unreachable!(),
+// Whether a name is written like a global in Glass code, i.e. starts with a capital letter
+fn is_global_name(name: &[u8]) -> bool {
+    match name.first() {
+        Some(c) if c.is_ascii_uppercase() => name.iter().all(|c| *c == b'_' || c.is_ascii_alphanumeric()),
+        _ => false,
+    }
+}
+
+fn bind_natives(program: &BytecodeProgram, natives: &NativeClasses) -> Vec<Option<(usize, usize)>> {
+    program.native_methods.iter()
+        .map(|(class_name, method_name)| natives.find(class_name, method_name))
+        .collect()
+}
+
+// Errors that happen outside of any Glass function, e.g. when flushing output
+fn host_error(error: RuntimeError) -> ExecutionError {
+    ExecutionError { error, stack_trace: Vec::new(), builtin: None }
+}
+
+// Runs a Glass program, keeping its heap and globals alive between calls so that Rust
+// code can create instances and call their methods. Input and output use the standard
+// streams unless others are given.
+pub struct Interpreter<'a> {
+    program: BytecodeProgram,
+
+    heap: Heap,
+
+    globals: HashMap<GlobalName, GlassValue>,
+
+    auto_generated_vars: HashMap<AutoGeneratedName, GlassValue>,
+
+    value_stack: Vec<GlassValue>,
+
+    // Instances that Rust code holds handles to, with how many times each was handed out
+    host_roots: HashMap<InstanceIndex, usize>,
+
+    natives: NativeClasses,
+
+    // Which registered native method each of the program's native methods refers to
+    native_bindings: Vec<Option<(usize, usize)>>,
+
+    limits: Limits,
+
+    instructions_executed: u64,
+
+    // The arguments given to the program, which it can read with the Args class
+    args: Vec<ByteString>,
+
+    // The environment variables the program can see, which is none unless some are given
+    env_vars: HashMap<ByteString, ByteString>,
+
+    input: BufReader<Box<dyn Read + 'a>>,
+
+    output: BufWriter<Box<dyn Write + 'a>>,
+
+    error_output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(program: BytecodeProgram) -> Interpreter<'a> {
+        // Populate globals with class definitions
+        let mut globals = HashMap::new();
+        for (i, class_name) in program.class_names.iter().enumerate() {
+            globals.insert(*class_name, GlassValue::Class(i as ClassIndex));
         }
 
-        *opcode_index += 1;
+        let natives = NativeClasses::standard();
+
+        Interpreter {
+            heap: Heap::new(&program),
+            native_bindings: bind_natives(&program, &natives),
+            natives,
+            limits: Limits::default(),
+            instructions_executed: 0,
+            args: Vec::new(),
+            env_vars: HashMap::new(),
+            program,
+            globals,
+            auto_generated_vars: HashMap::new(),
+            value_stack: Vec::new(),
+            host_roots: HashMap::new(),
+            input: BufReader::new(Box::new(std::io::stdin())),
+            output: BufWriter::new(Box::new(std::io::stdout())),
+            error_output: Box::new(std::io::stderr()),
+        }
     }
-}
 
-pub fn execute_program(program: &BytecodeProgram) -> Result<(), ExecutionError> {
-    let mut func_stack = Vec::new();
-    let mut opcode_index = 0;
+    // Replaces the standard native classes, which should be the same ones the program was
+    // parsed with
+    pub fn native_classes(mut self, natives: NativeClasses) -> Interpreter<'a> {
+        self.native_bindings = bind_natives(&self.program, &natives);
+        self.natives = natives;
+        self
+    }
 
-    match run_program(program, &mut opcode_index, &mut func_stack) {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            let mut stack_trace = Vec::new();
-            for (_, opcode_index, _) in func_stack {
-                stack_trace.push(opcode_index);
+    pub fn limits(mut self, limits: Limits) -> Interpreter<'a> {
+        self.limits = limits;
+        self
+    }
+
+    pub fn args(mut self, args: Vec<ByteString>) -> Interpreter<'a> {
+        self.args = args;
+        self
+    }
+
+    // Lets the program read environment variables with the Env class, and sets the ones named
+    // like globals, e.g. HOME as (HOME), as global strings. Names of classes are never replaced.
+    pub fn env_vars(mut self, vars: impl IntoIterator<Item = (ByteString, ByteString)>) -> Interpreter<'a> {
+        self.env_vars = vars.into_iter().collect();
+
+        for (name, value) in self.env_vars.iter() {
+            // Names the program never uses have no global to set
+            let global = match self.program.global_names.iter().position(|global| global == name) {
+                Some(global) if is_global_name(name) => global as GlobalName,
+                _ => continue,
+            };
+
+            if let Some(GlassValue::Class(_)) = self.globals.get(&global) {
+                continue;
             }
-            stack_trace.push(opcode_index);
-            Err(ExecutionError { error: err, stack_trace: stack_trace })
-        },
+
+            let index = self.heap.alloc_string(value.clone());
+            self.globals.insert(global, GlassValue::String(index));
+        }
+
+        self
+    }
+
+    pub fn input(mut self, input: impl Read + 'a) -> Interpreter<'a> {
+        self.input = BufReader::new(Box::new(input));
+        self
     }
+
+    pub fn output(mut self, output: impl Write + 'a) -> Interpreter<'a> {
+        self.output = BufWriter::new(Box::new(output));
+        self
+    }
+
+    pub fn error_output(mut self, error_output: impl Write + 'a) -> Interpreter<'a> {
+        self.error_output = Box::new(error_output);
+        self
+    }
+
+    pub fn program(&self) -> &BytecodeProgram {
+        &self.program
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    // Runs the program from M.m on a new instance of the main class
+    pub fn run(&mut self) -> Result<(), ExecutionError> {
+        self.run_with_hook(&mut NoHook)
+    }
+
+    // Runs the program with a hook watching every instruction
+    pub fn run_with_hook(&mut self, hook: &mut impl ExecutionHook) -> Result<(), ExecutionError> {
+        let main_class = self.program.class_names.iter().position(|name| *name == self.program.main_class)
+            .expect("verified program has a main class");
+        let main_object = self.heap.alloc_instance(main_class);
+        let main_func = self.program.classes[main_class].funcs[&self.program.main_func];
+
+        let result = self.call_function(main_object, main_func, hook);
+        self.value_stack.clear();
+        self.finish(result)
+    }
+
+    // Creates an instance of a class, running its constructor
+    pub fn instantiate(&mut self, class_name: &str) -> Result<InstanceHandle, ExecutionError> {
+        let class = self.program.class_names.iter()
+            .position(|name| self.program.global_names[*name as usize].as_slice() == class_name.as_bytes())
+            .ok_or_else(|| host_error(RuntimeError::UnknownClass(class_name.to_owned())))?;
+
+        let inst = self.heap.alloc_instance(class);
+        let handle = self.hold(inst);
+
+        if let Some(ctor_index) = self.program.classes[class].constructor {
+            let stack_start = self.value_stack.len();
+            let result = self.call_function(inst, ctor_index, &mut NoHook);
+            self.value_stack.truncate(stack_start);
+
+            if let Err(err) = self.finish(result) {
+                self.release(handle);
+                return Err(err);
+            }
+        }
+
+        Ok(handle)
+    }
+
+    // Calls a method on an instance. The arguments are pushed in order, so the last one is
+    // on top of the stack, and everything left on the stack afterwards is returned from
+    // the bottom up.
+    pub fn call(&mut self, instance: InstanceHandle, method: &str, args: &[Value]) -> Result<Vec<Value>, ExecutionError> {
+        let inst = self.held_instance(instance)?;
+        let class = self.heap.instance(inst).class;
+
+        let member = self.program.member_names.iter().position(|name| name.as_slice() == method.as_bytes());
+        let func = match member.map(|name| name as MemberName) {
+            Some(name) => match self.heap.instance(inst).variables.get(&name) {
+                Some(GlassValue::Function(func_inst, func_index)) => Some((*func_inst, *func_index)),
+                Some(val) => return Err(host_error(wrong_type(&self.program, &self.heap, "function", val))),
+                None => self.program.classes[class].funcs.get(&name).map(|func_index| (inst, *func_index)),
+            },
+            None => None,
+        };
+
+        let (func_inst, func_index) = func.ok_or_else(|| host_error(RuntimeError::MissingMember {
+            class: self.program.global_name_str(self.program.class_names[class]),
+            name: name_spelling(&ByteString::new(method.as_bytes().to_vec())),
+        }))?;
+
+        // Anything already on the stack, e.g. from eval, isn't part of the results
+        let stack_start = self.value_stack.len();
+        for arg in args {
+            let value = match arg {
+                Value::Number(num) => GlassValue::Number(*num),
+                Value::String(string) => GlassValue::String(self.heap.alloc_string(string.clone())),
+                Value::Instance(handle) => GlassValue::Instance(self.held_instance(*handle)?),
+            };
+            self.value_stack.push(value);
+        }
+
+        let result = self.call_function(func_inst, func_index, &mut NoHook);
+        let values: Vec<_> = self.value_stack.drain(stack_start..).collect();
+        self.finish(result)?;
+
+        let mut results = Vec::new();
+        for value in values {
+            results.push(match value {
+                GlassValue::Number(num) => Value::Number(num),
+                GlassValue::String(index) => Value::String(self.heap.string(index).clone()),
+                GlassValue::Instance(inst) => Value::Instance(self.hold(inst)),
+                val => return Err(host_error(wrong_type(&self.program, &self.heap, "number, string or instance", &val))),
+            });
+        }
+
+        Ok(results)
+    }
+
+    // Adds the classes defined in some more code to the program
+    pub fn load_classes(&mut self, filename: &str, code: &[u8]) -> Result<(), Vec<ParseError>> {
+        let first_new_class = self.program.classes.len();
+        parse_classes_into(&mut self.program, filename, code)?;
+
+        for (i, class_name) in self.program.class_names.iter().enumerate().skip(first_new_class) {
+            self.globals.insert(*class_name, GlassValue::Class(i as ClassIndex));
+        }
+
+        self.update_program();
+        Ok(())
+    }
+
+    // Runs a function body on an instance as if it were one of its methods. Unlike call,
+    // the value stack is left as it is afterwards, so later code can keep using it.
+    pub fn eval(&mut self, instance: InstanceHandle, filename: &str, code: &[u8]) -> Result<(), EvalError> {
+        let inst = self.held_instance(instance).map_err(EvalError::Execution)?;
+
+        let start = parse_snippet_into(&mut self.program, filename, code).map_err(EvalError::Parse)?;
+        self.update_program();
+
+        let result = self.call_function(inst, start, &mut NoHook);
+        self.finish(result).map_err(EvalError::Execution)
+    }
+
+    // Describes the values on the stack, from the bottom up
+    pub fn describe_stack(&self) -> Vec<String> {
+        self.value_stack.iter().map(|value| describe_value(&self.program, &self.heap, value)).collect()
+    }
+
+    pub(crate) fn streams(&mut self) -> (&mut dyn BufRead, &mut dyn Write, &mut dyn Write) {
+        (&mut self.input, &mut self.output, &mut self.error_output)
+    }
+
+    // Lets the garbage collector free an instance once nothing else refers to it
+    pub fn release(&mut self, instance: InstanceHandle) {
+        if let Some(count) = self.host_roots.get_mut(&instance.0) {
+            *count -= 1;
+            if *count == 0 {
+                self.host_roots.remove(&instance.0);
+            }
+        }
+    }
+
+    // Catches up with constants and native methods that were added to the program
+    fn update_program(&mut self) {
+        self.heap.add_constants(&self.program);
+        self.native_bindings = bind_natives(&self.program, &self.natives);
+    }
+
+    fn hold(&mut self, inst: InstanceIndex) -> InstanceHandle {
+        *self.host_roots.entry(inst).or_insert(0) += 1;
+        InstanceHandle(inst)
+    }
+
+    fn held_instance(&self, instance: InstanceHandle) -> Result<InstanceIndex, ExecutionError> {
+        if self.host_roots.contains_key(&instance.0) {
+            Ok(instance.0)
+        }
+        else {
+            Err(host_error(RuntimeError::ReleasedInstance))
+        }
+    }
+
+    fn call_function(&mut self, object: InstanceIndex, func_index: OpcodeIndex, hook: &mut impl ExecutionHook) -> Result<(), ExecutionError> {
+        let mut func_stack = Vec::new();
+        let mut opcode_index = func_index;
+
+        match run_function(self, object, &mut opcode_index, &mut func_stack, hook) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                let mut stack_trace = Vec::new();
+                for (_, opcode_index, _) in func_stack {
+                    stack_trace.push(opcode_index);
+                }
+                stack_trace.push(opcode_index);
+                Err(ExecutionError { error: err, stack_trace, builtin: self.program.builtin_name(opcode_index) })
+            },
+        }
+    }
+
+    // Writes out whatever the program managed to output, even if it ended in an error
+    fn finish(&mut self, result: Result<(), ExecutionError>) -> Result<(), ExecutionError> {
+        let flushed = flush_output(&mut self.output)
+            .and_then(|_| flush_output(&mut self.error_output))
+            .map_err(host_error);
+
+        result.and(flushed)
+    }
+}
+
+pub fn execute_program(program: BytecodeProgram) -> Result<(), ExecutionError> {
+    Interpreter::new(program).run()
 }
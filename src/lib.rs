@@ -1,3 +1,19 @@
+pub mod ast;
 pub mod bytecode;
+pub mod coverage;
+pub mod debugger;
+pub mod format;
+mod heap;
+pub mod hook;
 pub mod interpreter;
+pub mod json;
+pub mod lower;
+pub mod lsp;
+pub mod native;
 pub mod parser;
+pub mod profiler;
+pub mod repl;
+pub mod serialize;
+mod stdlib;
+pub mod trace;
+pub mod verifier;
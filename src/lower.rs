@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use byte_string::ByteString;
+
+use crate::ast;
+use crate::bytecode::*;
+use crate::native::NativeClasses;
+use crate::parser::{ClassOutline, NameReference, ParseError, ParseErrorKind};
+
+const MAIN_CLASS_NAME: &[u8] = b"M";
+const MAIN_FUNC_NAME: &[u8] = b"m";
+const CONSTRUCTOR_FUNC_NAME: &[u8] = b"c__";
+
+// The variable class, which is built into the interpreter rather than being a native class
+
+type NumberConstantIndex = u16;
+
+type StringConstantIndex = u16;
+
+// Builds up the bytecode for a program, one class and function at a time
+pub(crate) struct BytecodeGenerator {
+    pub(crate) instructions: Vec<u8>,
+
+    // Kept in the order they're defined, so that adding classes to a program doesn't
+    // change the indices of the existing ones
+    classes: Vec<(GlobalName, ClassDefinition)>,
+
+    member_names: HashMap<ByteString, MemberName>,
+
+    global_names: HashMap<ByteString, GlobalName>,
+
+    local_names: HashMap<ByteString, LocalName>,
+
+    strings: HashMap<ByteString, StringConstantIndex>,
+
+    numbers: Vec<f64>,
+
+    files: Vec<(OpcodeIndex, String)>,
+
+    positions: Vec<(OpcodeIndex, FilePosition)>,
+
+    native_methods: Vec<(ByteString, ByteString)>,
+
+    // Where the names of the classes and functions parsed by this generator are, keyed by
+    // class name and by the function's first instruction
+    class_positions: HashMap<GlobalName, SourceLocation>,
+
+    func_positions: HashMap<OpcodeIndex, FilePosition>,
+
+    // The classes defined and the names used in the code, for editor tooling
+    pub(crate) outline: Vec<ClassOutline>,
+
+    pub(crate) references: Vec<NameReference>,
+}
+
+impl BytecodeGenerator {
+    pub(crate) fn new() -> Self {
+        BytecodeGenerator {
+            instructions: Vec::new(),
+            classes: Vec::new(),
+            member_names: HashMap::new(),
+            global_names: HashMap::new(),
+            local_names: HashMap::new(),
+            strings: HashMap::new(),
+            numbers: Vec::new(),
+            files: Vec::new(),
+            positions: Vec::new(),
+            native_methods: Vec::new(),
+            class_positions: HashMap::new(),
+            func_positions: HashMap::new(),
+            outline: Vec::new(),
+            references: Vec::new(),
+        }
+    }
+
+    // Creates a generator that adds to an existing program
+    pub(crate) fn from_program(program: &BytecodeProgram) -> Self {
+        let name_map = |table: &[ByteString]| -> HashMap<ByteString, u16> {
+            table.iter().enumerate().map(|(index, name)| (name.clone(), index as u16)).collect()
+        };
+
+        BytecodeGenerator {
+            instructions: program.instructions.clone(),
+            classes: program.class_names.iter().copied().zip(program.classes.iter().cloned()).collect(),
+            member_names: name_map(&program.member_names),
+            global_names: name_map(&program.global_names),
+            local_names: name_map(&program.local_names),
+            strings: name_map(&program.strings),
+            numbers: program.numbers.clone(),
+            files: program.files.clone(),
+            positions: program.positions.clone(),
+            native_methods: program.native_methods.clone(),
+            class_positions: HashMap::new(),
+            func_positions: HashMap::new(),
+            outline: Vec::new(),
+            references: Vec::new(),
+        }
+    }
+
+    fn get_name(name_map: &mut HashMap<ByteString, u16>, name_str: ByteString) -> Option<u16> {
+        match name_map.get(&name_str) {
+            Some(name) => Some(*name),
+            None => {
+                if name_map.len() >= u16::MAX as usize {
+                    None
+                }
+                else {
+                    let name = name_map.len() as u16;
+                    name_map.insert(name_str, name);
+                    Some(name)
+                }
+            }
+        }
+    }
+
+    // TODO: we shouldn't have multiple number indices for the same number, but this
+    // isn't immediately easy to do because floats don't play nice with maps
+    fn get_number_index(&mut self, num: f64) -> Option<NumberConstantIndex> {
+        if self.numbers.len() >= StringConstantIndex::MAX as usize {
+            None
+        }
+        else {
+            let index = self.numbers.len();
+            self.numbers.push(num);
+            Some(index as NumberConstantIndex)
+        }
+    }
+
+    fn get_string_index(&mut self, string: ByteString) -> Option<StringConstantIndex> {
+        match self.strings.get(&string) {
+            Some(index) => Some(*index),
+            None => {
+                if self.strings.len() >= u16::MAX as usize {
+                    None
+                }
+                else {
+                    let index = self.strings.len() as u16;
+                    self.strings.insert(string, index);
+                    Some(index)
+                }
+            }
+        }
+    }
+
+    fn get_global_name(&mut self, name_str: ByteString) -> Option<GlobalName> {
+        Self::get_name(&mut self.global_names, name_str)
+    }
+
+    fn get_member_name(&mut self, name_str: ByteString) -> Option<MemberName> {
+        Self::get_name(&mut self.member_names, name_str)
+    }
+
+    fn get_local_name(&mut self, name_str: ByteString) -> Option<LocalName> {
+        Self::get_name(&mut self.local_names, name_str)
+    }
+
+    // The location of a position in the file being parsed
+    fn location(&self, pos: FilePosition) -> SourceLocation {
+        let file = match self.files.last() {
+            Some((_, file)) => file.clone(),
+            None => String::new(),
+        };
+
+        SourceLocation { file, pos }
+    }
+
+    fn error(&self, kind: ParseErrorKind, pos: FilePosition) -> ParseError {
+        ParseError { kind, location: Some(self.location(pos)), related: None }
+    }
+
+    fn error_with_related(&self, kind: ParseErrorKind, pos: FilePosition, related: FilePosition) -> ParseError {
+        ParseError { kind, location: Some(self.location(pos)), related: Some(self.location(related)) }
+    }
+
+    fn add_func(&mut self, class: &mut ClassDefinition, func_name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        let ByteString(name_bytes) = &func_name_str;
+        if name_bytes == CONSTRUCTOR_FUNC_NAME {
+            class.constructor = Some(self.instructions.len())
+        }
+
+        let member_name = match self.get_member_name(func_name_str) {
+            Some(member_name) => member_name,
+            None => return Err(self.error(ParseErrorKind::TooManyMembers, pos)),
+        };
+
+        match class.funcs.entry(member_name) {
+            Entry::Vacant(entry) => {
+                entry.insert(self.instructions.len());
+                self.func_positions.insert(self.instructions.len(), pos);
+                Ok(())
+            },
+            Entry::Occupied(entry) => match self.func_positions.get(entry.get()) {
+                Some(first_pos) => Err(self.error_with_related(ParseErrorKind::DuplicateFuncName, pos, *first_pos)),
+                None => Err(self.error(ParseErrorKind::DuplicateFuncName, pos)),
+            },
+        }
+    }
+
+    fn add_class(&mut self, class: ClassDefinition, class_name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        let class_name = match self.get_global_name(class_name_str.clone()) {
+            Some(class_name) => class_name,
+            None => return Err(self.error(ParseErrorKind::TooManyGlobals, pos)),
+        };
+
+        if self.classes.iter().any(|(existing_name, _)| *existing_name == class_name) {
+            let mut err = self.error(ParseErrorKind::DuplicateClassName, pos);
+            err.related = self.class_positions.get(&class_name).cloned();
+            return Err(err);
+        }
+
+        let ByteString(name_bytes) = &class_name_str;
+        if name_bytes == MAIN_CLASS_NAME {
+            let has_main_func = match self.member_names.get(MAIN_FUNC_NAME) {
+                Some(main_func_name) => class.funcs.contains_key(main_func_name),
+                None => false,
+            };
+
+            if !has_main_func {
+                return Err(self.error(ParseErrorKind::MissingMainFunc, pos));
+            }
+        }
+
+        self.class_positions.insert(class_name, self.location(pos));
+        self.classes.push((class_name, class));
+        Ok(())
+    }
+
+    pub(crate) fn set_filename(&mut self, file: String) {
+        self.files.push((self.instructions.len(), file));
+    }
+
+    fn set_position(&mut self, pos: FilePosition) {
+        match self.positions.last() {
+            Some((_, old_pos)) => {
+                if pos != *old_pos {
+                    self.positions.push((self.instructions.len(), pos));
+                }
+            },
+            _ => self.positions.push((self.instructions.len(), pos)),
+        }
+    }
+
+    fn add_opcode(&mut self, opcode: OpCode, pos: FilePosition) {
+        self.set_position(pos);
+        self.instructions.push(opcode as u8);
+    }
+
+    fn add_call(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Call, pos);
+    }
+
+    fn add_call_native(&mut self, class_name: &str, method_name: &str, pos: FilePosition) -> Result<(), ParseError> {
+        if self.native_methods.len() >= u16::MAX as usize {
+            return Err(self.error(ParseErrorKind::TooManyNativeMethods, pos));
+        }
+        let native_index = self.native_methods.len() as u16;
+        self.native_methods.push((ByteString::new(class_name.as_bytes().to_vec()), ByteString::new(method_name.as_bytes().to_vec())));
+
+        self.add_opcode(OpCode::CallNative, pos);
+        self.instructions.push((native_index >> 8) as u8);
+        self.instructions.push((native_index & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    fn add_construct(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Construct, pos);
+    }
+
+    fn add_duplicate(&mut self, index: u8, pos: FilePosition) {
+        self.add_opcode(OpCode::Duplicate, pos);
+        self.instructions.push(index);
+    }
+
+    fn add_instantiate(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Instantiate, pos);
+    }
+
+    fn add_jump_if(&mut self, loop_start: usize, pos: FilePosition) -> Result<(), ParseError> {
+        let jump_amount = self.instructions.len() - loop_start + 3;
+        if jump_amount > (u16::MAX as usize) {
+            return Err(self.error(ParseErrorKind::LoopTooLong, pos));
+        }
+
+        let hi = (jump_amount >> 8) as u8;
+        let lo = (jump_amount & 0xFF) as u8;
+
+        self.add_opcode(OpCode::JumpIf, pos);
+        self.instructions.push(hi);
+        self.instructions.push(lo);
+
+        self.instructions[loop_start - 2] = hi;
+        self.instructions[loop_start - 1] = lo;
+
+        Ok(())
+    }
+
+    fn add_jump_if_not(&mut self, pos: FilePosition) -> usize {
+        self.add_opcode(OpCode::JumpIfNot, pos);
+        self.instructions.push(0);
+        self.instructions.push(0);
+
+        self.instructions.len()
+    }
+
+    fn add_load(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Load, pos);
+    }
+
+    fn add_load_from(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::LoadFrom, pos);
+    }
+
+    fn add_pop(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Pop, pos);
+    }
+
+    fn add_push_global(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        self.references.push(NameReference { name: String::from_utf8_lossy(&name_str).into_owned(), global: true, pos });
+
+        let global_name = match self.get_global_name(name_str) {
+            Some(global_name) => global_name,
+            None => return Err(self.error(ParseErrorKind::TooManyGlobals, pos)),
+        };
+
+        self.add_opcode(OpCode::PushGlobal, pos);
+        self.instructions.push((global_name >> 8) as u8);
+        self.instructions.push((global_name & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    fn add_push_local(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        let local_name = match self.get_local_name(name_str) {
+            Some(local_name) => local_name,
+            None => return Err(self.error(ParseErrorKind::TooManyGlobals, pos)),
+        };
+
+        self.add_opcode(OpCode::PushLocal, pos);
+        self.instructions.push((local_name >> 8) as u8);
+        self.instructions.push((local_name & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    fn add_push_member(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        self.references.push(NameReference { name: String::from_utf8_lossy(&name_str).into_owned(), global: false, pos });
+
+        let member_name = match self.get_member_name(name_str) {
+            Some(member_name) => member_name,
+            None => return Err(self.error(ParseErrorKind::TooManyMembers, pos)),
+        };
+
+        self.add_opcode(OpCode::PushMember, pos);
+        self.instructions.push((member_name >> 8) as u8);
+        self.instructions.push((member_name & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    fn add_push_name(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        match name_str[0] {
+            b'A' ..= b'Z' => self.add_push_global(name_str, pos),
+            b'a' ..= b'z' => self.add_push_member(name_str, pos),
+            b'_' => self.add_push_local(name_str, pos),
+            _ => Err(self.error(ParseErrorKind::UnexpectedName, pos)),
+        }
+    }
+
+    fn add_push_number(&mut self, number: f64, pos: FilePosition) -> Result<(), ParseError> {
+        let number_index = match self.get_number_index(number) {
+            Some(index) => index,
+            None => return Err(self.error(ParseErrorKind::TooManyNumbers, pos)),
+        };
+
+        self.add_opcode(OpCode::PushNumber, pos);
+        self.instructions.push((number_index >> 8) as u8);
+        self.instructions.push((number_index & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    fn add_push_self(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::PushSelf, pos);
+    }
+
+    fn add_push_string(&mut self, string: ByteString, pos: FilePosition) -> Result<(), ParseError> {
+        let string_index = match self.get_string_index(string) {
+            Some(string_index) => string_index,
+            None => return Err(self.error(ParseErrorKind::TooManyStrings, pos)),
+        };
+
+        self.add_opcode(OpCode::PushString, pos);
+        self.instructions.push((string_index >> 8) as u8);
+        self.instructions.push((string_index & 0xFF) as u8);
+
+        Ok(())
+    }
+
+    pub(crate) fn add_return(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Return, pos);
+    }
+
+    fn add_store(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::Store, pos);
+    }
+
+    fn add_store_keep(&mut self, pos: FilePosition) {
+        self.add_opcode(OpCode::StoreKeep, pos);
+    }
+
+    // Turns a map of strings to indices into a list of the strings at those indices
+    fn index_table(map: HashMap<ByteString, u16>) -> Vec<ByteString> {
+        let mut table = std::vec::from_elem(ByteString::new(vec![]), map.len());
+        for (string, index) in map {
+            table[index as usize] = string;
+        }
+        table
+    }
+
+    pub(crate) fn get_program(self) -> Result<BytecodeProgram, ParseError> {
+        let mut class_names = Vec::new();
+        let mut classes = Vec::new();
+
+        for (name, class) in self.classes {
+            class_names.push(name);
+            classes.push(class);
+        }
+
+        let main_class_name = match self.global_names.get(MAIN_CLASS_NAME) {
+            Some(name) if class_names.contains(name) => *name,
+            _ => return Err(ParseError { kind: ParseErrorKind::MissingMainClass, location: None, related: None }),
+        };
+
+        let main_func_name = match self.member_names.get(MAIN_FUNC_NAME) {
+            Some(name) => *name,
+            // This should be unreachable...
+            None => return Err(ParseError { kind: ParseErrorKind::MissingMainFunc, location: None, related: None }),
+        };
+
+        Ok(BytecodeProgram {
+            class_names,
+            classes,
+            strings: Self::index_table(self.strings),
+            numbers: self.numbers,
+            instructions: self.instructions,
+            main_class: main_class_name,
+            main_func: main_func_name,
+            files: self.files,
+            positions: self.positions,
+            global_names: Self::index_table(self.global_names),
+            member_names: Self::index_table(self.member_names),
+            local_names: Self::index_table(self.local_names),
+            native_methods: self.native_methods,
+        })
+    }
+}
+
+// Native classes can still clash with each other, e.g. by having two methods with the same name
+pub(crate) fn add_builtin_classes(gen: &mut BytecodeGenerator, natives: &NativeClasses) -> Result<(), ParseError> {
+    gen.set_filename(BUILTIN_FILE.to_owned());
+    let builtin_pos = FilePosition { line: 0, col: 0 };
+
+    for native in natives.iter() {
+        let mut class = ClassDefinition::new();
+        for method in native.methods() {
+            gen.add_func(&mut class, ByteString::new(method.as_bytes().to_vec()), builtin_pos)?;
+            gen.add_call_native(native.name(), method, builtin_pos)?;
+            gen.add_return(builtin_pos);
+        }
+        gen.add_class(class, ByteString::new(native.name().as_bytes().to_vec()), builtin_pos)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn lower_statements(gen: &mut BytecodeGenerator, statements: &[ast::Statement]) -> Result<(), ParseError> {
+    for statement in statements {
+        let pos = statement.span.start;
+
+        match &statement.kind {
+            ast::StatementKind::PushName(name) => gen.add_push_name(name.clone(), pos)?,
+            ast::StatementKind::PushString { value, .. } => gen.add_push_string(value.clone(), pos)?,
+            ast::StatementKind::PushNumber { value, .. } => gen.add_push_number(*value, pos)?,
+            ast::StatementKind::Duplicate(index) => gen.add_duplicate(*index, pos),
+            ast::StatementKind::Pop => gen.add_pop(pos),
+            ast::StatementKind::Return => gen.add_return(pos),
+            ast::StatementKind::Load => gen.add_load(pos),
+            ast::StatementKind::Store => gen.add_store(pos),
+            ast::StatementKind::Call => gen.add_call(pos),
+            ast::StatementKind::LoadFrom => gen.add_load_from(pos),
+            ast::StatementKind::StoreSelf => {
+                gen.add_push_self(pos);
+                gen.add_store(pos);
+            },
+            ast::StatementKind::Instantiate => {
+                gen.add_load(pos);
+                gen.add_instantiate(pos);
+                gen.add_store_keep(pos);
+                gen.add_construct(pos);
+            },
+            ast::StatementKind::Loop(body) => {
+                gen.add_push_name(body.name.clone(), pos)?;
+                gen.add_load(pos);
+                let loop_start = gen.add_jump_if_not(pos);
+
+                lower_statements(gen, &body.body)?;
+
+                let end_pos = statement.span.last_char();
+                gen.add_push_name(body.name.clone(), end_pos)?;
+                gen.add_load(end_pos);
+                gen.add_jump_if(loop_start, end_pos)?;
+            },
+            ast::StatementKind::Comment(_) => {},
+        }
+    }
+
+    Ok(())
+}
+
+fn lower_method(gen: &mut BytecodeGenerator, class: &mut ClassDefinition, method: &ast::Method) -> Result<(), ParseError> {
+    gen.add_func(class, method.name.clone(), method.name_span.start)?;
+    lower_statements(gen, &method.body)?;
+    gen.add_return(method.span.last_char());
+    Ok(())
+}
+
+fn contains(span: &ast::Span, pos: FilePosition) -> bool {
+    (span.start.line, span.start.col) <= (pos.line, pos.col) && (pos.line, pos.col) < (span.end.line, span.end.col)
+}
+
+// Generates the bytecode for the classes in a file. Errors already found in a class mean
+// it's expected to be missing functions, so a missing main function isn't reported then.
+pub(crate) fn lower_file(gen: &mut BytecodeGenerator, file: &ast::File, errors: &mut Vec<ParseError>) {
+    gen.set_filename(file.name.clone());
+
+    for class in file.classes() {
+        let mut had_errors = errors.iter().any(|err| match &err.location {
+            Some(location) => location.file == file.name && contains(&class.span, location.pos),
+            None => false,
+        });
+
+        let mut definition = ClassDefinition::new();
+        for method in class.methods() {
+            if let Err(err) = lower_method(gen, &mut definition, method) {
+                errors.push(err);
+                had_errors = true;
+            }
+        }
+
+        match gen.add_class(definition, class.name.clone(), class.name_span.start) {
+            Err(err) if err.kind == ParseErrorKind::MissingMainFunc && had_errors => {},
+            Err(err) => errors.push(err),
+            Ok(()) => {},
+        }
+    }
+}
+
+// Generates the bytecode for a program's syntax tree, which can use the given native classes
+// as well as the builtin V class
+pub fn lower_program(program: &ast::Program, natives: &NativeClasses) -> Result<BytecodeProgram, Vec<ParseError>> {
+    let mut gen = BytecodeGenerator::new();
+    let mut errors = Vec::new();
+
+    add_builtin_classes(&mut gen, natives).map_err(|err| vec![err])?;
+
+    for file in program.files.iter() {
+        lower_file(&mut gen, file, &mut errors);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    gen.get_program().map_err(|err| vec![err])
+}
@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::bytecode::FilePosition;
+use crate::json::JsonValue;
+use crate::native::{NativeClass, NativeClasses};
+use crate::parser::{outline_file, Definition, FileOutline, NameReference};
+
+// JSON-RPC error codes
+const PARSE_ERROR: f64 = -32700.0;
+const INVALID_PARAMS: f64 = -32602.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+// What the LSP calls the kinds of symbols
+const SYMBOL_KIND_CLASS: usize = 5;
+const SYMBOL_KIND_METHOD: usize = 6;
+
+const SEVERITY_ERROR: usize = 1;
+
+// The full text of a document is sent on every change
+const TEXT_DOCUMENT_SYNC_FULL: usize = 1;
+
+// An open document, along with what the parser found in it
+struct Document {
+    text: String,
+
+    outline: FileOutline,
+}
+
+impl Document {
+    fn new(uri: &str, text: String) -> Document {
+        let outline = outline_file(uri, text.as_bytes());
+        Document { text, outline }
+    }
+
+    fn line(&self, line: usize) -> &[u8] {
+        let line = line.checked_sub(1).and_then(|line| self.text.as_bytes().split(|c| *c == b'\n').nth(line));
+        line.unwrap_or(&[])
+    }
+
+    // Converts a position to a line and UTF-16 offset, which is how the LSP counts
+    // characters, clamping positions past the end of the line to its end
+    fn lsp_position(&self, pos: FilePosition) -> JsonValue {
+        let line = self.line(pos.line);
+        let before = &line[..pos.col.saturating_sub(1).min(line.len())];
+
+        JsonValue::object(vec![
+            ("line", pos.line.saturating_sub(1).into()),
+            ("character", String::from_utf8_lossy(before).encode_utf16().count().into()),
+        ])
+    }
+
+    fn file_position(&self, line: usize, character: usize) -> FilePosition {
+        let text = String::from_utf8_lossy(self.line(line + 1)).into_owned();
+
+        let mut units = 0;
+        let mut col = text.len() + 1;
+        for (index, c) in text.char_indices() {
+            if units >= character {
+                col = index + 1;
+                break;
+            }
+            units += c.len_utf16();
+        }
+
+        FilePosition { line: line + 1, col }
+    }
+
+    fn lsp_range(&self, start: FilePosition, end: FilePosition) -> JsonValue {
+        JsonValue::object(vec![("start", self.lsp_position(start)), ("end", self.lsp_position(end))])
+    }
+
+    // Finds where the name starting at a position ends, which is after the closing
+    // parenthesis of a name in parentheses
+    fn name_end(&self, pos: FilePosition) -> FilePosition {
+        let line = self.line(pos.line);
+        let start = pos.col.saturating_sub(1);
+
+        if line.get(start) == Some(&b'(') {
+            if let Some(length) = line[start..].iter().position(|c| *c == b')') {
+                return FilePosition { line: pos.line, col: pos.col + length + 1 };
+            }
+        }
+
+        FilePosition { line: pos.line, col: pos.col + 1 }
+    }
+
+    fn name_range(&self, pos: FilePosition) -> JsonValue {
+        self.lsp_range(pos, self.name_end(pos))
+    }
+
+    fn reference_at(&self, pos: FilePosition) -> Option<&NameReference> {
+        self.outline.references.iter().find(|reference| {
+            reference.pos.line == pos.line && reference.pos.col <= pos.col && pos.col < self.name_end(reference.pos).col
+        })
+    }
+
+    fn diagnostics(&self, uri: &str) -> JsonValue {
+        let diagnostics = self.outline.errors.iter().filter_map(|err| {
+            let location = err.location.as_ref()?;
+            let end = FilePosition { line: location.pos.line, col: location.pos.col + 1 };
+
+            let related = err.related.iter().map(|related| JsonValue::object(vec![
+                ("location", JsonValue::object(vec![
+                    ("uri", JsonValue::string(uri)),
+                    ("range", self.lsp_range(related.pos, FilePosition { line: related.pos.line, col: related.pos.col + 1 })),
+                ])),
+                ("message", JsonValue::string(err.kind.related_note())),
+            ])).collect();
+
+            Some(JsonValue::object(vec![
+                ("range", self.lsp_range(location.pos, end)),
+                ("severity", SEVERITY_ERROR.into()),
+                ("source", JsonValue::string("glass")),
+                ("message", JsonValue::string(err.kind.to_string())),
+                ("relatedInformation", JsonValue::Array(related)),
+            ]))
+        }).collect();
+
+        JsonValue::Array(diagnostics)
+    }
+
+    fn symbol(&self, definition: &Definition, kind: usize, children: Vec<JsonValue>) -> JsonValue {
+        JsonValue::object(vec![
+            ("name", JsonValue::string(&definition.name)),
+            ("kind", kind.into()),
+            ("range", self.lsp_range(definition.start, definition.end)),
+            ("selectionRange", self.name_range(definition.name_pos)),
+            ("children", JsonValue::Array(children)),
+        ])
+    }
+}
+
+fn invalid_params() -> (f64, String) {
+    (INVALID_PARAMS, "invalid parameters".to_owned())
+}
+
+fn notification(method: &str, params: JsonValue) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("method", JsonValue::string(method)),
+        ("params", params),
+    ])
+}
+
+fn response(id: JsonValue, result: Result<JsonValue, (f64, String)>) -> JsonValue {
+    let (key, value) = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", JsonValue::object(vec![
+            ("code", JsonValue::Number(code)),
+            ("message", JsonValue::string(message)),
+        ])),
+    };
+
+    JsonValue::object(vec![("jsonrpc", JsonValue::string("2.0")), ("id", id), (key, value)])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: JsonValue) -> JsonValue {
+    notification("textDocument/publishDiagnostics", JsonValue::object(vec![
+        ("uri", JsonValue::string(uri)),
+        ("diagnostics", diagnostics),
+    ]))
+}
+
+fn document_uri(params: &JsonValue) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+// Methods without docs are shown by their name alone
+fn describe_method(native: &dyn NativeClass, index: usize, label: &str) -> String {
+    match native.method_docs().get(index) {
+        Some(doc) if !doc.is_empty() => format!("**{}** {}", label, doc),
+        _ => format!("**{}**", label),
+    }
+}
+
+fn builtin_hover(natives: &NativeClasses, reference: &NameReference) -> Option<String> {
+    if reference.global {
+        let native = natives.iter().find(|native| native.name() == reference.name)?;
+
+        let mut hover = match native.docs() {
+            "" => format!("**{}**\n", native.name()),
+            docs => format!("**{}**: {}\n", native.name(), docs),
+        };
+        for (index, method) in native.methods().iter().enumerate() {
+            hover.push_str(&format!("\n- {}", describe_method(native, index, method)));
+        }
+        return Some(hover);
+    }
+
+    let docs: Vec<_> = natives.iter()
+        .filter_map(|native| {
+            let index = native.methods().iter().position(|method| *method == reference.name)?;
+            Some(describe_method(native, index, &format!("{}.{}", native.name(), reference.name)))
+        })
+        .collect();
+
+    if docs.is_empty() { None } else { Some(docs.join("\n\n")) }
+}
+
+// Keeps track of the open documents and answers requests about them
+pub struct LanguageServer {
+    documents: BTreeMap<String, Document>,
+
+    // Documents are outlined with the standard classes, so those are the ones described
+    natives: NativeClasses,
+}
+
+impl LanguageServer {
+    pub fn new() -> LanguageServer {
+        LanguageServer { documents: BTreeMap::new(), natives: NativeClasses::standard() }
+    }
+
+    // Finds the document and position a request is about
+    fn document_position(&self, params: &JsonValue) -> Option<(&Document, FilePosition)> {
+        let document = self.documents.get(document_uri(params)?)?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_f64()? as usize;
+        let character = position.get("character")?.as_f64()? as usize;
+
+        Some((document, document.file_position(line, character)))
+    }
+
+    fn open(&mut self, uri: &str, text: String) -> JsonValue {
+        let document = Document::new(uri, text);
+        let diagnostics = document.diagnostics(uri);
+        self.documents.insert(uri.to_owned(), document);
+
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn definition(&self, params: &JsonValue) -> Result<JsonValue, (f64, String)> {
+        let (document, pos) = self.document_position(params).ok_or_else(invalid_params)?;
+        let reference = match document.reference_at(pos) {
+            Some(reference) => reference,
+            None => return Ok(JsonValue::Null),
+        };
+
+        // Member names aren't tied to a class, so any method with the name could be the one
+        let mut locations = Vec::new();
+        for (uri, document) in self.documents.iter() {
+            for class in document.outline.classes.iter() {
+                let definitions = if reference.global { std::slice::from_ref(&class.class) } else { class.methods.as_slice() };
+
+                for definition in definitions.iter().filter(|definition| definition.name == reference.name) {
+                    locations.push(JsonValue::object(vec![
+                        ("uri", JsonValue::string(uri)),
+                        ("range", document.name_range(definition.name_pos)),
+                    ]));
+                }
+            }
+        }
+
+        Ok(JsonValue::Array(locations))
+    }
+
+    fn document_symbols(&self, params: &JsonValue) -> Result<JsonValue, (f64, String)> {
+        let document = document_uri(params).and_then(|uri| self.documents.get(uri)).ok_or_else(invalid_params)?;
+
+        let symbols = document.outline.classes.iter().map(|class| {
+            let methods = class.methods.iter().map(|method| document.symbol(method, SYMBOL_KIND_METHOD, Vec::new())).collect();
+            document.symbol(&class.class, SYMBOL_KIND_CLASS, methods)
+        }).collect();
+
+        Ok(JsonValue::Array(symbols))
+    }
+
+    fn hover(&self, params: &JsonValue) -> Result<JsonValue, (f64, String)> {
+        let (document, pos) = self.document_position(params).ok_or_else(invalid_params)?;
+
+        let reference = match document.reference_at(pos) {
+            Some(reference) => reference,
+            None => return Ok(JsonValue::Null),
+        };
+
+        Ok(match builtin_hover(&self.natives, reference) {
+            Some(hover) => JsonValue::object(vec![
+                ("contents", JsonValue::object(vec![("kind", JsonValue::string("markdown")), ("value", JsonValue::string(hover))])),
+                ("range", document.name_range(reference.pos)),
+            ]),
+            None => JsonValue::Null,
+        })
+    }
+
+    // Handles a request or notification from the client, giving the messages to send back
+    pub fn handle(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        let method = message.get("method").and_then(JsonValue::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&JsonValue::Null);
+
+        let result = match method {
+            "initialize" => Ok(JsonValue::object(vec![
+                ("capabilities", JsonValue::object(vec![
+                    ("textDocumentSync", TEXT_DOCUMENT_SYNC_FULL.into()),
+                    ("definitionProvider", JsonValue::Bool(true)),
+                    ("documentSymbolProvider", JsonValue::Bool(true)),
+                    ("hoverProvider", JsonValue::Bool(true)),
+                ])),
+                ("serverInfo", JsonValue::object(vec![("name", JsonValue::string("glass"))])),
+            ])),
+            "shutdown" => Ok(JsonValue::Null),
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|document| document.get("text")).and_then(JsonValue::as_str);
+                return match (document_uri(params), text) {
+                    (Some(uri), Some(text)) => vec![self.open(uri, text.to_owned())],
+                    _ => Vec::new(),
+                };
+            },
+            "textDocument/didChange" => {
+                // Only full syncing is supported, so the last change has the whole text
+                let changes = params.get("contentChanges").and_then(JsonValue::as_array);
+                let text = changes.and_then(|changes| changes.last()).and_then(|change| change.get("text")).and_then(JsonValue::as_str);
+                return match (document_uri(params), text) {
+                    (Some(uri), Some(text)) => vec![self.open(uri, text.to_owned())],
+                    _ => Vec::new(),
+                };
+            },
+            "textDocument/didClose" => {
+                return match document_uri(params) {
+                    Some(uri) => {
+                        self.documents.remove(uri);
+                        vec![publish_diagnostics(uri, JsonValue::Array(Vec::new()))]
+                    },
+                    None => Vec::new(),
+                };
+            },
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/hover" => self.hover(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+
+        // Notifications have no id, and never get a response
+        match message.get("id") {
+            Some(id) => vec![response(id.clone(), result)],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        LanguageServer::new()
+    }
+}
+
+// Reads the body of a message, which comes after a header giving its length. Gives None
+// at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(output: &mut impl Write, message: &JsonValue) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+// Runs a language server that talks to an editor over the given streams, until the
+// editor tells it to exit or closes the input
+pub fn run_lsp(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = LanguageServer::new();
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match JsonValue::parse(&String::from_utf8_lossy(&body)) {
+            Ok(message) => message,
+            Err(err) => {
+                write_message(&mut output, &response(JsonValue::Null, Err((PARSE_ERROR, err))))?;
+                continue;
+            },
+        };
+
+        if message.get("method").and_then(JsonValue::as_str) == Some("exit") {
+            break;
+        }
+
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+
+    Ok(())
+}
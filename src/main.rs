@@ -1,70 +1,514 @@
+use byte_string::ByteString;
+
+use glass::bytecode::disassemble;
 use glass::bytecode::BytecodeProgram;
-use glass::bytecode::OpcodeIndex;
+use glass::coverage::Coverage;
+use glass::debugger::Debugger;
+use glass::format::format_code;
+use glass::hook::{ExecutionHook, ExecutionState};
 use glass::interpreter::*;
+use glass::json::JsonValue;
+use glass::lsp::run_lsp;
 use glass::parser::*;
+use glass::profiler::Profiler;
+use glass::repl::run_repl;
+use glass::serialize::{is_compiled_program, LoadError};
+use glass::trace::Tracer;
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// Where the debugger reads commands from when it isn't given a file
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+#[cfg(not(windows))]
+const TERMINAL: &str = "/dev/tty";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Human,
+
+    // One JSON object per line, for editors and scripts
+    Json,
+}
+
+struct Options {
+    files: Vec<String>,
+
+    // Everything after --, which the program can read with the Args class
+    program_args: Vec<String>,
+
+    repl: bool,
+
+    lsp: bool,
+
+    fmt: bool,
+
+    // Only report which files need formatting, instead of rewriting them
+    check: bool,
+
+    dump_bytecode: bool,
+
+    compile_output: Option<String>,
+
+    heap_stats: bool,
+
+    debug: bool,
+
+    // Where the debugger reads its commands from, which is the terminal if no file is given
+    debug_commands: Option<String>,
+
+    // Where to write the trace, which is stderr if no file is given
+    trace: Option<Option<String>>,
+
+    profile: bool,
+
+    profile_stacks: Option<String>,
+
+    coverage: bool,
+
+    coverage_lcov: Option<String>,
+
+    error_format: ErrorFormat,
+
+    // Whether the program can see environment variables
+    env_vars: bool,
+
+    limits: Limits,
+}
+
+fn print_usage(program_name: &str) {
+    eprintln!("Usage: {} [options] <glass-file>... [-- <args>...]", program_name);
+    eprintln!("       {} repl [limits]", program_name);
+    eprintln!("       {} lsp", program_name);
+    eprintln!("       {} fmt [--check] <glass-file>...", program_name);
+    eprintln!();
+    eprintln!("The input can either be Glass source files, or a single compiled .glassc file. Any");
+    eprintln!("arguments after -- are passed to the program, which can read them with the Args class.");
+    eprintln!("The repl command starts an interactive session instead, where classes can be defined");
+    eprintln!("with {{...}} and anything else is run on an instance of M. The lsp command starts a");
+    eprintln!("language server for editors, which talks to them over stdin and stdout. The fmt command");
+    eprintln!("rewrites source files with a consistent layout, or with --check lists the files that");
+    eprintln!("aren't formatted.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --compile <output>   Compile the program to a .glassc file instead of running it");
+    eprintln!("  --dump-bytecode      Print the compiled bytecode instead of running the program");
+    eprintln!("  --env                Let the program read environment variables with the Env class,");
+    eprintln!("                       and set the ones named like globals, e.g. (HOME), as globals");
+    eprintln!("  --heap-stats         Print heap and garbage collection statistics after running");
+    eprintln!("  --debug              Run the program in the debugger, which reads commands from the terminal");
+    eprintln!("  --debug-commands <file>");
+    eprintln!("                       Run the program in the debugger, reading its commands from a file");
+    eprintln!("  --trace              Print every instruction to stderr as it runs");
+    eprintln!("  --trace-file <file>  Write every instruction to a file as it runs");
+    eprintln!("  --profile            Print the calls, instructions and time of each function after running");
+    eprintln!("  --profile-stacks <file>");
+    eprintln!("                       Write the instructions run in each call stack to a file, in the");
+    eprintln!("                       collapsed format used by flame graph tools");
+    eprintln!("  --coverage           Print which lines, functions and loops ran after running");
+    eprintln!("  --coverage-lcov <file>");
+    eprintln!("                       Write the coverage to a file in the lcov format");
+    eprintln!("  --error-format=<human|json>");
+    eprintln!("                       How to print parse and runtime errors, where json prints one");
+    eprintln!("                       object per line");
+    eprintln!();
+    eprintln!("Limits, which stop the program with an error when exceeded:");
+    eprintln!("  --max-instructions <n>   Maximum number of instructions to execute");
+    eprintln!("  --max-stack <n>          Maximum number of values on the stack");
+    eprintln!("  --max-call-depth <n>     Maximum number of nested function calls");
+    eprintln!("  --max-instances <n>      Maximum number of live instances");
+    eprintln!("  --max-string-bytes <n>   Maximum total size of the strings created while running");
+    eprintln!("  --no-files               Stop the program from opening files");
+}
+
+fn parse_limit<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    match value {
+        Some(value) => value.parse().map_err(|_| format!("Invalid value {} for {}", value, option)),
+        None => Err(format!("Missing value for {}", option)),
+    }
+}
+
+fn parse_error_format(value: Option<&str>) -> Result<ErrorFormat, String> {
+    match value {
+        Some("human") => Ok(ErrorFormat::Human),
+        Some("json") => Ok(ErrorFormat::Json),
+        Some(value) => Err(format!("Invalid value {} for --error-format", value)),
+        None => Err("Missing value for --error-format".to_owned()),
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut args = args.peekable();
+    let repl = args.next_if(|arg| arg == "repl").is_some();
+    let lsp = !repl && args.next_if(|arg| arg == "lsp").is_some();
+    let fmt = !repl && !lsp && args.next_if(|arg| arg == "fmt").is_some();
+
+    let mut options = Options {
+        files: Vec::new(),
+        program_args: Vec::new(),
+        repl,
+        lsp,
+        fmt,
+        check: false,
+        dump_bytecode: false,
+        compile_output: None,
+        heap_stats: false,
+        debug: false,
+        debug_commands: None,
+        trace: None,
+        profile: false,
+        profile_stacks: None,
+        coverage: false,
+        coverage_lcov: None,
+        error_format: ErrorFormat::Human,
+        env_vars: false,
+        limits: Limits::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compile" => match args.next() {
+                Some(output) => options.compile_output = Some(output),
+                None => return Err("Missing output file for --compile".to_owned()),
+            },
+            "--check" if options.fmt => options.check = true,
+            "--dump-bytecode" => options.dump_bytecode = true,
+            "--env" => options.env_vars = true,
+            "--heap-stats" => options.heap_stats = true,
+            "--debug" => options.debug = true,
+            "--debug-commands" => match args.next() {
+                Some(commands) => {
+                    options.debug = true;
+                    options.debug_commands = Some(commands);
+                },
+                None => return Err("Missing commands file for --debug-commands".to_owned()),
+            },
+            "--trace" => options.trace = Some(None),
+            "--trace-file" => match args.next() {
+                Some(output) => options.trace = Some(Some(output)),
+                None => return Err("Missing output file for --trace-file".to_owned()),
+            },
+            "--profile" => options.profile = true,
+            "--profile-stacks" => match args.next() {
+                Some(output) => options.profile_stacks = Some(output),
+                None => return Err("Missing output file for --profile-stacks".to_owned()),
+            },
+            "--coverage" => options.coverage = true,
+            "--coverage-lcov" => match args.next() {
+                Some(output) => options.coverage_lcov = Some(output),
+                None => return Err("Missing output file for --coverage-lcov".to_owned()),
+            },
+            "--error-format" => options.error_format = parse_error_format(args.next().as_deref())?,
+            _ if arg.starts_with("--error-format=") => options.error_format = parse_error_format(arg.split_once('=').map(|(_, value)| value))?,
+            "--max-instructions" => options.limits.max_instructions = Some(parse_limit(&arg, args.next())?),
+            "--max-stack" => options.limits.max_stack_depth = Some(parse_limit(&arg, args.next())?),
+            "--max-call-depth" => options.limits.max_call_depth = Some(parse_limit(&arg, args.next())?),
+            "--max-instances" => options.limits.max_instances = Some(parse_limit(&arg, args.next())?),
+            "--max-string-bytes" => options.limits.max_string_bytes = Some(parse_limit(&arg, args.next())?),
+            "--no-files" => options.limits.no_files = true,
+            "--" => options.program_args.extend(args.by_ref()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => options.files.push(arg),
+        }
+    }
 
-fn get_stacktrace_line(program: &BytecodeProgram, index: OpcodeIndex) -> String {
-    let mut err_file = &program.files[0].1;
-    for (file_index, filename) in program.files.iter() {
-        if *file_index > index {
-            break;
+    if options.repl {
+        if !options.files.is_empty() {
+            return Err("The REPL doesn't take input files".to_owned());
         }
-        err_file = &filename;
+    }
+    else if options.lsp {
+        if !options.files.is_empty() {
+            return Err("The language server doesn't take input files".to_owned());
+        }
+    }
+    else if options.files.is_empty() {
+        return Err("No input files".to_owned());
     }
 
-    let mut err_pos = &program.positions[0].1;
-    for (pos_index, pos) in program.positions.iter() {
-        if *pos_index > index {
-            break;
+    Ok(options)
+}
+
+// The tools that can watch the program run, each of which is enabled by an option
+struct Hooks {
+    debugger: Option<Debugger>,
+
+    tracer: Option<Tracer<BufWriter<Box<dyn Write>>>>,
+
+    profiler: Option<Profiler>,
+
+    coverage: Option<Coverage>,
+}
+
+impl Hooks {
+    fn is_empty(&self) -> bool {
+        self.debugger.is_none() && self.tracer.is_none() && self.profiler.is_none() && self.coverage.is_none()
+    }
+}
+
+impl ExecutionHook for Hooks {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        self.debugger.before_instruction(state)?;
+        self.tracer.before_instruction(state)?;
+        self.profiler.before_instruction(state)?;
+        self.coverage.before_instruction(state)
+    }
+}
+
+// Formats each file in place, or with --check just reports the ones that would change
+fn run_fmt(files: &[(String, Vec<u8>)], check: bool, error_format: ErrorFormat) -> bool {
+    let mut success = true;
+
+    for (filename, content) in files.iter() {
+        let formatted = match format_code(filename, content) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for err in errors.iter() {
+                    match error_format {
+                        ErrorFormat::Human => eprint!("{}", format_parse_error(files, err)),
+                        ErrorFormat::Json => eprintln!("{}", err.to_json()),
+                    }
+                }
+                success = false;
+                continue;
+            },
+        };
+
+        if formatted == *content {
+            continue;
+        }
+
+        if check {
+            println!("{}", filename);
+            success = false;
+        }
+        else if let Err(err) = std::fs::write(filename, formatted) {
+            eprintln!("{}: error: {}", filename, err);
+            success = false;
         }
-        err_pos = &pos;
     }
 
-    format!("In file {} on line {}, column {}", err_file, err_pos.line, err_pos.col).to_owned()
+    success
+}
+
+// Loads the program, either by parsing source files or by reading a compiled program
+fn load_program(files: &[(String, Vec<u8>)], error_format: ErrorFormat) -> BytecodeProgram {
+    let compiled_files: Vec<_> = files.iter().filter(|(_, content)| is_compiled_program(content)).collect();
+
+    if compiled_files.is_empty() {
+        return match parse_program(files) {
+            Ok(program) => program,
+            Err(errors) => {
+                for err in errors.iter() {
+                    match error_format {
+                        ErrorFormat::Human => eprint!("{}", format_parse_error(files, err)),
+                        ErrorFormat::Json => eprintln!("{}", err.to_json()),
+                    }
+                }
+                std::process::exit(1);
+            },
+        };
+    }
+
+    if files.len() > 1 {
+        exit_with_load_error(None, &LoadError::CombinedWithFiles, error_format);
+    }
+
+    let (filename, content) = compiled_files[0];
+    match BytecodeProgram::read_from(&mut content.as_slice()) {
+        Ok(program) => program,
+        Err(err) => exit_with_load_error(Some(filename), &err, error_format),
+    }
+}
+
+fn exit_with_load_error(filename: Option<&str>, err: &LoadError, error_format: ErrorFormat) -> ! {
+    match (error_format, filename) {
+        (ErrorFormat::Human, Some(filename)) => eprintln!("{}: error: {}", filename, err),
+        (ErrorFormat::Human, None) => eprintln!("error: {}", err),
+        (ErrorFormat::Json, _) => eprintln!("{}", err.to_json(filename)),
+    }
+    std::process::exit(1);
+}
+
+// For source files that couldn't be read, which are reported like any other error so tools
+// reading JSON don't have to handle a separate format
+fn exit_with_file_error(filename: &str, err: &std::io::Error, error_format: ErrorFormat) -> ! {
+    match error_format {
+        ErrorFormat::Human => eprintln!("{}: error: {}", filename, err),
+        ErrorFormat::Json => eprintln!("{}", JsonValue::object(vec![
+            ("type", JsonValue::string("file_error")),
+            ("message", JsonValue::string(err.to_string())),
+            ("file", JsonValue::string(filename)),
+        ])),
+    }
+    std::process::exit(1);
 }
 
 fn main() {
     let mut args = std::env::args();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <glass-file>", args.nth(0).expect("nameless executable?"));
-        std::process::exit(1);
+    let program_name = args.next().expect("nameless executable?");
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            print_usage(&program_name);
+            std::process::exit(1);
+        },
+    };
+
+    if options.repl {
+        if let Err(err) = run_repl(std::io::stdin(), std::io::stdout(), std::io::stderr(), options.limits) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.lsp {
+        if let Err(err) = run_lsp(std::io::stdin().lock(), std::io::stdout()) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
     }
 
     let mut files = Vec::new();
-    for arg in args.skip(1) {
-        let mut file = match File::open(arg.clone()) {
-            Ok(file) => file,
+    for arg in options.files {
+        let mut file_content = Vec::new();
+        let result = File::open(&arg).and_then(|mut file| file.read_to_end(&mut file_content));
+        if let Err(err) = result {
+            exit_with_file_error(&arg, &err, options.error_format);
+        }
+        files.push((arg, file_content));
+    }
+
+    if options.fmt {
+        if !run_fmt(&files, options.check, options.error_format) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let program = load_program(&files, options.error_format);
+
+    if let Some(output) = options.compile_output {
+        let result = File::create(&output)
+            .and_then(|file| program.write_to(&mut BufWriter::new(file)));
+
+        if let Err(err) = result {
+            eprintln!("{}: error: {}", output, err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.dump_bytecode {
+        print!("{}", disassemble(&program));
+        return;
+    }
+
+    let trace_output: Option<Box<dyn Write>> = match options.trace {
+        Some(Some(output)) => match File::create(&output) {
+            Ok(file) => Some(Box::new(file)),
             Err(err) => {
-                eprintln!("{:?}", err);
+                eprintln!("{}: error: {}", output, err);
                 std::process::exit(1);
             },
-        };
+        },
+        Some(None) => Some(Box::new(std::io::stderr())),
+        None => None,
+    };
 
-        let mut file_content = Vec::new();
-        file.read_to_end(&mut file_content).expect("Error reading file!");
-        files.push((arg, file_content));
+    let debug_commands = options.debug.then(|| {
+        let path = options.debug_commands.as_deref().unwrap_or(TERMINAL);
+        match File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(err) => {
+                eprintln!("{}: error: {}", path, err);
+                std::process::exit(1);
+            },
+        }
+    });
+
+    let mut hooks = Hooks {
+        debugger: debug_commands.map(|commands| Debugger::new(&program, commands)),
+        tracer: trace_output.map(|output| Tracer::new(BufWriter::new(output))),
+        profiler: (options.profile || options.profile_stacks.is_some()).then(|| Profiler::new(&program)),
+        coverage: (options.coverage || options.coverage_lcov.is_some()).then(|| Coverage::new(&program)),
+    };
+
+    let program_args = options.program_args.into_iter().map(|arg| ByteString::new(arg.into_bytes())).collect();
+    let mut interpreter = Interpreter::new(program).limits(options.limits).args(program_args);
+    if options.env_vars {
+        let env_vars = std::env::vars_os().map(|(name, value)| (ByteString::new(name.into_encoded_bytes()), ByteString::new(value.into_encoded_bytes())));
+        interpreter = interpreter.env_vars(env_vars);
     }
 
-    match parse_program(&files) {
-        Ok(program) => {
-            match execute_program(&program) {
-                Ok(_) => (),
-                Err(mut err) => {
-                    eprintln!("Error: {:?}", err.error);
-                    eprintln!("Traceback:");
-                    err.stack_trace.reverse();
-                    for trace_line in err.stack_trace {
-                        eprintln!("  {}", get_stacktrace_line(&program, trace_line));
-                    }
-                    std::process::exit(1);
-                },
+    // Running without hooks avoids checking for them on every instruction
+    let result = if hooks.is_empty() {
+        interpreter.run()
+    }
+    else {
+        interpreter.run_with_hook(&mut hooks)
+    };
+
+    if let Some(tracer) = &mut hooks.tracer {
+        if let Err(err) = tracer.flush() {
+            eprintln!("error: failed to write the trace: {}", err);
+        }
+    }
+
+    if let Some(profiler) = &mut hooks.profiler {
+        if options.profile {
+            // Ignore errors writing to stderr, like eprintln would panic on
+            let _ = profiler.write_report(&mut std::io::stderr());
+        }
+
+        if let Some(output) = options.profile_stacks {
+            let result = File::create(&output)
+                .and_then(|file| profiler.write_collapsed_stacks(&mut BufWriter::new(file)));
+
+            if let Err(err) = result {
+                eprintln!("{}: error: {}", output, err);
+            }
+        }
+    }
+
+    if options.heap_stats {
+        eprintln!("{}", interpreter.heap_stats());
+    }
+
+    if let Some(coverage) = &hooks.coverage {
+        if options.coverage {
+            let _ = coverage.write_summary(interpreter.program(), &mut std::io::stderr());
+        }
+
+        if let Some(output) = options.coverage_lcov {
+            let result = File::create(&output)
+                .and_then(|file| coverage.write_lcov(interpreter.program(), &mut BufWriter::new(file)));
+
+            if let Err(err) = result {
+                eprintln!("{}: error: {}", output, err);
             }
+        }
+    }
+
+    match result {
+        Ok(_) => (),
+        // Quitting the debugger has already been reported
+        Err(ExecutionError { error: RuntimeError::Stopped, .. }) => std::process::exit(1),
+        Err(err) if options.error_format == ErrorFormat::Json => {
+            eprintln!("{}", err.to_json(interpreter.program()));
+            std::process::exit(1);
         },
         Err(err) => {
-            eprintln!("{:?}", err);
+            eprintln!("Error: {}", err);
+            eprintln!("Traceback:");
+            for trace_line in err.traceback(interpreter.program()) {
+                eprintln!("  {}", trace_line);
+            }
             std::process::exit(1);
         },
     }
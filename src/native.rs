@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use byte_string::ByteString;
+
+use crate::bytecode::*;
+use crate::heap::*;
+use crate::interpreter::{flush_output, pop_number, pop_string, wrong_type, Limits, RuntimeError};
+use crate::parser::valid_name;
+use crate::stdlib::*;
+
+// A builtin class implemented in Rust. Its name is written without parentheses, so a
+// class named "Foo" is used as (Foo) in Glass code.
+pub trait NativeClass {
+    fn name(&self) -> &str;
+
+    fn methods(&self) -> &[&str];
+
+    // What the class is for, shown by the language server when hovering over its name
+    fn docs(&self) -> &str {
+        ""
+    }
+
+    // Describes each method in the same order as methods(), starting with its stack effect
+    // like `x y -- x+y`. Methods left out are shown with only their name.
+    fn method_docs(&self) -> &[&str] {
+        &[]
+    }
+
+    // Runs the method at the given index of methods(), taking its arguments from and
+    // leaving its results on the value stack
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError>;
+
+    // Called when the garbage collector frees an instance of the class, to release anything
+    // that was kept for it
+    fn finalize(&mut self, _instance: InstanceIndex) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+// The part of a running interpreter that native methods have access to
+pub struct NativeContext<'i> {
+    pub(crate) program: &'i BytecodeProgram,
+
+    pub(crate) heap: &'i mut Heap,
+
+    pub(crate) value_stack: &'i mut Vec<GlassValue>,
+
+    pub(crate) auto_generated_vars: &'i HashMap<AutoGeneratedName, GlassValue>,
+
+    pub(crate) input: &'i mut dyn BufRead,
+
+    pub(crate) output: &'i mut dyn Write,
+
+    pub(crate) error_output: &'i mut dyn Write,
+
+    pub(crate) args: &'i [ByteString],
+
+    pub(crate) env_vars: &'i HashMap<ByteString, ByteString>,
+
+    // The instance the native method was called on
+    pub(crate) instance: InstanceIndex,
+
+    pub(crate) limits: &'i Limits,
+}
+
+impl<'i> NativeContext<'i> {
+    pub fn pop_number(&mut self) -> Result<f64, RuntimeError> {
+        pop_number(self.value_stack, self.program, self.heap)
+    }
+
+    pub fn pop_string(&mut self) -> Result<&ByteString, RuntimeError> {
+        let index = pop_string(self.value_stack, self.program, self.heap)?;
+        Ok(self.heap.string(index))
+    }
+
+    // Values of any type, for the builtin classes that work with names rather than data
+    pub(crate) fn pop_value(&mut self) -> Result<GlassValue, RuntimeError> {
+        self.value_stack.pop().ok_or(RuntimeError::EmptyStack)
+    }
+
+    pub(crate) fn push_value(&mut self, value: GlassValue) {
+        self.value_stack.push(value);
+    }
+
+    pub(crate) fn auto_generated_vars(&self) -> &HashMap<AutoGeneratedName, GlassValue> {
+        self.auto_generated_vars
+    }
+
+    pub(crate) fn wrong_type(&self, expected: &'static str, value: &GlassValue) -> RuntimeError {
+        wrong_type(self.program, self.heap, expected, value)
+    }
+
+    pub fn push_number(&mut self, num: f64) {
+        self.value_stack.push(GlassValue::Number(num));
+    }
+
+    pub fn push_string(&mut self, string: ByteString) {
+        let index = self.heap.alloc_string(string);
+        self.value_stack.push(GlassValue::String(index));
+    }
+
+    // Output is flushed before handing out the input, in case the program is prompting for it
+    pub fn input(&mut self) -> Result<&mut dyn BufRead, RuntimeError> {
+        flush_output(self.output)?;
+        Ok(&mut *self.input)
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    pub fn error_output(&mut self) -> &mut dyn Write {
+        &mut *self.error_output
+    }
+
+    // The arguments given to the program after the files to run
+    pub fn args(&self) -> &[ByteString] {
+        self.args
+    }
+
+    // Only the environment variables given to the interpreter can be read
+    pub fn env_var(&self, name: &[u8]) -> Option<&ByteString> {
+        self.env_vars.get(name)
+    }
+}
+
+// Why a native class couldn't be registered, as it would never be callable from Glass code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    InvalidClassName(String),
+    InvalidMethodName { class: String, method: String },
+    ReservedClassName(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::InvalidClassName(name) => write!(f, "invalid class name \"{}\", expected letters, digits and _ not starting with a digit", name),
+            RegisterError::InvalidMethodName { class, method } => {
+                write!(f, "invalid method name \"{}\" in class {}, expected letters, digits and _ not starting with a digit", method, class)
+            },
+            RegisterError::ReservedClassName(name) => write!(f, "class name {} is used by a builtin class that can't be replaced", name),
+        }
+    }
+}
+
+// The native classes available to a program, which have to be the same when it's parsed
+// and when it's run
+pub struct NativeClasses {
+    classes: Vec<Box<dyn NativeClass>>,
+}
+
+impl NativeClasses {
+    // Only the V class, which every program can rely on being there
+    pub fn new() -> NativeClasses {
+        NativeClasses { classes: vec![Box::new(VarsClass)] }
+    }
+
+    // The standard library classes A, Args, Env, File, I, O and S, along with V
+    pub fn standard() -> NativeClasses {
+        NativeClasses {
+            classes: vec![
+                Box::new(MathClass),
+                Box::new(ArgsClass),
+                Box::new(EnvClass),
+                Box::new(FileClass::new()),
+                Box::new(InputClass::new()),
+                Box::new(OutputClass),
+                Box::new(StringClass),
+                Box::new(VarsClass),
+            ],
+        }
+    }
+
+    // Adds a class, replacing any class with the same name. Names are written the way they
+    // would be between parentheses, so a single letter or a longer name like "Foo".
+    pub fn register(&mut self, class: impl NativeClass + 'static) -> Result<(), RegisterError> {
+        let name = class.name();
+        if !valid_name(name.as_bytes()) {
+            return Err(RegisterError::InvalidClassName(name.to_owned()));
+        }
+        if name == VARS_CLASS_NAME {
+            return Err(RegisterError::ReservedClassName(name.to_owned()));
+        }
+        if let Some(method) = class.methods().iter().find(|method| !valid_name(method.as_bytes())) {
+            return Err(RegisterError::InvalidMethodName { class: name.to_owned(), method: (*method).to_owned() });
+        }
+
+        self.classes.retain(|existing| existing.name() != class.name());
+        self.classes.push(Box::new(class));
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn NativeClass> {
+        self.classes.iter().map(|class| class.as_ref())
+    }
+
+    // Finds a method by its class and method name, giving the indices of both
+    pub(crate) fn find(&self, class_name: &[u8], method_name: &[u8]) -> Option<(usize, usize)> {
+        let class_index = self.classes.iter().position(|class| class.name().as_bytes() == class_name)?;
+        let method_index = self.classes[class_index].methods().iter().position(|method| method.as_bytes() == method_name)?;
+        Some((class_index, method_index))
+    }
+
+    pub(crate) fn finalize(&mut self, program: &BytecodeProgram, freed: &[(InstanceIndex, ClassIndex)]) -> Result<(), RuntimeError> {
+        for (instance, class) in freed {
+            let class_name = &program.global_names[program.class_names[*class] as usize];
+            if let Some(native) = self.classes.iter_mut().find(|native| native.name().as_bytes() == class_name.as_slice()) {
+                native.finalize(*instance)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn call(&mut self, (class_index, method_index): (usize, usize), context: &mut NativeContext) -> Result<(), RuntimeError> {
+        self.classes[class_index].call(method_index, context)
+    }
+}
+
+impl Default for NativeClasses {
+    fn default() -> Self {
+        NativeClasses::new()
+    }
+}
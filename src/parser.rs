@@ -1,23 +1,31 @@
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::fmt;
 use std::iter::Peekable;
 use std::slice::Iter;
 use std::str::FromStr;
 
 use byte_string::ByteString;
 
+use crate::ast;
 use crate::bytecode::*;
+use crate::json::JsonValue;
+use crate::lower::{add_builtin_classes, lower_file, lower_statements, BytecodeGenerator};
+use crate::native::NativeClasses;
+
+// Where in the code an invalid character was found, which decides what was expected instead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeContext {
+    TopLevel,
+    ClassBody,
+    FunctionBody,
+    Snippet,
+}
 
-const MAIN_CLASS_NAME: &[u8] = b"M";
-const MAIN_FUNC_NAME: &[u8] = b"m";
-const CONSTRUCTOR_FUNC_NAME: &[u8] = b"c__";
-
-#[derive(Debug)]
-pub enum ParseError {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
     DuplicateClassName,
     DuplicateFuncName,
     IndexTooBig,
-    InvalidChar,
+    InvalidChar(u8, CodeContext),
     InvalidInteger,
     InvalidNumber,
     InvalidParentheses,
@@ -31,19 +39,175 @@ pub enum ParseError {
     UnendedClass,
     UnendedFunc,
     UnendedLoop,
-    UnendedNumber,
-    UnendedParentheses,
+    // The character that was reached before the closing one, or None for the end of the file
+    UnendedNumber(Option<u8>),
+    UnendedParentheses(Option<u8>),
     UnendedString,
     UnexpectedName,
     TooManyGlobals,
     TooManyMembers,
+    TooManyNativeMethods,
     TooManyNumbers,
     TooManyStrings,
 }
 
-type NumberConstantIndex = u16;
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+
+    pub location: Option<SourceLocation>,
+
+    // Another place the error refers to, like where an unended string starts or where a
+    // duplicate name was first defined
+    pub related: Option<SourceLocation>,
+}
+
+impl ParseErrorKind {
+    // Describes what's at the related location of an error of this kind
+    pub fn related_note(&self) -> &'static str {
+        match self {
+            ParseErrorKind::DuplicateClassName | ParseErrorKind::DuplicateFuncName => "first defined here",
+            ParseErrorKind::UnendedNumber(_) => "number starts here",
+            ParseErrorKind::UnendedParentheses(_) => "parentheses open here",
+            ParseErrorKind::UnendedString => "string starts here",
+            _ => "related to this",
+        }
+    }
+}
+
+impl fmt::Display for CodeContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            CodeContext::TopLevel => "outside of a class, expected `{` to start a class",
+            CodeContext::ClassBody => "in a class body, expected `[` to start a function or `}` to end the class",
+            CodeContext::FunctionBody => "in a function body, expected a command or `]` to end the function",
+            CodeContext::Snippet => "where a command was expected",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+// Describes the character an unended name, number or string ran into
+fn describe_end(end: &Option<u8>) -> String {
+    match end {
+        Some(b'\n') => "the end of the line".to_owned(),
+        Some(c) => format!("`{}`", c.escape_ascii()),
+        None => "the end of the file".to_owned(),
+    }
+}
 
-type StringConstantIndex = u16;
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ParseErrorKind::InvalidChar(b'\\', CodeContext::FunctionBody | CodeContext::Snippet) => {
+                return write!(f, "invalid character `\\`, there's no loop for it to end");
+            },
+            ParseErrorKind::InvalidChar(c, context) => {
+                return write!(f, "invalid character `{}` {}", c.escape_ascii(), context);
+            },
+            ParseErrorKind::MissingMainFunc => "missing main function, class M needs a method m for the program to start from",
+            ParseErrorKind::UnendedNumber(end) => {
+                return write!(f, "unended number, expected `>` before {}", describe_end(end));
+            },
+            ParseErrorKind::UnendedParentheses(end) => {
+                return write!(f, "unended parentheses, expected `)` before {}", describe_end(end));
+            },
+            ParseErrorKind::UnendedString => "unended string, expected `\"` before the end of the file",
+            ParseErrorKind::DuplicateClassName => "duplicate class name",
+            ParseErrorKind::DuplicateFuncName => "duplicate function name",
+            ParseErrorKind::IndexTooBig => "stack index is too big",
+            ParseErrorKind::InvalidInteger => "invalid integer",
+            ParseErrorKind::InvalidNumber => "invalid number",
+            ParseErrorKind::InvalidParentheses => "invalid parentheses",
+            ParseErrorKind::InvalidString => "invalid string",
+            ParseErrorKind::LoopTooLong => "loop is too long",
+            ParseErrorKind::MissingClassName => "missing class name",
+            ParseErrorKind::MissingFuncName => "missing function name",
+            ParseErrorKind::MissingLoopName => "missing loop name",
+            ParseErrorKind::MissingMainClass => "missing main class",
+            ParseErrorKind::UnendedClass => "unended class",
+            ParseErrorKind::UnendedFunc => "unended function",
+            ParseErrorKind::UnendedLoop => "unended loop",
+            ParseErrorKind::UnexpectedName => "unexpected name",
+            ParseErrorKind::TooManyGlobals => "too many global names",
+            ParseErrorKind::TooManyMembers => "too many member names",
+            ParseErrorKind::TooManyNativeMethods => "too many native methods",
+            ParseErrorKind::TooManyNumbers => "too many number constants",
+            ParseErrorKind::TooManyStrings => "too many string constants",
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+impl ParseError {
+    // Describes the error as a single JSON object, with the same message and locations as
+    // format_parse_error
+    pub fn to_json(&self) -> JsonValue {
+        let related = self.related.as_ref().map(|related| JsonValue::object(vec![
+            ("message", JsonValue::string(self.kind.related_note())),
+            ("location", related.to_json()),
+        ]));
+
+        JsonValue::object(vec![
+            ("type", JsonValue::string("parse_error")),
+            ("message", JsonValue::string(self.kind.to_string())),
+            ("location", self.location.as_ref().map(|location| location.to_json()).into()),
+            ("related", related.into()),
+        ])
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: error: {}", location, self.kind),
+            None => write!(f, "error: {}", self.kind),
+        }
+    }
+}
+
+// A class or method definition, with where its name is and the code it covers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+
+    pub name_pos: FilePosition,
+
+    pub start: FilePosition,
+
+    // Just past the end of the definition
+    pub end: FilePosition,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassOutline {
+    pub class: Definition,
+
+    pub methods: Vec<Definition>,
+}
+
+// A global or member name pushed by some code, like the (Foo) in (Foo)* or the x in x.?
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameReference {
+    pub name: String,
+
+    pub global: bool,
+
+    pub pos: FilePosition,
+}
+
+// What editor tooling needs to know about a single file, which is available even when
+// parts of it fail to parse
+#[derive(Debug)]
+pub struct FileOutline {
+    pub classes: Vec<ClassOutline>,
+
+    pub references: Vec<NameReference>,
+
+    pub errors: Vec<ParseError>,
+}
 
 impl FilePosition {
     fn advance(&mut self, c: u8) {
@@ -61,21 +225,22 @@ struct CodeReader<'a> {
     code: Peekable<Iter<'a, u8>>,
 
     pos: FilePosition,
+
+    // Comments that have been skipped over and not yet added to the syntax tree
+    comments: Vec<ast::Comment>,
 }
 
 impl<'a> CodeReader<'a> {
-    fn new(code: &[u8]) -> CodeReader {
+    fn new(code: &[u8]) -> CodeReader<'_> {
         CodeReader {
             code: code.iter().peekable(),
-            pos: FilePosition { line: 1, col: 1 }
+            pos: FilePosition { line: 1, col: 1 },
+            comments: Vec::new(),
         }
     }
 
     fn peek(&mut self) -> Option<u8> {
-        match self.code.peek() {
-            Some(c) => Some(**c),
-            None => None,
-        }
+        self.code.peek().map(|c| **c)
     }
 
     fn next(&mut self) -> Option<(u8, FilePosition)> {
@@ -90,724 +255,600 @@ impl<'a> CodeReader<'a> {
     }
 }
 
-struct BytecodeGenerator {
-    instructions: Vec<u8>,
-
-    classes: HashMap<GlobalName, ClassDefinition>,
-
-    member_names: HashMap<ByteString, MemberName>,
-
-    global_names: HashMap<ByteString, GlobalName>,
-
-    local_names: HashMap<ByteString, LocalName>,
-
-    strings: HashMap<ByteString, StringConstantIndex>,
-
-    numbers: Vec<f64>,
+// Skips whitespace and comments, keeping the comments in the reader. Returns whether
+// there's anything after them.
+fn skip_whitespace(reader: &mut CodeReader) -> bool {
+    while let Some(c) = reader.peek() {
+        if c == b'\'' {
+            let start = reader.pos;
+            let mut text = ByteString::new(vec![]);
+            reader.next();
+            loop {
+                match reader.next() {
+                    Some((b'\'', _)) => break,
+                    Some((c, _)) => text.push(c),
+                    None => {
+                        reader.comments.push(ast::Comment { text, span: ast::Span { start, end: reader.pos } });
+                        return false;
+                    },
+                }
+            }
+            reader.comments.push(ast::Comment { text, span: ast::Span { start, end: reader.pos } });
+            continue;
+        }
+        else if !c.is_ascii_whitespace() {
+            return true;
+        }
 
-    files: Vec<(OpcodeIndex, String)>,
+        reader.next();
+    }
 
-    positions: Vec<(OpcodeIndex, FilePosition)>,
+    false
 }
 
-impl BytecodeGenerator {
-    fn new() -> Self {
-        BytecodeGenerator {
-            instructions: Vec::new(),
-            classes: HashMap::new(),
-            member_names: HashMap::new(),
-            global_names: HashMap::new(),
-            local_names: HashMap::new(),
-            strings: HashMap::new(),
-            numbers: Vec::new(),
-            files: Vec::new(),
-            positions: Vec::new(),
+// Skips ahead until reaching one of the stop characters, without treating the contents
+// of strings or comments as stop characters. Used to find a place to resume parsing
+// after an error.
+fn skip_until(reader: &mut CodeReader, stop: &[u8]) {
+    while let Some(c) = reader.peek() {
+        if stop.contains(&c) {
+            return;
         }
-    }
 
-    fn get_name(name_map: &mut HashMap<ByteString, u16>, name_str: ByteString) -> Option<u16> {
-        match name_map.get(&name_str) {
-            Some(name) => Some(*name),
-            None => {
-                if name_map.len() >= u16::MAX as usize {
-                    None
+        reader.next();
+
+        match c {
+            b'"' => {
+                loop {
+                    match reader.next() {
+                        Some((b'"', _)) | None => break,
+                        Some((b'\\', _)) => { reader.next(); },
+                        Some(_) => {},
+                    }
                 }
-                else {
-                    let name = name_map.len() as u16;
-                    name_map.insert(name_str, name);
-                    Some(name)
+            },
+            b'\'' => {
+                loop {
+                    match reader.next() {
+                        Some((b'\'', _)) | None => break,
+                        Some(_) => {},
+                    }
                 }
-            }
+            },
+            _ => {},
         }
     }
+}
 
-    // TODO: we shouldn't have multiple number indices for the same number, but this
-    // isn't immediately easy to do because floats don't play nice with maps
-    fn get_number_index(&mut self, num: f64) -> Option<NumberConstantIndex> {
-        if self.numbers.len() >= StringConstantIndex::MAX as usize {
-            None
-        }
-        else {
-            let index = self.numbers.len();
-            self.numbers.push(num);
-            Some(index as NumberConstantIndex)
-        }
-    }
+// Skips to the end of a function after an error in it, or to the start of the next
+// function or class if the function was never closed
+fn recover_function(reader: &mut CodeReader) {
+    skip_until(reader, b"[]{}");
 
-    fn get_string_index(&mut self, string: ByteString) -> Option<StringConstantIndex> {
-        match self.strings.get(&string) {
-            Some(index) => Some(*index),
-            None => {
-                if self.strings.len() >= u16::MAX as usize {
-                    None
-                }
-                else {
-                    let index = self.strings.len() as u16;
-                    self.strings.insert(string, index);
-                    Some(index)
-                }
-            }
-        }
+    if reader.peek() == Some(b']') {
+        reader.next();
     }
+}
 
-    fn get_global_name(&mut self, name_str: ByteString) -> Option<GlobalName> {
-        Self::get_name(&mut self.global_names, name_str)
-    }
+// Characters that can never appear in a parenthesized name or a number, so reaching
+// one of them means the name or number was never closed
+fn ends_unclosed_group(c: u8) -> bool {
+    matches!(c, b'[' | b']' | b'{' | b'}' | b'\n')
+}
 
-    fn get_member_name(&mut self, name_str: ByteString) -> Option<MemberName> {
-        Self::get_name(&mut self.member_names, name_str)
+pub(crate) fn valid_name(name: &[u8]) -> bool {
+    if name.is_empty() {
+        return false;
     }
 
-    fn get_local_name(&mut self, name_str: ByteString) -> Option<LocalName> {
-        Self::get_name(&mut self.local_names, name_str)
+    if name[0].is_ascii_digit() {
+        return false;
     }
 
-    fn add_func(&mut self, class: &mut ClassDefinition, func_name_str: ByteString) -> Result<(), ParseError> {
-        let ByteString(name_bytes) = &func_name_str;
-        if name_bytes == CONSTRUCTOR_FUNC_NAME {
-            class.constructor = Some(self.instructions.len())
-        }
-
-        let member_name = match self.get_member_name(func_name_str) {
-            Some(member_name) => member_name,
-            None => return Err(ParseError::TooManyMembers),
-        };
-
-        match class.funcs.entry(member_name) {
-            Entry::Vacant(entry) => {
-                entry.insert(self.instructions.len());
-                Ok(())
-            },
-            Entry::Occupied(_) => Err(ParseError::DuplicateFuncName),
+    for c in name {
+        if *c != b'_' && !c.is_ascii_alphanumeric() {
+            return false;
         }
     }
 
-    fn add_class(&mut self, class: ClassDefinition, class_name_str: ByteString) -> Result<(), ParseError> {
-        let class_name = match self.get_global_name(class_name_str.clone()) {
-            Some(class_name) => class_name,
-            None => return Err(ParseError::TooManyGlobals),
-        };
-
-        match self.classes.entry(class_name) {
-            Entry::Vacant(entry) => {
-                let ByteString(name_bytes) = &class_name_str;
-                if name_bytes == MAIN_CLASS_NAME {
-                    match self.member_names.get(MAIN_FUNC_NAME) {
-                        Some(main_func_name) => {
-                            if !class.funcs.contains_key(main_func_name) {
-                                return Err(ParseError::MissingMainFunc);
-                            }
-                        },
-                        None => return Err(ParseError::MissingMainFunc),
-                    }
-                }
+    true
+}
 
-                entry.insert(class);
-                Ok(())
-            },
-            Entry::Occupied(_) => Err(ParseError::DuplicateClassName),
-        }
+fn get_integer(int_str: &ByteString) -> Result<u8, ParseErrorKind> {
+    if int_str.is_empty() {
+        return Err(ParseErrorKind::InvalidNumber)
     }
 
-    fn set_filename(&mut self, file: String) {
-        self.files.push((self.instructions.len(), file));
-    }
+    let mut integer: usize = 0;
 
-    fn set_position(&mut self, pos: FilePosition) {
-        match self.positions.last() {
-            Some((_, old_pos)) => {
-                if pos != *old_pos {
-                    self.positions.push((self.instructions.len(), pos));
-                }
-            },
-            _ => self.positions.push((self.instructions.len(), pos)),
+    for c in int_str {
+        if !c.is_ascii_digit() {
+            return Err(ParseErrorKind::InvalidInteger);
+        }
+        integer = (integer * 10) + (*c - b'0') as usize;
+        if integer > (u8::MAX as usize) {
+            return Err(ParseErrorKind::IndexTooBig);
         }
     }
 
-    fn add_opcode(&mut self, opcode: OpCode, pos: FilePosition) {
-        self.set_position(pos);
-        self.instructions.push(opcode as u8);
-    }
-
-    fn add_call(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Call, pos);
-    }
+    Ok(integer as u8)
+}
 
-    fn add_construct(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Construct, pos);
+// Parses a class, function or loop name, returning the name and where it is,
+// or the position where a name was expected if there wasn't a valid one
+fn parse_name(reader: &mut CodeReader) -> Result<(ByteString, ast::Span), FilePosition> {
+    if !skip_whitespace(reader) {
+        return Err(reader.pos);
     }
 
-    fn add_duplicate(&mut self, index: u8, pos: FilePosition) {
-        self.add_opcode(OpCode::Duplicate, pos);
-        self.instructions.push(index);
-    }
+    let name_pos = reader.pos;
 
-    fn add_instantiate(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Instantiate, pos);
+    match reader.peek() {
+        Some(c) if c.is_ascii_alphabetic() => {
+            reader.next();
+            Ok((ByteString::new(vec![c]), ast::Span { start: name_pos, end: reader.pos }))
+        },
+        Some(b'(') => {
+            reader.next();
+            let mut name = ByteString::new(vec![]);
+            loop {
+                match reader.peek() {
+                    Some(b')') => {
+                        reader.next();
+                        if valid_name(&name) {
+                            return Ok((name, ast::Span { start: name_pos, end: reader.pos }));
+                        }
+                        return Err(name_pos);
+                    },
+                    Some(c) if !ends_unclosed_group(c) => {
+                        reader.next();
+                        name.push(c);
+                    },
+                    _ => return Err(name_pos),
+                }
+            }
+        },
+        _ => Err(name_pos),
     }
+}
 
-    fn add_jump_if(&mut self, loop_start: usize, pos: FilePosition) -> Result<(), ParseError> {
-        let jump_amount = self.instructions.len() - loop_start + 3;
-        if jump_amount > (u16::MAX as usize) {
-            return Err(ParseError::LoopTooLong);
-        }
+// Reads the syntax tree of a file, collecting its errors and an outline of the classes in it
+struct FileParser<'a> {
+    reader: CodeReader<'a>,
 
-        let hi = (jump_amount >> 8) as u8;
-        let lo = (jump_amount & 0xFF) as u8;
+    file: String,
 
-        self.add_opcode(OpCode::JumpIf, pos);
-        self.instructions.push(hi);
-        self.instructions.push(lo);
+    errors: Vec<ParseError>,
 
-        self.instructions[loop_start - 2] = hi;
-        self.instructions[loop_start - 1] = lo;
+    outline: Vec<ClassOutline>,
+}
 
-        Ok(())
+impl<'a> FileParser<'a> {
+    fn new(file: &str, code: &'a [u8]) -> FileParser<'a> {
+        FileParser {
+            reader: CodeReader::new(code),
+            file: file.to_owned(),
+            errors: Vec::new(),
+            outline: Vec::new(),
+        }
     }
 
-    fn add_jump_if_not(&mut self, pos: FilePosition) -> usize {
-        self.add_opcode(OpCode::JumpIfNot, pos);
-        self.instructions.push(0);
-        self.instructions.push(0);
-
-        self.instructions.len()
+    fn location(&self, pos: FilePosition) -> SourceLocation {
+        SourceLocation { file: self.file.clone(), pos }
     }
 
-    fn add_load(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Load, pos);
+    fn error(&self, kind: ParseErrorKind, pos: FilePosition) -> ParseError {
+        ParseError { kind, location: Some(self.location(pos)), related: None }
     }
 
-    fn add_load_from(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::LoadFrom, pos);
+    fn error_with_related(&self, kind: ParseErrorKind, pos: FilePosition, related: FilePosition) -> ParseError {
+        ParseError { kind, location: Some(self.location(pos)), related: Some(self.location(related)) }
     }
 
-    fn add_pop(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Pop, pos);
+    fn take_comments(&mut self) -> impl Iterator<Item = ast::Comment> {
+        std::mem::take(&mut self.reader.comments).into_iter()
     }
 
-    fn add_push_global(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
-        let global_name = match self.get_global_name(name_str) {
-            Some(global_name) => global_name,
-            None => return Err(ParseError::TooManyGlobals),
-        };
+    fn parse_file(&mut self) -> ast::File {
+        let start = self.reader.pos;
+        let mut items = Vec::new();
+
+        while skip_whitespace(&mut self.reader) {
+            items.extend(self.take_comments().map(ast::Item::Comment));
 
-        self.add_opcode(OpCode::PushGlobal, pos);
-        self.instructions.push((global_name >> 8) as u8);
-        self.instructions.push((global_name & 0xFF) as u8);
+            match self.reader.peek() {
+                Some(b'{') => {
+                    if let Some(class) = self.parse_class() {
+                        items.push(ast::Item::Class(class));
+                    }
+                },
+                Some(c) => {
+                    self.errors.push(self.error(ParseErrorKind::InvalidChar(c, CodeContext::TopLevel), self.reader.pos));
+                    skip_until(&mut self.reader, b"{");
+                },
+                None => unreachable!(),
+            }
+        }
 
-        Ok(())
+        items.extend(self.take_comments().map(ast::Item::Comment));
+        ast::File { name: self.file.clone(), items, span: ast::Span { start, end: self.reader.pos } }
     }
 
-    fn add_push_local(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
-        let local_name = match self.get_local_name(name_str) {
-            Some(local_name) => local_name,
-            None => return Err(ParseError::TooManyGlobals),
+    // Parses a class, giving None if it has no name or never ends
+    fn parse_class(&mut self) -> Option<ast::Class> {
+        let class_pos = match self.reader.next() {
+            Some((b'{', pos)) => pos,
+            _ => unreachable!(),
         };
 
-        self.add_opcode(OpCode::PushLocal, pos);
-        self.instructions.push((local_name >> 8) as u8);
-        self.instructions.push((local_name & 0xFF) as u8);
-
-        Ok(())
-    }
+        let first_error = self.errors.len();
+        let mut items = Vec::new();
+        let mut methods = Vec::new();
 
-    fn add_push_member(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
-        let member_name = match self.get_member_name(name_str) {
-            Some(member_name) => member_name,
-            None => return Err(ParseError::TooManyMembers),
+        let name = match parse_name(&mut self.reader) {
+            Ok(name) => Some(name),
+            Err(pos) => {
+                self.errors.push(self.error(ParseErrorKind::MissingClassName, pos));
+                skip_until(&mut self.reader, b"[{}");
+                None
+            },
         };
 
-        self.add_opcode(OpCode::PushMember, pos);
-        self.instructions.push((member_name >> 8) as u8);
-        self.instructions.push((member_name & 0xFF) as u8);
+        let mut ended = false;
+        while skip_whitespace(&mut self.reader) {
+            items.extend(self.take_comments().map(ast::ClassItem::Comment));
+
+            match self.reader.peek() {
+                Some(b'[') => {
+                    let func_pos = self.reader.pos;
+                    match self.parse_method(&mut methods) {
+                        Ok(method) => items.push(ast::ClassItem::Method(method)),
+                        Err(err) => {
+                            self.errors.push(err);
+                            recover_function(&mut self.reader);
+                        },
+                    }
 
-        Ok(())
-    }
+                    // A function with an error still covers the code up to where parsing resumes
+                    if let Some(method) = methods.last_mut().filter(|method| method.start == func_pos) {
+                        method.end = self.reader.pos;
+                    }
+                },
+                Some(b'}') => {
+                    self.reader.next();
+                    ended = true;
+                    break;
+                },
+                Some(b'{') => break,
+                Some(c) => {
+                    self.errors.push(self.error(ParseErrorKind::InvalidChar(c, CodeContext::ClassBody), self.reader.pos));
+                    skip_until(&mut self.reader, b"[{}");
+                },
+                None => unreachable!(),
+            }
+        }
 
-    fn add_push_name(&mut self, name_str: ByteString, pos: FilePosition) -> Result<(), ParseError> {
-        match name_str[0] {
-            b'A' ..= b'Z' => self.add_push_global(name_str, pos),
-            b'a' ..= b'z' => self.add_push_member(name_str, pos),
-            b'_' => self.add_push_local(name_str, pos),
-            _ => Err(ParseError::UnexpectedName),
+        // An earlier error in this class may have skipped past its end, so only report it
+        // as unended if nothing else was wrong with it
+        if !ended && self.errors.len() == first_error {
+            self.errors.push(self.error(ParseErrorKind::UnendedClass, class_pos));
         }
-    }
 
-    fn add_push_number(&mut self, number: f64, pos: FilePosition) -> Result<(), ParseError> {
-        let number_index = match self.get_number_index(number) {
-            Some(index) => index,
-            None => return Err(ParseError::TooManyNumbers),
-        };
+        let (name, name_span) = name?;
+        let class = Definition { name: String::from_utf8_lossy(&name).into_owned(), name_pos: name_span.start, start: class_pos, end: self.reader.pos };
+        self.outline.push(ClassOutline { class, methods });
 
-        self.add_opcode(OpCode::PushNumber, pos);
-        self.instructions.push((number_index >> 8) as u8);
-        self.instructions.push((number_index & 0xFF) as u8);
+        if !ended {
+            return None;
+        }
 
-        Ok(())
+        Some(ast::Class { name, name_span, items, span: ast::Span { start: class_pos, end: self.reader.pos } })
     }
 
-    fn add_push_self(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::PushSelf, pos);
-    }
+    fn parse_method(&mut self, methods: &mut Vec<Definition>) -> Result<ast::Method, ParseError> {
+        let func_pos = match self.reader.next() {
+            Some((b'[', pos)) => pos,
+            _ => unreachable!(),
+        };
 
-    fn add_push_string(&mut self, string: ByteString, pos: FilePosition) -> Result<(), ParseError> {
-        let string_index = match self.get_string_index(string) {
-            Some(string_index) => string_index,
-            None => return Err(ParseError::TooManyMembers),
+        let (name, name_span) = match parse_name(&mut self.reader) {
+            Ok(name) => name,
+            Err(pos) => return Err(self.error(ParseErrorKind::MissingFuncName, pos)),
         };
 
-        self.add_opcode(OpCode::PushString, pos);
-        self.instructions.push((string_index >> 8) as u8);
-        self.instructions.push((string_index & 0xFF) as u8);
+        methods.push(Definition { name: String::from_utf8_lossy(&name).into_owned(), name_pos: name_span.start, start: func_pos, end: self.reader.pos });
 
-        Ok(())
+        let body = self.parse_statements(CodeContext::FunctionBody, func_pos, None)?;
+        Ok(ast::Method { name, name_span, body, span: ast::Span { start: func_pos, end: self.reader.pos } })
     }
 
-    fn add_return(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Return, pos);
-    }
+    // Parses the commands in a function up to and including its closing bracket, or in a loop
+    // up to and including its backslash. A snippet is a function body on its own, which
+    // instead ends at the end of the code.
+    fn parse_statements(&mut self, context: CodeContext, func_pos: FilePosition, loop_pos: Option<FilePosition>) -> Result<Vec<ast::Statement>, ParseError> {
+        let snippet = context == CodeContext::Snippet;
+        let mut statements = Vec::new();
 
-    fn add_store(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::Store, pos);
-    }
+        while skip_whitespace(&mut self.reader) {
+            statements.extend(self.take_comments().map(|comment| ast::Statement { kind: ast::StatementKind::Comment(comment.text), span: comment.span }));
 
-    fn add_store_keep(&mut self, pos: FilePosition) {
-        self.add_opcode(OpCode::StoreKeep, pos);
-    }
+            // These can't appear in a function, so the function must be missing its end
+            if let Some(c @ (b'[' | b'{' | b'}')) = self.reader.peek() {
+                if snippet {
+                    return Err(self.error(ParseErrorKind::InvalidChar(c, context), self.reader.pos));
+                }
+                break;
+            }
 
-    fn get_program(self) -> Result<BytecodeProgram, ParseError> {
-        let mut class_names = Vec::new();
-        let mut classes = Vec::new();
+            let (c, pos) = match self.reader.next() {
+                Some(next) => next,
+                None => unreachable!(),
+            };
+
+            let kind = match c {
+                b',' => ast::StatementKind::Pop,
+                b'^' => ast::StatementKind::Return,
+                b'*' => ast::StatementKind::Load,
+                b'=' => ast::StatementKind::Store,
+                b'?' => ast::StatementKind::Call,
+                b'.' => ast::StatementKind::LoadFrom,
+                b'$' => ast::StatementKind::StoreSelf,
+                b'!' => ast::StatementKind::Instantiate,
+                c if c.is_ascii_alphabetic() => ast::StatementKind::PushName(ByteString::new(vec![c])),
+                c if c.is_ascii_digit() => ast::StatementKind::Duplicate(c - b'0'),
+                b'/' => {
+                    let (name, name_span) = match parse_name(&mut self.reader) {
+                        Ok(name) => name,
+                        Err(name_pos) => return Err(self.error(ParseErrorKind::MissingLoopName, name_pos)),
+                    };
+                    let name_comments = self.take_comments().collect();
+
+                    let body = self.parse_statements(context, func_pos, Some(pos))?;
+                    ast::StatementKind::Loop(ast::Loop { name, name_span, name_comments, body })
+                },
+                b'\\' => match loop_pos {
+                    Some(_) => return Ok(statements),
+                    None => return Err(self.error(ParseErrorKind::InvalidChar(c, context), pos)),
+                },
+                b'(' => self.parse_parentheses(pos)?,
+                b'"' => self.parse_string(pos)?,
+                b'<' => self.parse_number(pos)?,
+                b']' if !snippet => {
+                    if let Some(loop_pos) = loop_pos {
+                        return Err(self.error(ParseErrorKind::UnendedLoop, loop_pos));
+                    }
 
-        for (name, class) in self.classes {
-            class_names.push(name);
-            classes.push(class);
+                    return Ok(statements);
+                },
+                c => return Err(self.error(ParseErrorKind::InvalidChar(c, context), pos)),
+            };
+
+            statements.push(ast::Statement { kind, span: ast::Span { start: pos, end: self.reader.pos } });
         }
 
-        let mut strings = std::vec::from_elem(ByteString::new(vec![]), self.strings.len());
-        for (string, index) in self.strings {
-            strings[index as usize] = string;
+        if !snippet {
+            return Err(self.error(ParseErrorKind::UnendedFunc, func_pos));
         }
 
-        let main_class_name = match self.global_names.get(MAIN_CLASS_NAME) {
-            Some(name) => *name,
-            None => return Err(ParseError::MissingMainClass),
-        };
+        if let Some(loop_pos) = loop_pos {
+            return Err(self.error(ParseErrorKind::UnendedLoop, loop_pos));
+        }
 
-        let main_func_name = match self.member_names.get(MAIN_FUNC_NAME) {
-            Some(name) => *name,
-            // This should be unreachable...
-            None => return Err(ParseError::MissingMainFunc),
-        };
+        statements.extend(self.take_comments().map(|comment| ast::Statement { kind: ast::StatementKind::Comment(comment.text), span: comment.span }));
+        Ok(statements)
+    }
 
-        Ok(BytecodeProgram {
-            class_names: class_names,
-            classes: classes,
-            strings: strings,
-            numbers: self.numbers,
-            instructions: self.instructions,
-            main_class: main_class_name,
-            main_func: main_func_name,
-            files: self.files,
-            positions: self.positions,
-        })
-    }
-}
-
-fn add_builtin_classes(gen: &mut BytecodeGenerator) {
-    gen.set_filename("<builtin>".to_owned());
-    let builtin_pos = FilePosition { line: 0, col: 0 };
-
-    // Arithmetic class
-    let mut math = ClassDefinition::new();
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'a']));
-    gen.add_opcode(OpCode::Add, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'd']));
-    gen.add_opcode(OpCode::Divide, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'e']));
-    gen.add_opcode(OpCode::Equal, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'f']));
-    gen.add_opcode(OpCode::Floor, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'g', b'e']));
-    gen.add_opcode(OpCode::GreaterEqual, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'g', b't']));
-    gen.add_opcode(OpCode::GreaterThan, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'l', b'e']));
-    gen.add_opcode(OpCode::LessEqual, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'l', b't']));
-    gen.add_opcode(OpCode::LessThan, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'm']));
-    gen.add_opcode(OpCode::Multiply, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'm', b'o', b'd']));
-    gen.add_opcode(OpCode::Modulo, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b'n', b'e']));
-    gen.add_opcode(OpCode::NotEqual, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut math, ByteString::new(vec![b's']));
-    gen.add_opcode(OpCode::Subtract, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_class(math, ByteString::new(vec![b'A']));
-
-    // Input class
-    let mut input = ClassDefinition::new();
-    let _ = gen.add_func(&mut input, ByteString::new(vec![b'c']));
-    gen.add_opcode(OpCode::InputChar, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut input, ByteString::new(vec![b'e']));
-    gen.add_opcode(OpCode::InputEof, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut input, ByteString::new(vec![b'l']));
-    gen.add_opcode(OpCode::InputLine, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_class(input, ByteString(vec![b'I']));
-
-    // Output class
-    let mut output = ClassDefinition::new();
-    let _ = gen.add_func(&mut output, ByteString::new(vec![b'o']));
-    gen.add_opcode(OpCode::OutputString, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut output, ByteString::new(vec![b'o', b'n']));
-    gen.add_opcode(OpCode::OutputNumber, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_class(output, ByteString::new(vec![b'O']));
-
-    // String class
-    let mut string = ClassDefinition::new();
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'a']));
-    gen.add_opcode(OpCode::Concat, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'd']));
-    gen.add_opcode(OpCode::StringSplit, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'e']));
-    gen.add_opcode(OpCode::StringEqual, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'i']));
-    gen.add_opcode(OpCode::Index, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'l']));
-    gen.add_opcode(OpCode::Length, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b'n', b's']));
-    gen.add_opcode(OpCode::NumToString, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b's', b'i']));
-    gen.add_opcode(OpCode::StringReplace, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut string, ByteString::new(vec![b's', b'n']));
-    gen.add_opcode(OpCode::StringToNum, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_class(string, ByteString::new(vec![b'S']));
-
-    // Variable class
-    let mut vars = ClassDefinition::new();
-    let _ = gen.add_func(&mut vars, ByteString::new(vec![b'd']));
-    gen.add_opcode(OpCode::VarDelete, builtin_pos);
-    gen.add_return(builtin_pos);
-    let _ = gen.add_func(&mut vars, ByteString::new(vec![b'n']));
-    gen.add_opcode(OpCode::VarNew, builtin_pos);
-    gen.add_return(builtin_pos);
-
-    let _ = gen.add_class(vars, ByteString::new(vec![b'V']));
-}
+    // Parses a name or stack index in parentheses, after the opening parenthesis
+    fn parse_parentheses(&mut self, pos: FilePosition) -> Result<ast::StatementKind, ParseError> {
+        let mut name = ByteString::new(vec![]);
+        loop {
+            match self.reader.peek() {
+                Some(b')') => {
+                    self.reader.next();
+                    if valid_name(&name) {
+                        return Ok(ast::StatementKind::PushName(name));
+                    }
 
-fn skip_whitespace(reader: &mut CodeReader) -> bool {
-    while let Some(c) = reader.peek() {
-        if c == b'\'' {
-            reader.next();
-            loop {
-                match reader.next() {
-                    Some((b'\'', _)) => break,
-                    Some(_) => {},
-                    None => return false,
+                    return match get_integer(&name) {
+                        Ok(index) => Ok(ast::StatementKind::Duplicate(index)),
+                        Err(kind) => Err(self.error(kind, pos)),
+                    };
                 }
+                Some(c) if !ends_unclosed_group(c) => {
+                    self.reader.next();
+                    name.push(c);
+                },
+                end => return Err(self.error_with_related(ParseErrorKind::UnendedParentheses(end), self.reader.pos, pos)),
             }
-            continue;
         }
-        else if !c.is_ascii_whitespace() {
-            return true;
+    }
+
+    // Parses a string after its opening quote
+    fn parse_string(&mut self, pos: FilePosition) -> Result<ast::StatementKind, ParseError> {
+        let mut value = ByteString::new(vec![]);
+        let mut text = ByteString::new(vec![]);
+        loop {
+            match self.reader.next() {
+                Some((b'"', _)) => return Ok(ast::StatementKind::PushString { value, text }),
+                Some((b'\\', _)) => {
+                    text.push(b'\\');
+                    match self.reader.next() {
+                        Some((c, _)) => {
+                            text.push(c);
+                            value.push(if c == b'n' { b'\n' } else { c });
+                        },
+                        None => return Err(self.error_with_related(ParseErrorKind::UnendedString, self.reader.pos, pos)),
+                    }
+                }
+                Some((c, _)) => {
+                    text.push(c);
+                    value.push(c);
+                },
+                None => return Err(self.error_with_related(ParseErrorKind::UnendedString, self.reader.pos, pos)),
+            }
         }
+    }
 
-        reader.next();
+    // Parses a number after its opening angle bracket
+    fn parse_number(&mut self, pos: FilePosition) -> Result<ast::StatementKind, ParseError> {
+        let mut text = String::new();
+        loop {
+            match self.reader.peek() {
+                Some(b'>') => {
+                    self.reader.next();
+                    return match f64::from_str(&text) {
+                        Ok(value) => Ok(ast::StatementKind::PushNumber { value, text }),
+                        Err(_) => Err(self.error(ParseErrorKind::InvalidNumber, pos)),
+                    };
+                },
+                Some(c) if !ends_unclosed_group(c) => {
+                    self.reader.next();
+                    text.push(c as char);
+                },
+                end => return Err(self.error_with_related(ParseErrorKind::UnendedNumber(end), self.reader.pos, pos)),
+            }
+        }
     }
+}
 
-    false
+fn parse_file(gen: &mut BytecodeGenerator, filename: &str, code: &[u8], errors: &mut Vec<ParseError>) {
+    let mut parser = FileParser::new(filename, code);
+    let tree = parser.parse_file();
+
+    errors.append(&mut parser.errors);
+    gen.outline.append(&mut parser.outline);
+
+    lower_file(gen, &tree, errors);
 }
 
-fn valid_name(name: &ByteString) -> bool {
-    if name.len() == 0 {
-        return false;
-    }
+// Parses a file into a syntax tree without generating any bytecode for it
+pub fn parse_file_ast(filename: &str, code: &[u8]) -> Result<ast::File, Vec<ParseError>> {
+    let mut parser = FileParser::new(filename, code);
+    let tree = parser.parse_file();
 
-    if name[0].is_ascii_digit() {
-        return false;
-    }
+    if parser.errors.is_empty() { Ok(tree) } else { Err(parser.errors) }
+}
 
-    for c in name {
-        if *c != b'_' && !c.is_ascii_alphanumeric() {
-            return false;
+// Parses the files of a program into a syntax tree, which crate::lower::lower_program can
+// then turn into bytecode
+pub fn parse_ast(files: &[(String, Vec<u8>)]) -> Result<ast::Program, Vec<ParseError>> {
+    let mut program = ast::Program { files: Vec::new() };
+    let mut errors = Vec::new();
+
+    for (filename, code) in files {
+        match parse_file_ast(filename, code) {
+            Ok(file) => program.files.push(file),
+            Err(mut file_errors) => errors.append(&mut file_errors),
         }
     }
 
-    true
+    if errors.is_empty() { Ok(program) } else { Err(errors) }
 }
 
-fn get_integer(int_str: &ByteString) -> Result<u8, ParseError> {
-    if int_str.len() == 0 {
-        return Err(ParseError::InvalidNumber)
-    }
+// Finds the line of source code a location refers to, if it came from one of the given files
+fn get_source_line<'a>(files: &'a [(String, Vec<u8>)], location: &SourceLocation) -> Option<&'a [u8]> {
+    let (_, code) = files.iter().find(|(filename, _)| *filename == location.file)?;
 
-    let mut integer: usize = 0;
+    code.split(|c| *c == b'\n').nth(location.pos.line.checked_sub(1)?)
+}
 
-    for c in int_str {
-        if !c.is_ascii_digit() {
-            println!("{}", c);
-            return Err(ParseError::InvalidInteger);
-        }
-        integer = (integer * 10) + (*c as usize - '0' as usize);
-        if integer > (u8::MAX as usize) {
-            return Err(ParseError::IndexTooBig);
-        }
-    }
+// Shows the line of code a location is on, with a caret under the character it points at
+fn format_source_line(files: &[(String, Vec<u8>)], location: &SourceLocation) -> String {
+    let line = match get_source_line(files, location) {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => return String::new(),
+    };
 
-    Ok(integer as u8)
+    // Keep any tabs before the error, so the caret lines up with the offending character
+    let caret_indent: String = line.iter()
+        .take(location.pos.col.saturating_sub(1))
+        .map(|c| if *c == b'\t' { '\t' } else { ' ' })
+        .collect();
+
+    format!("{}\n{}^\n", String::from_utf8_lossy(line), caret_indent)
 }
 
-fn parse_name(reader: &mut CodeReader) -> Option<ByteString> {
-    if !skip_whitespace(reader) {
-        return None;
+// Describes an error along with the line of code it's on and a caret pointing at where
+// on the line it is, followed by a note for any other place the error refers to
+pub fn format_parse_error(files: &[(String, Vec<u8>)], err: &ParseError) -> String {
+    let mut message = format!("{}\n", err);
+
+    if let Some(location) = &err.location {
+        message.push_str(&format_source_line(files, location));
     }
 
-    match reader.peek() {
-        Some(c) if c.is_ascii_alphabetic() => {
-            match reader.next() {
-                Some((c, _)) => Some(ByteString::new(vec![c])),
-                _ => unreachable!(),
-            }
-        },
-        Some(b'(') => {
-            reader.next();
-            let mut name = ByteString::new(vec![]);
-            loop {
-                match reader.next() {
-                    Some((b')', _)) => {
-                        if valid_name(&name) {
-                            return Some(name);
-                        }
-                        else {
-                            return None;
-                        }
-                    }
-                    Some((c, _)) => name.push(c),
-                    None => return None,
-                }
-            }
-        },
-        _ => None,
+    if let Some(related) = &err.related {
+        message.push_str(&format!("{}: note: {}\n", related, err.kind.related_note()));
+        message.push_str(&format_source_line(files, related));
     }
-}
 
-fn parse_function(reader: &mut CodeReader, class: &mut ClassDefinition, gen: &mut BytecodeGenerator) -> Result<(), ParseError> {
-    assert!(match reader.next() { Some((b'[', _)) => true, _ => false });
+    message
+}
 
-    let name = match parse_name(reader) {
-        Some(name) => name,
-        None => return Err(ParseError::MissingFuncName),
-    };
+// Parses a file on its own, finding the classes and methods it defines and the names it uses.
+// Errors that depend on the rest of the program, like a missing main class, aren't reported.
+pub fn outline_file(filename: &str, code: &[u8]) -> FileOutline {
+    let mut gen = BytecodeGenerator::new();
+    let mut errors = Vec::new();
 
-    gen.add_func(class, name)?;
-
-    let mut loop_stack = Vec::new();
-
-    while skip_whitespace(reader) {
-        match reader.next() {
-            Some((b',', pos)) => gen.add_pop(pos),
-            Some((b'^', pos)) => gen.add_return(pos),
-            Some((b'*', pos)) => gen.add_load(pos),
-            Some((b'=', pos)) => gen.add_store(pos),
-            Some((b'?', pos)) => gen.add_call(pos),
-            Some((b'.', pos)) => gen.add_load_from(pos),
-            Some((c, pos)) if c.is_ascii_lowercase() => gen.add_push_member(ByteString::new(vec![c]), pos)?,
-            Some((c, pos)) if c.is_ascii_uppercase() => gen.add_push_global(ByteString::new(vec![c]), pos)?,
-            Some((c, pos)) if c.is_ascii_digit() => gen.add_duplicate(c - ('0' as u8), pos),
-            Some((b'$', pos)) => {
-                gen.add_push_self(pos);
-                gen.add_store(pos);
-            },
-            Some((b'!', pos)) => {
-                gen.add_load(pos);
-                gen.add_instantiate(pos);
-                gen.add_store_keep(pos);
-                gen.add_construct(pos);
-            },
-            Some((b'/', pos)) => {
-                let loop_name = match parse_name(reader) {
-                    Some(name) => name,
-                    None => return Err(ParseError::MissingLoopName),
-                };
-
-                gen.add_push_name(loop_name.clone(), pos)?;
-                gen.add_load(pos);
-                loop_stack.push((loop_name, gen.add_jump_if_not(pos)))
-            },
-            Some((b'\\', pos)) => {
-                match loop_stack.pop() {
-                    None => return Err(ParseError::InvalidChar),
-                    Some((loop_name, loop_start)) => {
-                        gen.add_push_name(loop_name, pos)?;
-                        gen.add_load(pos);
-                        gen.add_jump_if(loop_start, pos)?;
-                    }
-                }
-            },
-            Some((b'(', pos)) => {
-                let mut name = ByteString::new(vec![]);
-                loop {
-                    match reader.next() {
-                        Some((b')', _)) => {
-                            if valid_name(&name) {
-                                gen.add_push_name(name, pos)?;
-                                break;
-                            }
-                            else {
-                                gen.add_duplicate(get_integer(&name)?, pos);
-                                break;
-                            }
-                        }
-                        Some((c, _)) => name.push(c),
-                        None => return Err(ParseError::UnendedParentheses),
-                    }
-                }
-            },
-            Some((b'"', pos)) => {
-                let mut string = ByteString::new(vec![]);
-                loop {
-                    match reader.next() {
-                        Some((b'"', _)) => {
-                            gen.add_push_string(string, pos)?;
-                            break;
-                        }
-                        Some((b'\\', _)) => {
-                            match reader.next() {
-                                Some((b'n', _)) => string.push(b'\n'),
-                                Some((c, _)) => string.push(c),
-                                None => return Err(ParseError::UnendedString),
-                            }
-                        }
-                        Some((c, _)) => string.push(c),
-                        None => return Err(ParseError::UnendedString),
-                    }
-                }
-            },
-            Some((b'<', pos)) => {
-                let mut num_str = String::new();
-                loop {
-                    match reader.next() {
-                        Some((b'>', _)) => {
-                            let number = match f64::from_str(&num_str) {
-                                Ok(num) => num,
-                                Err(_) => return Err(ParseError::InvalidNumber),
-                            };
-
-                            gen.add_push_number(number, pos)?;
-                            break;
-                        },
-                        Some((c, _)) => num_str.push(c as char),
-                        None => return Err(ParseError::UnendedNumber),
-                    }
-                }
-            },
-            Some((b']', pos)) => {
-                if !loop_stack.is_empty() {
-                    return Err(ParseError::UnendedLoop);
-                }
+    add_builtin_classes(&mut gen, &NativeClasses::standard()).expect("the standard classes don't clash");
+    parse_file(&mut gen, filename, code, &mut errors);
 
-                gen.add_return(pos);
-                return Ok(());
-            },
-            Some(_) => return Err(ParseError::InvalidChar),
-            None => unreachable!(),
-        }
-    }
+    FileOutline { classes: gen.outline, references: gen.references, errors }
+}
 
-    Err(ParseError::UnendedFunc)
+pub fn parse_program(files: &[(String, Vec<u8>)]) -> Result<BytecodeProgram, Vec<ParseError>> {
+    parse_program_with_natives(files, &NativeClasses::standard())
 }
 
-fn parse_class(reader: &mut CodeReader, gen: &mut BytecodeGenerator) -> Result<(), ParseError> {
-    assert!(match reader.next() { Some((b'{', _)) => true, _ => false });
+// Parses a program that can use the given native classes as well as the builtin V class
+pub fn parse_program_with_natives(files: &[(String, Vec<u8>)], natives: &NativeClasses) -> Result<BytecodeProgram, Vec<ParseError>> {
+    let mut gen = BytecodeGenerator::new();
+    let mut errors = Vec::new();
 
-    let name = match parse_name(reader) {
-        Some(name) => name,
-        None => return Err(ParseError::MissingClassName),
-    };
+    add_builtin_classes(&mut gen, natives).map_err(|err| vec![err])?;
 
-    let mut class = ClassDefinition::new();
+    for (filename, code) in files {
+        parse_file(&mut gen, filename, code, &mut errors);
+    }
 
-    while skip_whitespace(reader) {
-        match reader.peek() {
-            Some(b'[') => {
-                parse_function(reader, &mut class, gen)?;
-            },
-            Some(b'}') => {
-                reader.next();
-                return gen.add_class(class, name);
-            },
-            Some(_) => {
-                return Err(ParseError::InvalidChar);
-            },
-            None => unreachable!(),
-        }
+    if !errors.is_empty() {
+        return Err(errors);
     }
 
-    Err(ParseError::UnendedClass)
+    gen.get_program().map_err(|err| vec![err])
 }
 
-pub fn parse_program(files: &Vec<(String, Vec<u8>)>) -> Result<BytecodeProgram, ParseError> {
-    let mut gen = BytecodeGenerator::new();
+// Adds the classes defined in some more code to an existing program. The program is left
+// as it was if there are any errors.
+pub fn parse_classes_into(program: &mut BytecodeProgram, filename: &str, code: &[u8]) -> Result<(), Vec<ParseError>> {
+    let mut gen = BytecodeGenerator::from_program(program);
+    let mut errors = Vec::new();
 
-    add_builtin_classes(&mut gen);
+    parse_file(&mut gen, filename, code, &mut errors);
 
-    for (filename, code) in files {
-        let mut reader = CodeReader::new(code);
-        gen.set_filename(filename.clone());
-
-        while skip_whitespace(&mut reader) {
-            match reader.peek() {
-                Some(b'{') => parse_class(&mut reader, &mut gen)?,
-                _ => return Err(ParseError::InvalidChar),
-            }
-        }
+    if !errors.is_empty() {
+        return Err(errors);
     }
 
-    gen.get_program()
+    *program = gen.get_program().map_err(|err| vec![err])?;
+    Ok(())
+}
+
+// Compiles a function body on its own and adds it to an existing program, without making
+// it part of any class. Returns the index it starts at.
+pub fn parse_snippet_into(program: &mut BytecodeProgram, filename: &str, code: &[u8]) -> Result<OpcodeIndex, Vec<ParseError>> {
+    let mut gen = BytecodeGenerator::from_program(program);
+    let mut parser = FileParser::new(filename, code);
+
+    gen.set_filename(filename.to_owned());
+    let start = gen.instructions.len();
+
+    let start_pos = parser.reader.pos;
+    let statements = parser.parse_statements(CodeContext::Snippet, start_pos, None).map_err(|err| vec![err])?;
+    lower_statements(&mut gen, &statements).map_err(|err| vec![err])?;
+    gen.add_return(parser.reader.pos);
+
+    *program = gen.get_program().map_err(|err| vec![err])?;
+    Ok(start)
 }
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::bytecode::*;
+use crate::hook::{ExecutionHook, ExecutionState};
+use crate::interpreter::RuntimeError;
+
+#[derive(Clone, Copy, Default)]
+struct FunctionStats {
+    calls: u64,
+
+    // Instructions run in the function itself, not counting the functions it calls
+    instructions: u64,
+
+    self_time: Duration,
+
+    // Time from being called to returning, counted once for recursive calls
+    total_time: Duration,
+}
+
+// Counts the calls, instructions and time spent in each function. Calls and returns are
+// noticed from the call depth changing between instructions, so nothing extra is needed
+// from the interpreter.
+pub struct Profiler {
+    // Each function's first instruction and the function it starts
+    function_starts: HashMap<OpcodeIndex, usize>,
+
+    names: Vec<String>,
+
+    stats: Vec<FunctionStats>,
+
+    // The functions currently being run and when they were called, innermost last
+    frames: Vec<(usize, Instant)>,
+
+    // How many times each function is on the call stack
+    active_calls: Vec<usize>,
+
+    // The instructions run in each distinct call stack, for flame graphs
+    stacks: HashMap<Vec<usize>, u64>,
+
+    // Instructions and time since the innermost function last changed
+    pending_instructions: u64,
+
+    last_switch: Instant,
+}
+
+impl Profiler {
+    pub fn new(program: &BytecodeProgram) -> Profiler {
+        let mut profiler = Profiler {
+            function_starts: HashMap::new(),
+            names: Vec::new(),
+            stats: Vec::new(),
+            frames: Vec::new(),
+            active_calls: Vec::new(),
+            stacks: HashMap::new(),
+            pending_instructions: 0,
+            last_switch: Instant::now(),
+        };
+
+        for (class_name, class) in program.class_names.iter().zip(program.classes.iter()) {
+            for (func_name, func_index) in class.funcs.iter() {
+                let name = format!("{}.{}", program.global_name_str(*class_name), program.member_name_str(*func_name));
+                profiler.function_starts.insert(*func_index, profiler.names.len());
+                profiler.names.push(name);
+            }
+        }
+
+        profiler.stats = vec![FunctionStats::default(); profiler.names.len()];
+        profiler.active_calls = vec![0; profiler.names.len()];
+        profiler
+    }
+
+    // Finds the function starting at an instruction, adding one for code that isn't part
+    // of any class
+    fn function_id(&mut self, program: &BytecodeProgram, index: OpcodeIndex) -> usize {
+        if let Some(id) = self.function_starts.get(&index) {
+            return *id;
+        }
+
+        let id = self.names.len();
+        self.function_starts.insert(index, id);
+        self.names.push(program.function_name(index).unwrap_or_else(|| "<unknown>".to_owned()));
+        self.stats.push(FunctionStats::default());
+        self.active_calls.push(0);
+        id
+    }
+
+    // Charges what's been run since the last change to the innermost function
+    fn switch(&mut self, now: Instant) {
+        if let Some((id, _)) = self.frames.last() {
+            let stats = &mut self.stats[*id];
+            stats.instructions += self.pending_instructions;
+            stats.self_time += now - self.last_switch;
+
+            if self.pending_instructions > 0 {
+                let stack: Vec<_> = self.frames.iter().map(|(id, _)| *id).collect();
+                *self.stacks.entry(stack).or_insert(0) += self.pending_instructions;
+            }
+        }
+
+        self.pending_instructions = 0;
+        self.last_switch = now;
+    }
+
+    fn enter(&mut self, id: usize, now: Instant) {
+        self.switch(now);
+        self.frames.push((id, now));
+        self.stats[id].calls += 1;
+        self.active_calls[id] += 1;
+    }
+
+    fn leave(&mut self, now: Instant) {
+        self.switch(now);
+        if let Some((id, called_at)) = self.frames.pop() {
+            self.active_calls[id] -= 1;
+            if self.active_calls[id] == 0 {
+                self.stats[id].total_time += now - called_at;
+            }
+        }
+    }
+
+    // Ends any calls that are still going, like the main function when the program has
+    // finished
+    fn finish(&mut self) {
+        let now = Instant::now();
+        while !self.frames.is_empty() {
+            self.leave(now);
+        }
+    }
+
+    // Writes a table of the functions that were called, with the most instructions first
+    pub fn write_report(&mut self, output: &mut impl Write) -> std::io::Result<()> {
+        self.finish();
+
+        let mut called: Vec<_> = (0..self.names.len()).filter(|id| self.stats[*id].calls > 0).collect();
+        called.sort_by(|a, b| self.stats[*b].instructions.cmp(&self.stats[*a].instructions).then(self.names[*a].cmp(&self.names[*b])));
+
+        let name_width = called.iter().map(|id| self.names[*id].len()).max().unwrap_or(0).max("Function".len());
+        let total_instructions: u64 = self.stats.iter().map(|stats| stats.instructions).sum();
+
+        writeln!(output, "{:<name_width$}  {:>10}  {:>14}  {:>7}  {:>12}  {:>12}",
+            "Function", "Calls", "Instructions", "%", "Self ms", "Total ms")?;
+
+        for id in called {
+            let stats = &self.stats[id];
+            let percent = if total_instructions == 0 { 0.0 } else { stats.instructions as f64 * 100.0 / total_instructions as f64 };
+
+            writeln!(output, "{:<name_width$}  {:>10}  {:>14}  {:>6.2}%  {:>12.3}  {:>12.3}",
+                self.names[id], stats.calls, stats.instructions, percent,
+                stats.self_time.as_secs_f64() * 1000.0, stats.total_time.as_secs_f64() * 1000.0)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the instructions run in each call stack in the collapsed format flame graph
+    // tools take, like "M.m;C.c;A.a 12"
+    pub fn write_collapsed_stacks(&mut self, output: &mut impl Write) -> std::io::Result<()> {
+        self.finish();
+
+        let mut lines: Vec<_> = self.stacks.iter().map(|(stack, instructions)| {
+            let names: Vec<_> = stack.iter().map(|id| self.names[*id].as_str()).collect();
+            (names.join(";"), *instructions)
+        }).collect();
+        lines.sort();
+
+        for (stack, instructions) in lines {
+            writeln!(output, "{} {}", stack, instructions)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ExecutionHook for Profiler {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        let depth = state.call_depth();
+
+        // A call is always to the start of a function, and only goes one level deeper
+        if self.frames.len() <= depth {
+            let id = self.function_id(state.program(), state.opcode_index());
+            self.enter(id, Instant::now());
+        }
+        else if self.frames.len() > depth + 1 {
+            let now = Instant::now();
+            while self.frames.len() > depth + 1 {
+                self.leave(now);
+            }
+        }
+
+        self.pending_instructions += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Read, Write};
+
+use crate::interpreter::{EvalError, Interpreter, Limits};
+use crate::parser::{format_parse_error, parse_program, ParseError};
+
+const PROMPT: &[u8] = b"glass> ";
+
+const CONTINUATION_PROMPT: &[u8] = b"  ...> ";
+
+// The program the REPL starts with, which gives snippets an M instance to run on
+const STARTING_CODE: &[u8] = b"{M[m]}";
+
+// Whether some code has closed every class, function, loop, string and comment it opened,
+// so that it can be parsed as a whole
+fn is_complete(code: &[u8]) -> bool {
+    let mut depth = 0;
+    let mut chars = code.iter();
+
+    while let Some(c) = chars.next() {
+        match c {
+            b'{' | b'[' | b'/' => depth += 1,
+            b'}' | b']' | b'\\' => depth -= 1,
+            b'"' => loop {
+                match chars.next() {
+                    Some(b'"') => break,
+                    Some(b'\\') => { chars.next(); },
+                    Some(_) => {},
+                    None => return false,
+                }
+            },
+            b'\'' => loop {
+                match chars.next() {
+                    Some(b'\'') => break,
+                    Some(_) => {},
+                    None => return false,
+                }
+            },
+            _ => {},
+        }
+    }
+
+    depth <= 0
+}
+
+fn write_parse_errors(error_output: &mut dyn Write, entries: &[(String, Vec<u8>)], errors: &[ParseError]) -> io::Result<()> {
+    for err in errors {
+        write!(error_output, "{}", format_parse_error(entries, err))?;
+    }
+    Ok(())
+}
+
+// Reads entries and runs them until the input ends. An entry starting with { defines
+// classes, and anything else is run as a function body on an instance of M. Errors are
+// reported without losing any classes, members or values on the stack.
+pub fn run_repl<'a>(input: impl Read + 'a, output: impl Write + 'a, error_output: impl Write + 'a, limits: Limits) -> io::Result<()> {
+    let mut entries = vec![("<repl>".to_owned(), STARTING_CODE.to_vec())];
+    let program = parse_program(&entries).expect("REPL starting code doesn't parse");
+
+    let mut interpreter = Interpreter::new(program)
+        .input(input)
+        .output(output)
+        .error_output(error_output)
+        .limits(limits);
+    let main = interpreter.instantiate("M").expect("M has no constructor to fail");
+
+    let mut at_end = false;
+
+    while !at_end {
+        let mut code = Vec::new();
+
+        // Keep reading lines while something is left open
+        loop {
+            let (input, output, _) = interpreter.streams();
+            output.write_all(if code.is_empty() { PROMPT } else { CONTINUATION_PROMPT })?;
+            output.flush()?;
+
+            // At the end of the input, run whatever's left before stopping
+            if input.read_until(b'\n', &mut code)? == 0 || !code.ends_with(b"\n") {
+                output.write_all(b"\n")?;
+                output.flush()?;
+                at_end = true;
+                break;
+            }
+
+            if is_complete(&code) {
+                break;
+            }
+        }
+
+        let trimmed = code.trim_ascii();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let filename = format!("<repl {}>", entries.len());
+        let is_class = trimmed.starts_with(b"{");
+        entries.push((filename.clone(), code.clone()));
+
+        if is_class {
+            if let Err(errors) = interpreter.load_classes(&filename, &code) {
+                write_parse_errors(interpreter.streams().2, &entries, &errors)?;
+            }
+            continue;
+        }
+
+        let result = interpreter.eval(main, &filename, &code);
+        match result {
+            Ok(()) => {},
+            Err(EvalError::Parse(errors)) => {
+                write_parse_errors(interpreter.streams().2, &entries, &errors)?;
+                continue;
+            },
+            Err(EvalError::Execution(err)) => {
+                let traceback = err.traceback(interpreter.program());
+                let error_output = interpreter.streams().2;
+
+                writeln!(error_output, "Error: {}", err)?;
+                writeln!(error_output, "Traceback:")?;
+                for trace_line in traceback {
+                    writeln!(error_output, "  {}", trace_line)?;
+                }
+            },
+        }
+
+        let stack = interpreter.describe_stack();
+        let output = interpreter.streams().1;
+        if stack.is_empty() {
+            writeln!(output, "Stack: empty")?;
+        }
+        else {
+            writeln!(output, "Stack: {}", stack.join(", "))?;
+        }
+    }
+
+    interpreter.streams().1.flush()
+}
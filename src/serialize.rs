@@ -0,0 +1,362 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use byte_string::ByteString;
+
+use crate::bytecode::*;
+use crate::json::JsonValue;
+use crate::verifier::{verify, VerifyError};
+
+pub const MAGIC: &[u8; 6] = b"GLASSC";
+
+pub const FORMAT_VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum LoadError {
+    BadChecksum,
+    BadMagic,
+    CombinedWithFiles,
+    Corrupt(&'static str),
+    IOError(std::io::Error),
+    Invalid(VerifyError),
+    Truncated,
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::BadChecksum => write!(f, "compiled program is corrupted (checksum mismatch)"),
+            LoadError::BadMagic => write!(f, "not a compiled Glass program"),
+            LoadError::CombinedWithFiles => write!(f, "a compiled program can't be combined with other files"),
+            LoadError::Corrupt(reason) => write!(f, "compiled program is corrupted ({})", reason),
+            LoadError::IOError(err) => write!(f, "I/O error: {}", err),
+            LoadError::Invalid(err) => write!(f, "{}", err),
+            LoadError::Truncated => write!(f, "compiled program is truncated"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "compiled program has format version {}, but only version {} is supported", version, FORMAT_VERSION)
+            },
+        }
+    }
+}
+
+impl LoadError {
+    // The file is left out for errors that aren't about a single file
+    pub fn to_json(&self, filename: Option<&str>) -> JsonValue {
+        JsonValue::object(vec![
+            ("type", JsonValue::string("load_error")),
+            ("message", JsonValue::string(self.to_string())),
+            ("file", filename.map(JsonValue::string).into()),
+        ])
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::IOError(err)
+    }
+}
+
+// 32-bit FNV-1a, used to detect corrupted files
+fn checksum(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in data {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+// Returns whether some data looks like it's the start of a compiled program
+pub fn is_compiled_program(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn to_u32(value: usize) -> std::io::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} is too large to store in a compiled program", value))
+    })
+}
+
+struct ByteWriter {
+    data: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    // Lengths, counts and indices are all written as 32 bits, so anything bigger can't be stored
+    fn u32(&mut self, value: usize) -> std::io::Result<()> {
+        self.data.extend_from_slice(&to_u32(value)?.to_be_bytes());
+        Ok(())
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.data.extend_from_slice(&value.to_bits().to_be_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> std::io::Result<()> {
+        self.u32(value.len())?;
+        self.data.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn string_table(&mut self, table: &[ByteString]) -> std::io::Result<()> {
+        self.u32(table.len())?;
+        for string in table {
+            self.bytes(string)?;
+        }
+        Ok(())
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, amount: usize) -> Result<&'a [u8], LoadError> {
+        if self.data.len() - self.pos < amount {
+            return Err(LoadError::Truncated);
+        }
+
+        let bytes = &self.data[self.pos..self.pos + amount];
+        self.pos += amount;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn f64(&mut self) -> Result<f64, LoadError> {
+        let bytes = self.take(8)?;
+        let mut bits = [0; 8];
+        bits.copy_from_slice(bytes);
+        Ok(f64::from_bits(u64::from_be_bytes(bits)))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], LoadError> {
+        let len = self.u32()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        match String::from_utf8(self.bytes()?.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => Err(LoadError::Corrupt("invalid file name")),
+        }
+    }
+
+    fn string_table(&mut self) -> Result<Vec<ByteString>, LoadError> {
+        let len = self.u32()?;
+        let mut table = Vec::new();
+        for _ in 0..len {
+            table.push(ByteString::new(self.bytes()?.to_vec()));
+        }
+        Ok(table)
+    }
+}
+
+impl BytecodeProgram {
+    // Writes the program in the compiled .glassc format
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut body = ByteWriter { data: Vec::new() };
+
+        body.string_table(&self.strings)?;
+
+        body.u32(self.numbers.len())?;
+        for num in self.numbers.iter() {
+            body.f64(*num);
+        }
+
+        body.string_table(&self.global_names)?;
+        body.string_table(&self.member_names)?;
+        body.string_table(&self.local_names)?;
+
+        body.u32(self.native_methods.len())?;
+        for (class_name, method_name) in self.native_methods.iter() {
+            body.bytes(class_name)?;
+            body.bytes(method_name)?;
+        }
+
+        body.u32(self.classes.len())?;
+        for (class_name, class) in self.class_names.iter().zip(self.classes.iter()) {
+            body.u16(*class_name);
+
+            match class.constructor {
+                Some(ctor_index) => {
+                    body.u8(1);
+                    body.u32(ctor_index)?;
+                },
+                None => body.u8(0),
+            }
+
+            // Sorted so that compiling the same program always gives the same file
+            let mut funcs: Vec<_> = class.funcs.iter().collect();
+            funcs.sort();
+
+            body.u32(funcs.len())?;
+            for (func_name, func_index) in funcs {
+                body.u16(*func_name);
+                body.u32(*func_index)?;
+            }
+        }
+
+        body.u16(self.main_class);
+        body.u16(self.main_func);
+
+        body.bytes(&self.instructions)?;
+
+        body.u32(self.files.len())?;
+        for (file_index, filename) in self.files.iter() {
+            body.u32(*file_index)?;
+            body.bytes(filename.as_bytes())?;
+        }
+
+        body.u32(self.positions.len())?;
+        for (pos_index, pos) in self.positions.iter() {
+            body.u32(*pos_index)?;
+            body.u32(pos.line)?;
+            body.u32(pos.col)?;
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_be_bytes())?;
+        writer.write_all(&to_u32(body.data.len())?.to_be_bytes())?;
+        writer.write_all(&checksum(&body.data).to_be_bytes())?;
+        writer.write_all(&body.data)?;
+        writer.flush()
+    }
+
+    // Reads a program in the compiled .glassc format, verifying that it's safe to run
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<BytecodeProgram, LoadError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut header = ByteReader { data: &data, pos: 0 };
+
+        if header.take(MAGIC.len()).map_err(|_| LoadError::BadMagic)? != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+
+        let version = header.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let body_len = header.u32()?;
+        let expected_checksum = header.u32()? as u32;
+        if data.len() - header.pos < body_len {
+            return Err(LoadError::Truncated);
+        }
+        if checksum(&data[header.pos..]) != expected_checksum {
+            return Err(LoadError::BadChecksum);
+        }
+
+        let mut body = ByteReader { data: &data, pos: header.pos };
+
+        let strings = body.string_table()?;
+
+        let mut numbers = Vec::new();
+        for _ in 0..body.u32()? {
+            numbers.push(body.f64()?);
+        }
+
+        let global_names = body.string_table()?;
+        let member_names = body.string_table()?;
+        let local_names = body.string_table()?;
+
+        let mut native_methods = Vec::new();
+        for _ in 0..body.u32()? {
+            let class_name = ByteString::new(body.bytes()?.to_vec());
+            let method_name = ByteString::new(body.bytes()?.to_vec());
+            native_methods.push((class_name, method_name));
+        }
+
+        let mut class_names = Vec::new();
+        let mut classes = Vec::new();
+        for _ in 0..body.u32()? {
+            class_names.push(body.u16()?);
+
+            let mut class = ClassDefinition::new();
+            class.constructor = match body.u8()? {
+                0 => None,
+                1 => Some(body.u32()?),
+                _ => return Err(LoadError::Corrupt("invalid constructor flag")),
+            };
+
+            for _ in 0..body.u32()? {
+                let func_name = body.u16()?;
+                let func_index = body.u32()?;
+                if class.funcs.insert(func_name, func_index).is_some() {
+                    return Err(LoadError::Corrupt("duplicate function name"));
+                }
+            }
+
+            classes.push(class);
+        }
+
+        let main_class = body.u16()?;
+        let main_func = body.u16()?;
+
+        let instructions = body.bytes()?.to_vec();
+
+        let mut files = Vec::new();
+        for _ in 0..body.u32()? {
+            let file_index = body.u32()?;
+            files.push((file_index, body.string()?));
+        }
+
+        let mut positions = Vec::new();
+        for _ in 0..body.u32()? {
+            let pos_index = body.u32()?;
+            let line = body.u32()?;
+            let col = body.u32()?;
+            positions.push((pos_index, FilePosition { line, col }));
+        }
+
+        if body.pos != data.len() {
+            return Err(LoadError::Corrupt("unexpected data after the end of the program"));
+        }
+
+        let program = BytecodeProgram {
+            class_names,
+            classes,
+            strings,
+            numbers,
+            instructions,
+            main_class,
+            main_func,
+            files,
+            positions,
+            global_names,
+            member_names,
+            local_names,
+            native_methods,
+        };
+
+        match verify(&program) {
+            Ok(()) => Ok(program),
+            Err(err) => Err(LoadError::Invalid(err)),
+        }
+    }
+}
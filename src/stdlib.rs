@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use byte_string::ByteString;
+
+use crate::heap::{GlassValue, InstanceIndex};
+use crate::interpreter::{flush_output, RuntimeError};
+use crate::native::{NativeClass, NativeContext};
+
+fn io_error(err: std::io::Error) -> RuntimeError {
+    RuntimeError::IOError(err.to_string())
+}
+
+fn bool_number(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+fn get_index(length: usize, num: f64) -> Result<usize, RuntimeError> {
+    if num != num.floor() || num < 0.0 || num as usize >= length {
+        Err(RuntimeError::InvalidIndex { index: num, length })
+    }
+    else {
+        Ok(num as usize)
+    }
+}
+
+// The names of each class's methods and what they do, followed by their indices, which are what
+// gets passed to call(). Each description starts with the stack effect, with the last argument on
+// the top of the stack.
+
+const MATH_METHODS: [&str; 12] = ["a", "d", "e", "f", "ge", "gt", "le", "lt", "m", "mod", "ne", "s"];
+
+const MATH_DOCS: [&str; 12] = [
+    "`x y -- x+y` Adds two numbers",
+    "`x y -- x/y` Divides two numbers",
+    "`x y -- x==y` Gives 1 if the numbers are equal, otherwise 0",
+    "`x -- floor(x)` Rounds a number down",
+    "`x y -- x>=y` Gives 1 if x is greater than or equal to y, otherwise 0",
+    "`x y -- x>y` Gives 1 if x is greater than y, otherwise 0",
+    "`x y -- x<=y` Gives 1 if x is less than or equal to y, otherwise 0",
+    "`x y -- x<y` Gives 1 if x is less than y, otherwise 0",
+    "`x y -- x*y` Multiplies two numbers",
+    "`x y -- x%y` Gives the remainder of dividing x by y",
+    "`x y -- x!=y` Gives 1 if the numbers are different, otherwise 0",
+    "`x y -- x-y` Subtracts y from x",
+];
+
+const MATH_ADD: usize = 0;
+const MATH_DIVIDE: usize = 1;
+const MATH_EQUAL: usize = 2;
+const MATH_FLOOR: usize = 3;
+const MATH_GREATER_EQUAL: usize = 4;
+const MATH_GREATER: usize = 5;
+const MATH_LESS_EQUAL: usize = 6;
+const MATH_LESS: usize = 7;
+const MATH_MULTIPLY: usize = 8;
+const MATH_MODULO: usize = 9;
+const MATH_NOT_EQUAL: usize = 10;
+const MATH_SUBTRACT: usize = 11;
+
+const ARGS_METHODS: [&str; 2] = ["i", "l"];
+
+const ARGS_DOCS: [&str; 2] = [
+    "`n -- s` Gives the argument at index n, counting from 0",
+    "`-- n` Gives the number of arguments",
+];
+
+const ARGS_INDEX: usize = 0;
+const ARGS_LENGTH: usize = 1;
+
+const ENV_METHODS: [&str; 2] = ["e", "g"];
+
+const ENV_DOCS: [&str; 2] = [
+    "`s -- e` Gives 1 if the environment variable named s is set, otherwise 0",
+    "`s -- v` Gives the value of an environment variable, which is empty if it isn't set",
+];
+
+const ENV_EXISTS: usize = 0;
+const ENV_GET: usize = 1;
+
+const FILE_METHODS: [&str; 7] = ["c", "c__", "cl", "e", "l", "o", "w"];
+
+const FILE_DOCS: [&str; 7] = [
+    "`-- c` Reads a single character, which is empty at the end of the file",
+    "`--` Creates an instance with no file open",
+    "`--` Closes the file, writing out anything still buffered",
+    "`-- e` Gives 1 if there's nothing left to read, otherwise 0",
+    "`-- l` Reads a line, including its newline",
+    "`path mode --` Opens a file to read (`r`), write (`w`) or append to (`a`)",
+    "`s --` Writes a string",
+];
+
+const FILE_CHAR: usize = 0;
+const FILE_CONSTRUCT: usize = 1;
+const FILE_CLOSE: usize = 2;
+const FILE_EOF: usize = 3;
+const FILE_LINE: usize = 4;
+const FILE_OPEN: usize = 5;
+const FILE_WRITE: usize = 6;
+
+const INPUT_METHODS: [&str; 3] = ["c", "e", "l"];
+
+const INPUT_DOCS: [&str; 3] = [
+    "`-- c` Reads a single character, which is empty at the end of the input",
+    "`-- e` Gives 1 if the last character read was the end of the input, otherwise 0",
+    "`-- l` Reads a line, including its newline",
+];
+
+const INPUT_CHAR: usize = 0;
+const INPUT_EOF: usize = 1;
+const INPUT_LINE: usize = 2;
+
+const OUTPUT_METHODS: [&str; 5] = ["e", "en", "f", "o", "on"];
+
+const OUTPUT_DOCS: [&str; 5] = [
+    "`s --` Writes a string to the standard error",
+    "`n --` Writes a number to the standard error",
+    "`--` Flushes whatever has been written to the standard output",
+    "`s --` Writes a string",
+    "`n --` Writes a number",
+];
+
+const OUTPUT_ERROR_STRING: usize = 0;
+const OUTPUT_ERROR_NUMBER: usize = 1;
+const OUTPUT_FLUSH: usize = 2;
+const OUTPUT_STRING: usize = 3;
+const OUTPUT_NUMBER: usize = 4;
+
+const STRING_METHODS: [&str; 8] = ["a", "d", "e", "i", "l", "ns", "si", "sn"];
+
+const STRING_DOCS: [&str; 8] = [
+    "`x y -- xy` Joins two strings",
+    "`s n -- x y` Splits a string into the parts before and after index n",
+    "`x y -- x==y` Gives 1 if the strings are equal, otherwise 0",
+    "`s n -- c` Gives the character at index n of a string",
+    "`s -- n` Gives the length of a string",
+    "`n -- c` Gives the character with a number's character code",
+    "`s n c -- s` Replaces the character at index n of a string",
+    "`c -- n` Gives the character code of a single character string",
+];
+
+const STRING_CONCAT: usize = 0;
+const STRING_SPLIT: usize = 1;
+const STRING_EQUAL: usize = 2;
+const STRING_INDEX: usize = 3;
+const STRING_LENGTH: usize = 4;
+const STRING_NUM_TO_STRING: usize = 5;
+const STRING_REPLACE: usize = 6;
+const STRING_TO_NUM: usize = 7;
+
+const VARS_METHODS: [&str; 2] = ["d", "n"];
+
+const VARS_DOCS: [&str; 2] = [
+    "`v --` Deletes a generated variable",
+    "`-- v` Creates a new variable name that's different from every other",
+];
+
+const VARS_DELETE: usize = 0;
+const VARS_NEW: usize = 1;
+
+// V can't be replaced by a registered class, as other classes may be written using it
+pub(crate) const VARS_CLASS_NAME: &str = "V";
+
+// Arithmetic class
+pub(crate) struct MathClass;
+
+impl NativeClass for MathClass {
+    fn name(&self) -> &str {
+        "A"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &MATH_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Arithmetic and comparisons on numbers"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &MATH_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        // Floor is the only method with a single argument
+        if method == MATH_FLOOR {
+            let num = context.pop_number()?;
+            context.push_number(num.floor());
+            return Ok(());
+        }
+
+        let num1 = context.pop_number()?;
+        let num2 = context.pop_number()?;
+
+        let result = match method {
+            MATH_ADD => num1 + num2,
+            MATH_DIVIDE => num2 / num1,
+            MATH_EQUAL => bool_number(num1 == num2),
+            MATH_GREATER_EQUAL => bool_number(num1 <= num2),
+            MATH_GREATER => bool_number(num1 < num2),
+            MATH_LESS_EQUAL => bool_number(num1 >= num2),
+            MATH_LESS => bool_number(num1 > num2),
+            MATH_MULTIPLY => num1 * num2,
+            MATH_MODULO => num2 % num1,
+            MATH_NOT_EQUAL => bool_number(num1 != num2),
+            MATH_SUBTRACT => num2 - num1,
+            _ => unreachable!("no A method at index {}", method),
+        };
+
+        context.push_number(result);
+        Ok(())
+    }
+}
+
+// Command line arguments class
+pub(crate) struct ArgsClass;
+
+impl NativeClass for ArgsClass {
+    fn name(&self) -> &str {
+        "Args"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &ARGS_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "The command line arguments given to the program after `--`"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &ARGS_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            ARGS_INDEX => {
+                let index = context.pop_number()?;
+                let count = context.args().len();
+                let arg = match get_index(count, index) {
+                    Ok(index) => context.args()[index].clone(),
+                    Err(_) => return Err(RuntimeError::MissingArg { index, count }),
+                };
+                context.push_string(arg);
+            },
+            ARGS_LENGTH => context.push_number(context.args().len() as f64),
+            _ => unreachable!("no Args method at index {}", method),
+        }
+
+        Ok(())
+    }
+}
+
+// Environment variables class
+pub(crate) struct EnvClass;
+
+impl NativeClass for EnvClass {
+    fn name(&self) -> &str {
+        "Env"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &ENV_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Environment variables, which are only available when running with `--env`"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &ENV_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        let name = context.pop_string()?.clone();
+        let value = context.env_var(&name).cloned();
+
+        match method {
+            ENV_EXISTS => context.push_number(bool_number(value.is_some())),
+            ENV_GET => context.push_string(value.unwrap_or_default()),
+            _ => unreachable!("no Env method at index {}", method),
+        }
+
+        Ok(())
+    }
+}
+
+enum FileStream {
+    Read(BufReader<File>),
+    Write(BufWriter<File>),
+}
+
+struct OpenFile {
+    path: String,
+
+    stream: FileStream,
+}
+
+fn file_error(path: &str, err: std::io::Error) -> RuntimeError {
+    RuntimeError::FileError(format!("{}: {}", path, err))
+}
+
+// File class, where each instance can have a file open. Instances are told apart by their
+// index in the heap, and a file that's left open is closed when its instance is collected.
+pub(crate) struct FileClass {
+    files: HashMap<InstanceIndex, OpenFile>,
+}
+
+impl FileClass {
+    pub fn new() -> FileClass {
+        FileClass { files: HashMap::new() }
+    }
+
+    fn open(&mut self, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        let mode = context.pop_string()?.clone();
+        let path = String::from_utf8_lossy(context.pop_string()?).into_owned();
+
+        if context.limits.no_files {
+            return Err(RuntimeError::FilesDisabled);
+        }
+
+        let mut options = OpenOptions::new();
+        match mode.as_slice() {
+            b"r" => options.read(true),
+            b"w" => options.write(true).create(true).truncate(true),
+            b"a" => options.append(true).create(true),
+            _ => return Err(RuntimeError::FileError(format!("invalid mode \"{}\", expected r, w or a", String::from_utf8_lossy(&mode)))),
+        };
+
+        let file = options.open(&path).map_err(|err| file_error(&path, err))?;
+        let stream = match mode.as_slice() {
+            b"r" => FileStream::Read(BufReader::new(file)),
+            _ => FileStream::Write(BufWriter::new(file)),
+        };
+
+        // Replacing a file that's still open closes it
+        if let Some(old) = self.files.insert(context.instance, OpenFile { path, stream }) {
+            close(old)?;
+        }
+
+        Ok(())
+    }
+
+    fn reader(&mut self, instance: InstanceIndex) -> Result<(&str, &mut BufReader<File>), RuntimeError> {
+        match self.files.get_mut(&instance) {
+            Some(OpenFile { path, stream: FileStream::Read(reader) }) => Ok((path, reader)),
+            Some(OpenFile { path, .. }) => Err(RuntimeError::FileError(format!("{}: not open for reading", path))),
+            None => Err(RuntimeError::FileError("no file is open".to_owned())),
+        }
+    }
+
+    fn writer(&mut self, instance: InstanceIndex) -> Result<(&str, &mut BufWriter<File>), RuntimeError> {
+        match self.files.get_mut(&instance) {
+            Some(OpenFile { path, stream: FileStream::Write(writer) }) => Ok((path, writer)),
+            Some(OpenFile { path, .. }) => Err(RuntimeError::FileError(format!("{}: not open for writing", path))),
+            None => Err(RuntimeError::FileError("no file is open".to_owned())),
+        }
+    }
+}
+
+fn close(file: OpenFile) -> Result<(), RuntimeError> {
+    match file.stream {
+        FileStream::Read(_) => Ok(()),
+        FileStream::Write(mut writer) => writer.flush().map_err(|err| file_error(&file.path, err)),
+    }
+}
+
+impl NativeClass for FileClass {
+    fn name(&self) -> &str {
+        "File"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &FILE_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Reading and writing files, with one file open per instance"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &FILE_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            FILE_CHAR => {
+                let (path, reader) = self.reader(context.instance)?;
+                let mut byte = [ 0 ];
+                let amount_read = reader.read(&mut byte).map_err(|err| file_error(path, err))?;
+                context.push_string(ByteString::new(byte[..amount_read].to_vec()));
+            },
+            // Heap slots are reused, so a file still kept for this one is closed rather than dropped
+            FILE_CONSTRUCT => {
+                if let Some(old) = self.files.remove(&context.instance) {
+                    close(old)?;
+                }
+            },
+            FILE_CLOSE => match self.files.remove(&context.instance) {
+                Some(file) => close(file)?,
+                None => return Err(RuntimeError::FileError("no file is open".to_owned())),
+            },
+            FILE_EOF => {
+                let (path, reader) = self.reader(context.instance)?;
+                let at_end = reader.fill_buf().map_err(|err| file_error(path, err))?.is_empty();
+                context.push_number(bool_number(at_end));
+            },
+            FILE_LINE => {
+                let (path, reader) = self.reader(context.instance)?;
+                let mut line = Vec::new();
+                reader.read_until(b'\n', &mut line).map_err(|err| file_error(path, err))?;
+                context.push_string(ByteString::new(line));
+            },
+            FILE_OPEN => self.open(context)?,
+            FILE_WRITE => {
+                let string = context.pop_string()?.clone();
+                let (path, writer) = self.writer(context.instance)?;
+                writer.write_all(&string).map_err(|err| file_error(path, err))?;
+            },
+            _ => unreachable!("no File method at index {}", method),
+        }
+
+        Ok(())
+    }
+
+    fn finalize(&mut self, instance: InstanceIndex) -> Result<(), RuntimeError> {
+        match self.files.remove(&instance) {
+            Some(file) => close(file),
+            None => Ok(()),
+        }
+    }
+}
+
+// Input class
+pub(crate) struct InputClass {
+    eof: bool,
+}
+
+impl InputClass {
+    pub fn new() -> InputClass {
+        InputClass { eof: false }
+    }
+}
+
+impl NativeClass for InputClass {
+    fn name(&self) -> &str {
+        "I"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &INPUT_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Reading from the standard input"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &INPUT_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            INPUT_CHAR => {
+                let mut input_bytes = [ 0 ];
+                let amount_read = context.input()?.read(&mut input_bytes).map_err(io_error)?;
+                self.eof = amount_read == 0;
+                context.push_string(ByteString::new(Vec::from(input_bytes)));
+            },
+            INPUT_EOF => context.push_number(bool_number(self.eof)),
+            INPUT_LINE => {
+                let mut line = Vec::new();
+                context.input()?.read_until(b'\n', &mut line).map_err(io_error)?;
+                context.push_string(ByteString::new(line));
+            },
+            _ => unreachable!("no I method at index {}", method),
+        }
+
+        Ok(())
+    }
+}
+
+// Output class
+pub(crate) struct OutputClass;
+
+impl NativeClass for OutputClass {
+    fn name(&self) -> &str {
+        "O"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &OUTPUT_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Writing to the standard output and standard error"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &OUTPUT_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            // Standard output is flushed first so that everything comes out in the order it was written
+            OUTPUT_ERROR_STRING => {
+                let string = context.pop_string()?.clone();
+                flush_output(context.output())?;
+                context.error_output().write_all(&string).map_err(io_error)
+            },
+            OUTPUT_ERROR_NUMBER => {
+                let num = context.pop_number()?;
+                flush_output(context.output())?;
+                write!(context.error_output(), "{}", num).map_err(io_error)
+            },
+            OUTPUT_FLUSH => flush_output(context.output()),
+            OUTPUT_STRING => {
+                let string = context.pop_string()?.clone();
+                context.output().write_all(&string).map_err(io_error)
+            },
+            OUTPUT_NUMBER => {
+                let num = context.pop_number()?;
+                write!(context.output(), "{}", num).map_err(io_error)
+            },
+            _ => unreachable!("no O method at index {}", method),
+        }
+    }
+}
+
+// String class
+pub(crate) struct StringClass;
+
+impl NativeClass for StringClass {
+    fn name(&self) -> &str {
+        "S"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &STRING_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Operations on strings"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &STRING_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            STRING_CONCAT => {
+                let mut str1 = context.pop_string()?.clone();
+                let mut str2 = context.pop_string()?.clone();
+                str2.append(&mut str1);
+                context.push_string(str2);
+            },
+            STRING_SPLIT => {
+                let index_float = context.pop_number()?;
+                let string = context.pop_string()?;
+
+                if index_float.floor() != index_float || index_float < 0.0 || index_float > string.len() as f64 {
+                    return Err(RuntimeError::InvalidIndex { index: index_float, length: string.len() });
+                }
+
+                let index = index_float as usize;
+                let str1 = ByteString::new(Vec::from(&string[..index]));
+                let str2 = ByteString::new(Vec::from(&string[index..]));
+
+                context.push_string(str1);
+                context.push_string(str2);
+            },
+            STRING_EQUAL => {
+                let str1 = context.pop_string()?.clone();
+                let str2 = context.pop_string()?;
+                let equal = str1 == *str2;
+                context.push_number(bool_number(equal));
+            },
+            STRING_INDEX => {
+                let num = context.pop_number()?;
+                let string = context.pop_string()?;
+                let char_str = ByteString(vec![string[get_index(string.len(), num)?]]);
+                context.push_string(char_str);
+            },
+            STRING_LENGTH => {
+                let len = context.pop_string()?.len();
+                context.push_number(len as f64);
+            },
+            STRING_NUM_TO_STRING => {
+                let num = context.pop_number()?;
+                if num.floor() != num || !(0.0..=255.0).contains(&num) {
+                    return Err(RuntimeError::WrongType { expected: "whole number from 0 to 255", found: format!("number {}", num) });
+                }
+                context.push_string(ByteString(vec![num as u8]));
+            },
+            STRING_REPLACE => {
+                let char_str = context.pop_string()?.clone();
+                let index_float = context.pop_number()?;
+                let mut string = context.pop_string()?.clone();
+
+                let index = get_index(string.len(), index_float)?;
+                if char_str.len() != 1 {
+                    return Err(RuntimeError::WrongType { expected: "string of length 1", found: format!("string of length {}", char_str.len()) });
+                }
+
+                string[index] = char_str[0];
+                context.push_string(string);
+            },
+            STRING_TO_NUM => {
+                let string = context.pop_string()?;
+                if string.len() != 1 {
+                    return Err(RuntimeError::WrongType { expected: "string of length 1", found: format!("string of length {}", string.len()) });
+                }
+                let num = string[0] as f64;
+                context.push_number(num);
+            },
+            _ => unreachable!("no S method at index {}", method),
+        }
+
+        Ok(())
+    }
+}
+
+// Variable class, which hands out names that can't clash with any written in the program
+pub(crate) struct VarsClass;
+
+impl NativeClass for VarsClass {
+    fn name(&self) -> &str {
+        VARS_CLASS_NAME
+    }
+
+    fn methods(&self) -> &[&str] {
+        &VARS_METHODS
+    }
+
+    fn docs(&self) -> &str {
+        "Variables with generated names"
+    }
+
+    fn method_docs(&self) -> &[&str] {
+        &VARS_DOCS
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        match method {
+            VARS_DELETE => {
+                let value = context.pop_value()?;
+                if !matches!(value, GlassValue::AutoGeneratedName(_)) {
+                    return Err(context.wrong_type("auto-generated name", &value));
+                }
+            },
+            VARS_NEW => {
+                let name = context.auto_generated_vars().len();
+                context.push_value(GlassValue::AutoGeneratedName(name));
+            },
+            _ => unreachable!("no V method at index {}", method),
+        }
+
+        Ok(())
+    }
+}
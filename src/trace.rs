@@ -0,0 +1,44 @@
+use std::io::Write;
+
+use crate::bytecode::format_instruction;
+use crate::hook::{ExecutionHook, ExecutionState};
+use crate::interpreter::RuntimeError;
+
+// How many values from the top of the stack are shown on each line
+const TRACE_STACK_VALUES: usize = 3;
+
+// Writes a line for every instruction as it's about to run, with where it came from and
+// what's on top of the stack for it to use
+pub struct Tracer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W) -> Tracer<W> {
+        Tracer { output }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W: Write> ExecutionHook for Tracer<W> {
+    fn before_instruction(&mut self, state: &mut ExecutionState) -> Result<(), RuntimeError> {
+        let index = state.opcode_index();
+        let location = state.program().source_location(index);
+        let (instruction, _) = format_instruction(state.program(), index);
+
+        let stack = if state.stack_depth() == 0 {
+            "empty".to_owned()
+        }
+        else {
+            let top = state.stack_top(TRACE_STACK_VALUES).join(", ");
+            if state.stack_depth() > TRACE_STACK_VALUES { format!("..., {}", top) } else { top }
+        };
+
+        writeln!(self.output, "{:06}  {:<30}  {}:{}:{}  stack: {}",
+            index, instruction, location.file, location.pos.line, location.pos.col, stack)
+            .map_err(|err| RuntimeError::IOError(err.to_string()))
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::bytecode::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    DuplicateClass,
+    FunctionOutsideProgram,
+    InvalidClassName,
+    InvalidFunctionName,
+    InvalidGlobalName,
+    InvalidJumpTarget,
+    InvalidLocalName,
+    InvalidMemberName,
+    InvalidNativeMethod,
+    InvalidNumber,
+    InvalidOpcode(u8),
+    InvalidString,
+    MismatchedClassTable,
+    MissingDebugInfo,
+    MissingMainClass,
+    MissingMainFunc,
+    MissingReturn,
+    TruncatedInstruction,
+    UnorderedDebugInfo,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+
+    // The instruction the error was found at, if it relates to a specific one
+    pub index: Option<OpcodeIndex>,
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyErrorKind::DuplicateClass => write!(f, "class is defined more than once"),
+            VerifyErrorKind::FunctionOutsideProgram => write!(f, "function doesn't start on an instruction"),
+            VerifyErrorKind::InvalidClassName => write!(f, "class has an invalid name"),
+            VerifyErrorKind::InvalidFunctionName => write!(f, "function has an invalid name"),
+            VerifyErrorKind::InvalidGlobalName => write!(f, "invalid global name"),
+            VerifyErrorKind::InvalidJumpTarget => write!(f, "jump doesn't land on an instruction in the same function"),
+            VerifyErrorKind::InvalidLocalName => write!(f, "invalid local name"),
+            VerifyErrorKind::InvalidMemberName => write!(f, "invalid member name"),
+            VerifyErrorKind::InvalidNativeMethod => write!(f, "invalid native method"),
+            VerifyErrorKind::InvalidNumber => write!(f, "invalid number constant"),
+            VerifyErrorKind::InvalidOpcode(byte) => write!(f, "invalid opcode 0x{:02x}", byte),
+            VerifyErrorKind::InvalidString => write!(f, "invalid string constant"),
+            VerifyErrorKind::MismatchedClassTable => write!(f, "class names don't match the class definitions"),
+            VerifyErrorKind::MissingDebugInfo => write!(f, "missing file or position information"),
+            VerifyErrorKind::MissingMainClass => write!(f, "main class isn't defined"),
+            VerifyErrorKind::MissingMainFunc => write!(f, "main class has no main function"),
+            VerifyErrorKind::MissingReturn => write!(f, "function doesn't end with a return"),
+            VerifyErrorKind::TruncatedInstruction => write!(f, "instruction is missing its operand"),
+            VerifyErrorKind::UnorderedDebugInfo => write!(f, "file or position information is out of order"),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "invalid bytecode at instruction {}: {}", index, self.kind),
+            None => write!(f, "invalid bytecode: {}", self.kind),
+        }
+    }
+}
+
+fn error(kind: VerifyErrorKind, index: Option<OpcodeIndex>) -> VerifyError {
+    VerifyError { kind, index }
+}
+
+fn read_operand(instructions: &[u8], index: OpcodeIndex, size: usize) -> usize {
+    match size {
+        1 => instructions[index + 1] as usize,
+        2 => ((instructions[index + 1] as usize) << 8) | instructions[index + 2] as usize,
+        _ => 0,
+    }
+}
+
+fn check_debug_info<T>(table: &[(OpcodeIndex, T)]) -> Result<(), VerifyError> {
+    if table.is_empty() || table[0].0 != 0 {
+        return Err(error(VerifyErrorKind::MissingDebugInfo, None));
+    }
+
+    if table.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+        return Err(error(VerifyErrorKind::UnorderedDebugInfo, None));
+    }
+
+    Ok(())
+}
+
+// Checks that the class table is consistent, and returns the start of every function
+fn check_classes(program: &BytecodeProgram) -> Result<Vec<OpcodeIndex>, VerifyError> {
+    if program.class_names.len() != program.classes.len() {
+        return Err(error(VerifyErrorKind::MismatchedClassTable, None));
+    }
+
+    let mut seen_classes = HashSet::new();
+    let mut func_starts = Vec::new();
+
+    for (class_name, class) in program.class_names.iter().zip(program.classes.iter()) {
+        if *class_name as usize >= program.global_names.len() {
+            return Err(error(VerifyErrorKind::InvalidClassName, None));
+        }
+
+        if !seen_classes.insert(*class_name) {
+            return Err(error(VerifyErrorKind::DuplicateClass, None));
+        }
+
+        for (func_name, func_index) in class.funcs.iter() {
+            if *func_name as usize >= program.member_names.len() {
+                return Err(error(VerifyErrorKind::InvalidFunctionName, Some(*func_index)));
+            }
+
+            func_starts.push(*func_index);
+        }
+
+        if let Some(ctor_index) = class.constructor {
+            if !class.funcs.values().any(|func_index| *func_index == ctor_index) {
+                return Err(error(VerifyErrorKind::FunctionOutsideProgram, Some(ctor_index)));
+            }
+        }
+    }
+
+    let main_class = program.class_names.iter().position(|name| *name == program.main_class);
+    match main_class {
+        None => return Err(error(VerifyErrorKind::MissingMainClass, None)),
+        Some(class_index) => {
+            if !program.classes[class_index].funcs.contains_key(&program.main_func) {
+                return Err(error(VerifyErrorKind::MissingMainFunc, None));
+            }
+        },
+    }
+
+    func_starts.sort_unstable();
+    func_starts.dedup();
+
+    Ok(func_starts)
+}
+
+// Checks that a program is well formed, so that it can be run without the interpreter
+// tripping over invalid instructions, operands or tables
+pub fn verify(program: &BytecodeProgram) -> Result<(), VerifyError> {
+    let func_starts = check_classes(program)?;
+
+    check_debug_info(&program.files)?;
+    check_debug_info(&program.positions)?;
+
+    let instructions = &program.instructions;
+
+    // Decode every instruction, so we know where the instruction boundaries are
+    let mut boundaries = HashSet::new();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+
+    while index < instructions.len() {
+        let opcode = match OpCode::from_byte(instructions[index]) {
+            Some(opcode) => opcode,
+            None => return Err(error(VerifyErrorKind::InvalidOpcode(instructions[index]), Some(index))),
+        };
+
+        let size = opcode.operand_size();
+        if index + size >= instructions.len() {
+            return Err(error(VerifyErrorKind::TruncatedInstruction, Some(index)));
+        }
+
+        boundaries.insert(index);
+        decoded.push((index, opcode, read_operand(instructions, index, size)));
+        index += 1 + size;
+    }
+
+    for func_start in func_starts.iter() {
+        if !boundaries.contains(func_start) {
+            return Err(error(VerifyErrorKind::FunctionOutsideProgram, Some(*func_start)));
+        }
+    }
+
+    // Each function runs until the next one starts, and has to end with a return so
+    // that execution can never run off the end of it
+    let mut func_ranges = Vec::new();
+    for (i, func_start) in func_starts.iter().enumerate() {
+        let func_end = match func_starts.get(i + 1) {
+            Some(next_start) => *next_start,
+            None => instructions.len(),
+        };
+        func_ranges.push((*func_start, func_end));
+    }
+
+    for (func_start, func_end) in func_ranges.iter() {
+        let last_instruction = decoded.iter()
+            .take_while(|(index, _, _)| index < func_end)
+            .filter(|(index, _, _)| index >= func_start)
+            .last();
+
+        match last_instruction {
+            Some((_, OpCode::Return, _)) => {},
+            _ => return Err(error(VerifyErrorKind::MissingReturn, Some(*func_start))),
+        }
+    }
+
+    for (index, opcode, operand) in decoded {
+        let in_range = |table_len: usize| operand < table_len;
+
+        let valid = match opcode {
+            OpCode::CallNative => in_range(program.native_methods.len()),
+            OpCode::PushGlobal => in_range(program.global_names.len()),
+            OpCode::PushLocal => in_range(program.local_names.len()),
+            OpCode::PushMember => in_range(program.member_names.len()),
+            OpCode::PushNumber => in_range(program.numbers.len()),
+            OpCode::PushString => in_range(program.strings.len()),
+            OpCode::JumpIf | OpCode::JumpIfNot => {
+                let target = if opcode == OpCode::JumpIf {
+                    (index + 3).checked_sub(operand)
+                }
+                else {
+                    Some(index + 3 + operand)
+                };
+
+                let func = func_ranges.iter().find(|(start, end)| *start <= index && index < *end);
+                match (target, func) {
+                    (Some(target), Some((start, end))) => {
+                        boundaries.contains(&target) && *start <= target && target < *end
+                    },
+                    _ => false,
+                }
+            },
+            _ => true,
+        };
+
+        if !valid {
+            let kind = match opcode {
+                OpCode::CallNative => VerifyErrorKind::InvalidNativeMethod,
+                OpCode::PushGlobal => VerifyErrorKind::InvalidGlobalName,
+                OpCode::PushLocal => VerifyErrorKind::InvalidLocalName,
+                OpCode::PushMember => VerifyErrorKind::InvalidMemberName,
+                OpCode::PushNumber => VerifyErrorKind::InvalidNumber,
+                OpCode::PushString => VerifyErrorKind::InvalidString,
+                _ => VerifyErrorKind::InvalidJumpTarget,
+            };
+
+            return Err(error(kind, Some(index)));
+        }
+    }
+
+    Ok(())
+}
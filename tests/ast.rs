@@ -0,0 +1,112 @@
+use byte_string::ByteString;
+
+use glass::ast::*;
+use glass::bytecode::{disassemble, FilePosition};
+use glass::lower::lower_program;
+use glass::native::NativeClasses;
+use glass::parser::{parse_ast, parse_program};
+
+fn span(start: (usize, usize), end: (usize, usize)) -> Span {
+    Span {
+        start: FilePosition { line: start.0, col: start.1 },
+        end: FilePosition { line: end.0, col: end.1 },
+    }
+}
+
+fn name(name: &str) -> ByteString {
+    ByteString::new(name.as_bytes().to_vec())
+}
+
+fn files(code: &str) -> Vec<(String, Vec<u8>)> {
+    vec![("test.glass".to_owned(), code.as_bytes().to_vec())]
+}
+
+#[test]
+fn classes_and_methods() {
+    let program = parse_ast(&files("'main'\n{M\n[m(_o)O!\"hi\"(_o)o.?]}")).unwrap();
+    assert_eq!(program.files.len(), 1);
+
+    let file = &program.files[0];
+    assert_eq!(file.name, "test.glass");
+    assert_eq!(file.span, span((1, 1), (3, 22)));
+    assert_eq!(file.items[0], Item::Comment(Comment { text: name("main"), span: span((1, 1), (1, 7)) }));
+
+    let classes: Vec<_> = program.classes().collect();
+    assert_eq!(classes.len(), 1);
+    assert_eq!(classes[0].name, name("M"));
+    assert_eq!(classes[0].name_span, span((2, 2), (2, 3)));
+    assert_eq!(classes[0].span, span((2, 1), (3, 22)));
+
+    let method = classes[0].methods().next().unwrap();
+    assert_eq!(method.name, name("m"));
+    assert_eq!(method.name_span, span((3, 2), (3, 3)));
+    assert_eq!(method.span, span((3, 1), (3, 21)));
+
+    let kinds: Vec<_> = method.body.iter().map(|statement| statement.kind.clone()).collect();
+    assert_eq!(kinds, vec![
+        StatementKind::PushName(name("_o")),
+        StatementKind::PushName(name("O")),
+        StatementKind::Instantiate,
+        StatementKind::PushString { value: name("hi"), text: name("hi") },
+        StatementKind::PushName(name("_o")),
+        StatementKind::PushName(name("o")),
+        StatementKind::LoadFrom,
+        StatementKind::Call,
+    ]);
+    assert_eq!(method.body[0].span, span((3, 3), (3, 7)));
+    assert_eq!(method.body[3].span, span((3, 9), (3, 13)));
+}
+
+#[test]
+fn statements() {
+    let program = parse_ast(&files("{M[m<1.50>\"a\\nb\"(12)3,^*=$/(_x)a\\]}")).unwrap();
+    let method = program.classes().next().unwrap().methods().next().unwrap();
+
+    assert_eq!(method.body[0].kind, StatementKind::PushNumber { value: 1.5, text: "1.50".to_owned() });
+    assert_eq!(method.body[1].kind, StatementKind::PushString { value: name("a\nb"), text: name("a\\nb") });
+    assert_eq!(method.body[2].kind, StatementKind::Duplicate(12));
+    assert_eq!(method.body[2].span, span((1, 17), (1, 21)));
+    assert_eq!(method.body[3].kind, StatementKind::Duplicate(3));
+
+    let kinds: Vec<_> = method.body[4..9].iter().map(|statement| statement.kind.clone()).collect();
+    assert_eq!(kinds, vec![StatementKind::Pop, StatementKind::Return, StatementKind::Load, StatementKind::Store, StatementKind::StoreSelf]);
+
+    let body = match &method.body[9].kind {
+        StatementKind::Loop(body) => body,
+        kind => panic!("expected a loop, got {:?}", kind),
+    };
+    assert_eq!(body.name, name("_x"));
+    assert_eq!(body.name_span, span((1, 28), (1, 32)));
+    assert_eq!(body.body, vec![Statement { kind: StatementKind::PushName(name("a")), span: span((1, 32), (1, 33)) }]);
+    assert_eq!(method.body[9].span, span((1, 27), (1, 34)));
+}
+
+#[test]
+fn syntax_errors() {
+    let errors = parse_ast(&files("{M[m#]}")).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].location.as_ref().unwrap().pos, FilePosition { line: 1, col: 5 });
+}
+
+#[test]
+fn lowering_matches_parse_program() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let files = vec![(path.display().to_string(), std::fs::read(&path).unwrap())];
+
+        let tree = parse_ast(&files).unwrap();
+        let lowered = lower_program(&tree, &NativeClasses::standard()).ok().unwrap();
+        let parsed = parse_program(&files).ok().unwrap();
+
+        assert_eq!(disassemble(&lowered), disassemble(&parsed), "{} lowered differently", path.display());
+    }
+}
+
+#[test]
+fn lowering_errors() {
+    let tree = parse_ast(&files("{M[m]}{C[a][a]}")).unwrap();
+    let errors = lower_program(&tree, &NativeClasses::standard()).err().unwrap();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "test.glass:1:13: error: duplicate function name");
+}
@@ -7,11 +7,11 @@ use assert_cmd::Command;
 
 #[test]
 fn auto_vars_success() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&r#"
+    let file = glass_file(r#"
         {M[moO!vV!vn.?000000Z!z.?*oo.?vn.?X!*oo.?vd.?]}
         {Z[(c__)oO!"a"oo.?][z"b"oo.?x.?][x"c"oo.?"d"=]}
         {X[(c__)oO!"e"oo.?]}
-    "#.to_owned())?;
+    "#)?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -6,7 +6,7 @@ use assert_cmd::Command;
 
 #[test]
 fn comment_test() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"'comment'{'comment'M'comment'''['comment'm(_o)'c'O!<8>(_o)'X'(on).'48'?/'X'(_o)''\\]}".to_owned())?;
+    let file = glass_file("'comment'{'comment'M'comment'''['comment'm(_o)'c'O!<8>(_o)'X'(on).'48'?/'X'(_o)''\\]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -7,7 +7,7 @@ use assert_cmd::Command;
 
 #[test]
 fn local_dollar_sign() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[m(_t)$(_t)a.?][a(_o)O!<1>(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[m(_t)$(_t)a.?][a(_o)O!<1>(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -20,7 +20,7 @@ fn local_dollar_sign() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn member_dollar_sign() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mt$(_t)$(_t)a.?][atx.?][x(_o)O!<2>(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[mt$(_t)$(_t)a.?][atx.?][x(_o)O!<2>(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -33,7 +33,7 @@ fn member_dollar_sign() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn global_dollar_sign() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mX$(_n)N!(_n)a.?][a(_o)O!<3>(_o)(on).?]}{N[aXa.?]}".to_owned())?;
+    let file = glass_file("{M[mX$(_n)N!(_n)a.?][a(_o)O!<3>(_o)(on).?]}{N[aXa.?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
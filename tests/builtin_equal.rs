@@ -7,7 +7,7 @@ use assert_cmd::Command;
 
 #[test]
 fn local_equal() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[m(_t)<1>=(_t)*(_o)O!(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[m(_t)<1>=(_t)*(_o)O!(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -20,7 +20,7 @@ fn local_equal() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn member_equal() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[ma\"2\"=(_t)$(_t)x.?][x(_o)O!a*(_o)o.?]}".to_owned())?;
+    let file = glass_file("{M[ma\"2\"=(_t)$(_t)x.?][x(_o)O!a*(_o)o.?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -33,7 +33,7 @@ fn member_equal() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn global_equal() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mA\"3\"=(_t)T!(_t)o.?]}{T[o(_o)O!A*(_o)o.?]}".to_owned())?;
+    let file = glass_file("{M[mA\"3\"=(_t)T!(_t)o.?]}{T[o(_o)O!A*(_o)o.?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
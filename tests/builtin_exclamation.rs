@@ -7,7 +7,7 @@ use assert_cmd::Command;
 
 #[test]
 fn local_exclamation() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[m(_t)M!(_t)a.?][a(_o)O!<1>(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[m(_t)M!(_t)a.?][a(_o)O!<1>(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -20,7 +20,7 @@ fn local_exclamation() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn member_exclamation() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mxM!(_t)$(_t)a.?][axy.?][y(_o)O!<2>(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[mxM!(_t)$(_t)a.?][axy.?][y(_o)O!<2>(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -33,7 +33,7 @@ fn member_exclamation() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn global_exclamation() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mZX!aY!ay.?]}{Y[yZy.?]}{X[y(_o)O!<3>(_o)(on).?]}".to_owned())?;
+    let file = glass_file("{M[mZX!aY!ay.?]}{Y[yZy.?]}{X[y(_o)O!<3>(_o)(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -46,7 +46,7 @@ fn global_exclamation() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn exclamation_constructor() -> Result<(), Box<dyn std::error::Error>> {
-    let file = glass_file(&"{M[mX<1>=YZ!Yz.?]}{Z[(c__)oO!Yz.?X<2>=][zX*o(on).?]}".to_owned())?;
+    let file = glass_file("{M[mX<1>=YZ!Yz.?]}{Z[(c__)oO!Yz.?X<2>=][zX*o(on).?]}")?;
     let mut cmd = Command::cargo_bin("glass")?;
 
     cmd.arg(file.path())
@@ -0,0 +1,138 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+use assert_fs::NamedTempFile;
+
+#[test]
+fn compiled_program_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)C!(_c)o.?]}{C[o(_o)O!(_i)I!(_i)l.?(_o)o.?<4.5>(_o)(on).?]}")?;
+    let output = NamedTempFile::new("program.glassc")?;
+
+    Command::cargo_bin("glass")?
+        .arg("--compile")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout("");
+
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .write_stdin("line\n")
+        .assert()
+        .success()
+        .stdout("line\n4.5");
+
+    Ok(())
+}
+
+#[test]
+fn compiled_program_stack_trace() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\n(_x)*]}")?;
+    let output = NamedTempFile::new("program.glassc")?;
+
+    Command::cargo_bin("glass")?
+        .arg("--compile")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .success();
+
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stderr(format!(
+            "Error: name (_x) has not been set\nTraceback:\n  In file {} on line 2, column 5\n",
+            file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn compiled_program_corrupted() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o)O!\"Hello\"(_o)o.?]}")?;
+    let output = NamedTempFile::new("program.glassc")?;
+
+    Command::cargo_bin("glass")?
+        .arg("--compile")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .success();
+
+    let mut data = std::fs::read(output.path())?;
+
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    std::fs::write(output.path(), &data)?;
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stderr(format!("{}: error: compiled program is corrupted (checksum mismatch)\n", output.path().display()));
+
+    data.truncate(last - 10);
+    std::fs::write(output.path(), &data)?;
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stderr(format!("{}: error: compiled program is truncated\n", output.path().display()));
+
+    data[7] = 99;
+    std::fs::write(output.path(), &data)?;
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stderr(format!(
+            "{}: error: compiled program has format version 99, but only version 2 is supported\n",
+            output.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn compiled_program_with_other_files() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m]}")?;
+    let output = NamedTempFile::new("program.glassc")?;
+
+    Command::cargo_bin("glass")?
+        .arg("--compile")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .success();
+
+    Command::cargo_bin("glass")?
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .failure()
+        .stderr("error: a compiled program can't be combined with other files\n");
+
+    Ok(())
+}
+
+#[test]
+fn compiled_program_too_large() -> Result<(), Box<dyn std::error::Error>> {
+    let files = vec![("test.glass".to_owned(), b"{M[m]}".to_vec())];
+    let mut program = match glass::parser::parse_program(&files) {
+        Ok(program) => program,
+        Err(_) => panic!("test program failed to parse"),
+    };
+
+    // A position that doesn't fit in 32 bits can't be written rather than being cut short
+    program.positions[0].1.line = u32::MAX as usize + 1;
+
+    let mut data = Vec::new();
+    let err = program.write_to(&mut data).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "4294967296 is too large to store in a compiled program");
+    assert!(data.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+const PROGRAM: &str = "{M[m
+  (_c)C!
+  <2>(_c)f.?
+]}
+{C[f
+  (_n)1=,
+  /(_n)
+    (_n)(_n)*<1>(_a)A!(_a)s.?=
+  \\
+  (_z)<0>=
+  /(_z)
+    \"never\"(_o)O!(_o)o.?
+    (_z)<0>=
+  \\
+]
+[g
+  <1>,
+]}";
+
+#[test]
+fn coverage_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--coverage")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("")
+       .stderr(format!(
+           "{}: 10/15 lines (66.7%), 4/8 branches (50.0%), 2/3 functions\n\
+            \x20 M: 3/3 lines (100.0%), 1/1 functions\n\
+            \x20   M.m: 3/3 lines (100.0%), called 1 time\n\
+            \x20 C: 7/12 lines (58.3%), 4/8 branches (50.0%), 1/2 functions\n\
+            \x20   C.f: 7/10 lines (70.0%), 4/8 branches (50.0%), called 1 time\n\
+            \x20     never run: lines 12, 13, 14\n\
+            \x20     loop never skipped at line 7, column 3\n\
+            \x20     loop never entered at line 11, column 3\n\
+            \x20     loop end never reached at line 14, column 3\n\
+            \x20   C.g: never called\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn coverage_lcov() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let lcov_file = assert_fs::NamedTempFile::new("coverage.info")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--coverage-lcov")
+       .arg(lcov_file.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stderr("");
+
+    assert_eq!(std::fs::read_to_string(lcov_file.path())?, format!(
+        "TN:\nSF:{}\n\
+         FN:2,M.m\nFN:6,C.f\nFN:17,C.g\n\
+         FNDA:1,M.m\nFNDA:1,C.f\nFNDA:0,C.g\n\
+         FNF:3\nFNH:2\n\
+         BRDA:7,197,0,0\nBRDA:7,197,1,1\n\
+         BRDA:9,233,0,1\nBRDA:9,233,1,1\n\
+         BRDA:11,247,0,1\nBRDA:11,247,1,0\n\
+         BRDA:14,282,0,-\nBRDA:14,282,1,-\n\
+         BRF:8\nBRH:4\n\
+         DA:2,1\nDA:3,1\nDA:4,1\nDA:6,1\nDA:7,1\nDA:8,2\nDA:9,2\nDA:10,1\nDA:11,1\n\
+         DA:12,0\nDA:13,0\nDA:14,0\nDA:15,1\nDA:17,0\nDA:18,0\n\
+         LF:15\nLH:10\n\
+         end_of_record\n",
+        file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn coverage_multiple_files() -> Result<(), Box<dyn std::error::Error>> {
+    let main_file = glass_file("{M[m(_c)C!(_c)c.?]}")?;
+    let class_file = glass_file("{C\n[c<1>,]\n[d<2>,]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--coverage")
+       .arg(main_file.path())
+       .arg(class_file.path())
+       .assert()
+       .success()
+       .stderr(format!(
+           "{}: 1/1 lines (100.0%), 1/1 functions\n\
+            \x20 M: 1/1 lines (100.0%), 1/1 functions\n\
+            \x20   M.m: 1/1 lines (100.0%), called 1 time\n\
+            {}: 1/2 lines (50.0%), 1/2 functions\n\
+            \x20 C: 1/2 lines (50.0%), 1/2 functions\n\
+            \x20   C.c: 1/1 lines (100.0%), called 1 time\n\
+            \x20   C.d: never called\n",
+           main_file.path().display(), class_file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn coverage_after_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\n(_x)*\n<1>,\n]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--coverage")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}: 1/3 lines (33.3%), 1/1 functions\n\
+            \x20 M: 1/3 lines (33.3%), 1/1 functions\n\
+            \x20   M.m: 1/3 lines (33.3%), called 1 time\n\
+            \x20     never run: lines 3, 4\n\
+            Error: name (_x) has not been set\n\
+            Traceback:\n  In file {path} on line 2, column 5\n"));
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+use assert_fs::NamedTempFile;
+use assert_fs::fixture::FileWriteStr;
+
+const PROGRAM: &str = "{M[m
+  (_c)C!
+  <1>(_c)f.?
+  x<2>=
+  \"done\"(_o)O!(_o)o.?
+]}
+{C[f
+  (_a)1=
+  (_a)*
+]}";
+
+fn commands_file(commands: &str) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+    let file = NamedTempFile::new("commands.txt")?;
+    file.write_str(commands)?;
+    Ok(file)
+}
+
+#[test]
+fn debugger_stops_at_start() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("quit\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout(format!("{}:2:3 in M.m\n  000164  PushLocal     (_c)\n(debug) ", file.path().display()))
+       .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn debugger_continue_to_end() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("continue\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout(format!("{}:2:3 in M.m\n  000164  PushLocal     (_c)\n(debug) done", file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_line_breakpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("break glass.txt:9\nc\nstack\nlocals\nbacktrace\nc\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout(format!(
+           "{path}:2:3 in M.m\n  000164  PushLocal     (_c)\n\
+            (debug) Breakpoint 1 at glass.txt:9\n\
+            (debug) Breakpoint 1, {path}:9:3 in C.f\n  000220  PushLocal     (_a)\n\
+            (debug)   [0] number 1\n\
+            (debug)   (_a) = number 1\n\
+            (debug)   #0 C.f at {path}:9:3\n  #1 M.m at {path}:3:12\n\
+            (debug) done"));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_function_breakpoint_and_finish() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("b C.f\nc\nfinish\nmembers\nq\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout(format!(
+           "{path}:2:3 in M.m\n  000164  PushLocal     (_c)\n\
+            (debug) Breakpoint 1 at C.f\n\
+            (debug) Breakpoint 1, {path}:8:3 in C.f\n  000214  PushLocal     (_a)\n\
+            (debug) {path}:4:3 in M.m\n  000185  PushMember    x\n\
+            (debug) No member variables\n\
+            (debug) "));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_stepping() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("si\nstep\nnext\nnext\nnext\nnext\nnext\nstep\nmembers\nq\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout(format!(
+           "{path}:2:3 in M.m\n  000164  PushLocal     (_c)\n\
+            (debug) {path}:2:7 in M.m\n  000167  PushGlobal    C\n\
+            (debug) {path}:2:8 in M.m\n  000170  Load\n\
+            (debug) {path}:3:3 in M.m\n  000174  PushNumber    <1>\n\
+            (debug) {path}:3:6 in M.m\n  000177  PushLocal     (_c)\n\
+            (debug) {path}:3:10 in M.m\n  000180  PushMember    f\n\
+            (debug) {path}:3:11 in M.m\n  000183  LoadFrom\n\
+            (debug) {path}:3:12 in M.m\n  000184  Call\n\
+            (debug) {path}:8:3 in C.f\n  000214  PushLocal     (_a)\n\
+            (debug) No member variables\n\
+            (debug) "));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_invalid_commands() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let commands = commands_file("break glass.txt:30\nbreak C.g\nbreak nowhere\ndelete 1\nfly\nbreakpoints\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout(format!(
+           "{path}:2:3 in M.m\n  000164  PushLocal     (_c)\n\
+            (debug) No code on line 30 of glass.txt\n\
+            (debug) No function C.g\n\
+            (debug) Invalid breakpoint nowhere, expected <file>:<line> or <Class>.<func>\n\
+            (debug) No breakpoint 1\n\
+            (debug) Unknown command fly, try help\n\
+            (debug) No breakpoints\n\
+            (debug) \n"));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_globals_and_members() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\n  X<7>=\n  y\"z\"=\n  <0>\n]}")?;
+    let commands = commands_file("globals\nbreak glass.txt:4\nc\nglobals\nmembers\nc\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout(format!(
+           "{path}:2:3 in M.m\n  000164  PushGlobal    X\n\
+            (debug) No global variables\n\
+            (debug) Breakpoint 1 at glass.txt:4\n\
+            (debug) Breakpoint 1, {path}:4:3 in M.m\n  000178  PushNumber    <0>\n\
+            (debug)   X = number 7\n\
+            (debug)   y = string \"z\"\n\
+            (debug) "));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_line_breakpoint_in_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_a)A!(_o)O!(_n)<3>=\n/(_n)(_n)*(_o)(on).?(_n)(_n)*<1>(_a)s.?=\\\n]}")?;
+    let commands = commands_file("break glass.txt:2\nc\nc\nc\nc\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    // The whole loop is on one line, so the breakpoint is reached again on every iteration
+    let path = file.path().display();
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout(format!(
+           "{path}:1:5 in M.m\n  000164  PushLocal     (_a)\n\
+            (debug) Breakpoint 1 at glass.txt:2\n\
+            (debug) Breakpoint 1, {path}:2:1 in M.m\n  000191  PushLocal     (_n)\n\
+            (debug) 3Breakpoint 1, {path}:2:6 in M.m\n  000198  PushLocal     (_n)\n\
+            (debug) 2Breakpoint 1, {path}:2:6 in M.m\n  000198  PushLocal     (_n)\n\
+            (debug) 1"));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_commands_separate_from_input() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_i)I!(_o)O!(_i)l.?(_o)o.?]}")?;
+    let commands = commands_file("continue\n")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--debug-commands")
+       .arg(commands.path())
+       .arg(file.path())
+       .write_stdin("step\n")
+       .assert()
+       .success()
+       .stdout(format!("{}:1:5 in M.m\n  000164  PushLocal     (_i)\n(debug) step\n", file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_missing_commands_file() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--debug-commands")
+       .arg("missing-commands.txt")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout("");
+    assert!(assert.get_output().stderr.starts_with(b"missing-commands.txt: error: "));
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn dump_bytecode_listing() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_n)<3>=/(_n)\"a\",(_n)<0>=\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let output = cmd.arg("--dump-bytecode")
+       .arg(file.path())
+       .assert()
+       .success()
+       .get_output()
+       .stdout
+       .clone();
+
+    let listing = String::from_utf8(output)?;
+    let path = file.path().display().to_string();
+    let expected = [
+        "M.m:".to_owned(),
+        format!("  000164  PushLocal     (_n)                      {}:1:5", path),
+        format!("  000167  PushNumber    <3>                       {}:1:9", path),
+        format!("  000170  Store                                   {}:1:12", path),
+        format!("  000171  PushLocal     (_n)                      {}:1:13", path),
+        format!("  000174  Load                                    {}:1:13", path),
+        format!("  000175  JumpIfNot     -> 000196                 {}:1:13", path),
+        format!("  000178  PushString    \"a\"                       {}:1:18", path),
+        format!("  000181  Pop                                     {}:1:21", path),
+        format!("  000182  PushLocal     (_n)                      {}:1:22", path),
+        format!("  000185  PushNumber    <0>                       {}:1:26", path),
+        format!("  000188  Store                                   {}:1:29", path),
+        format!("  000189  PushLocal     (_n)                      {}:1:30", path),
+        format!("  000192  Load                                    {}:1:30", path),
+        format!("  000193  JumpIf        -> 000178                 {}:1:30", path),
+        format!("  000196  Return                                  {}:1:31", path),
+        "".to_owned(),
+    ].join("\n");
+
+    assert!(listing.starts_with("A.a:\n  000000  CallNative    A.a"));
+    assert!(listing.ends_with(&expected), "unexpected listing:\n{}", listing);
+
+    Ok(())
+}
+
+#[test]
+fn dump_bytecode_does_not_run() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o)O!\"Hi\"(_o)o.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let output = cmd.arg(file.path())
+       .arg("--dump-bytecode")
+       .assert()
+       .success()
+       .get_output()
+       .stdout
+       .clone();
+
+    assert!(!String::from_utf8(output)?.starts_with("Hi"));
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use byte_string::ByteString;
+
+use glass::bytecode::BytecodeProgram;
+use glass::interpreter::{Interpreter, RuntimeError, Value};
+use glass::parser::parse_program;
+
+const COUNTER: &str = r#"
+    {M[m]}
+    {C[(c__)(ad)A!c<0>=]
+      [i(_n)1=,cc*(_n)*(ad)a.?=]
+      [gc*]
+      [s(_s)S!"ab""cd"(_s)a.?]
+      [n(_t)C!(_t)*]
+      [p(_o)O!"hi"(_o)o.?]}
+"#;
+
+fn compile(code: &str) -> BytecodeProgram {
+    let files = vec![("test.glass".to_owned(), code.as_bytes().to_vec())];
+    match parse_program(&files) {
+        Ok(program) => program,
+        Err(_) => panic!("test program failed to parse"),
+    }
+}
+
+#[test]
+fn call_method_with_arguments() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+
+    let counter = interpreter.instantiate("C").unwrap();
+    assert!(interpreter.call(counter, "i", &[Value::Number(5.0)]).unwrap().is_empty());
+    assert!(interpreter.call(counter, "i", &[Value::Number(2.5)]).unwrap().is_empty());
+
+    let results = interpreter.call(counter, "g", &[]).unwrap();
+    assert_eq!(results, vec![Value::Number(7.5)]);
+}
+
+#[test]
+fn call_returns_strings_and_instances() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+
+    let results = interpreter.call(counter, "s", &[]).unwrap();
+    assert_eq!(results, vec![Value::String(ByteString::new(b"abcd".to_vec()))]);
+
+    let new_counter = match interpreter.call(counter, "n", &[]).unwrap().as_slice() {
+        [Value::Instance(handle)] => *handle,
+        _ => panic!("expected a single instance"),
+    };
+    assert_ne!(new_counter, counter);
+
+    let results = interpreter.call(new_counter, "g", &[]).unwrap();
+    assert_eq!(results, vec![Value::Number(0.0)]);
+}
+
+#[test]
+fn call_writes_output() {
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(compile(COUNTER)).output(&mut output);
+
+    let counter = interpreter.instantiate("C").unwrap();
+    interpreter.call(counter, "p", &[]).unwrap();
+    drop(interpreter);
+
+    assert_eq!(output, b"hi");
+}
+
+#[test]
+fn handles_survive_garbage_collection() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+    interpreter.call(counter, "i", &[Value::Number(3.0)]).unwrap();
+
+    for _ in 0..2000 {
+        interpreter.call(counter, "s", &[]).unwrap();
+    }
+
+    assert!(interpreter.heap_stats().collections > 0);
+    assert_eq!(interpreter.call(counter, "g", &[]).unwrap(), vec![Value::Number(3.0)]);
+}
+
+#[test]
+fn unknown_class() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+
+    match interpreter.instantiate("D") {
+        Err(err) => assert!(matches!(err.error, RuntimeError::UnknownClass(name) if name == "D")),
+        Ok(_) => panic!("instantiating an unknown class should fail"),
+    }
+}
+
+#[test]
+fn missing_method() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+
+    match interpreter.call(counter, "missing", &[]) {
+        Err(err) => assert_eq!(err.to_string(), "instance of class C has no member or method (missing)"),
+        Ok(_) => panic!("calling a missing method should fail"),
+    }
+}
+
+#[test]
+fn released_instance() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+    interpreter.release(counter);
+
+    match interpreter.call(counter, "g", &[]) {
+        Err(err) => assert!(matches!(err.error, RuntimeError::ReleasedInstance)),
+        Ok(_) => panic!("calling a method on a released instance should fail"),
+    }
+}
+
+#[test]
+fn runtime_error_in_method() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+
+    match interpreter.call(counter, "i", &[Value::String(ByteString::new(b"x".to_vec()))]) {
+        Err(err) => {
+            assert_eq!(err.to_string(), "in builtin A.a: expected number, got string \"x\"");
+            assert_eq!(err.stack_trace.len(), 2);
+        },
+        Ok(_) => panic!("adding a string should fail"),
+    }
+
+    // The interpreter is still usable afterwards
+    assert_eq!(interpreter.call(counter, "g", &[]).unwrap(), vec![Value::Number(0.0)]);
+}
+
+// Keeps everything written to it along with a | for each flush, so that standard output and
+// standard error can share one log
+struct Log(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().push(b'|');
+        Ok(())
+    }
+}
+
+#[test]
+fn output_flush_and_error_order() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new(compile("{M[m]}{C[p(_o)O!\"a\"(_o)o.?(_o)f.?\"b\"(_o)o.?\"c\"(_o)e.?<1>(_o)(en).?]}"))
+        .output(Log(log.clone()))
+        .error_output(Log(log.clone()));
+
+    let instance = interpreter.instantiate("C").unwrap();
+    interpreter.call(instance, "p", &[]).unwrap();
+    drop(interpreter);
+
+    // Writing to standard error flushes what was written to standard output before it
+    assert_eq!(log.borrow().as_slice(), b"a|b|c|1||");
+}
+
+#[test]
+fn program_args() {
+    let args = vec![ByteString::new(b"a".to_vec()), ByteString::new(b"bc".to_vec())];
+    let mut interpreter = Interpreter::new(compile("{M[m]}{C[c(_g)(Args)!<1>(_g)i.?(_g)l.?]}")).args(args);
+    let instance = interpreter.instantiate("C").unwrap();
+
+    let results = interpreter.call(instance, "c", &[]).unwrap();
+    assert_eq!(results, vec![Value::String(ByteString::new(b"bc".to_vec())), Value::Number(2.0)]);
+}
+
+#[test]
+fn env_vars() {
+    let vars = vec![
+        (ByteString::new(b"NAME".to_vec()), ByteString::new(b"glass".to_vec())),
+        (ByteString::new(b"lower".to_vec()), ByteString::new(b"only by lookup".to_vec())),
+        (ByteString::new(b"C".to_vec()), ByteString::new(b"not a class".to_vec())),
+    ];
+    let mut interpreter = Interpreter::new(compile("{M[m]}{C[g(NAME)*(_e)(Env)!\"lower\"(_e)g.?]}")).env_vars(vars);
+
+    // C is still the class rather than the variable
+    let instance = interpreter.instantiate("C").unwrap();
+
+    let results = interpreter.call(instance, "g", &[]).unwrap();
+    assert_eq!(results, vec![
+        Value::String(ByteString::new(b"glass".to_vec())),
+        Value::String(ByteString::new(b"only by lookup".to_vec())),
+    ]);
+}
+
+#[test]
+fn call_only_returns_its_own_results() {
+    let mut interpreter = Interpreter::new(compile(COUNTER));
+    let counter = interpreter.instantiate("C").unwrap();
+
+    // Values left on the stack by eval stay there rather than being returned by call
+    interpreter.eval(counter, "eval", b"<1>\"left\"").unwrap();
+    interpreter.call(counter, "i", &[Value::Number(4.0)]).unwrap();
+    let results = interpreter.call(counter, "g", &[]).unwrap();
+
+    assert_eq!(results, vec![Value::Number(4.0)]);
+    assert_eq!(interpreter.describe_stack(), vec!["number 1".to_owned(), "string \"left\"".to_owned()]);
+}
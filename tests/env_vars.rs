@@ -0,0 +1,78 @@
+mod common;
+
+use crate::common::glass_expression_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn env_globals() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(GLASS_TEST)*(_o)o.?X*(_o)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--env")
+       .arg(file.path())
+       .env("GLASS_TEST", "from env ")
+       .env("X", "x")
+       .assert()
+       .success()
+       .stdout("from env x");
+
+    Ok(())
+}
+
+#[test]
+fn env_globals_off_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(GLASS_TEST)*(_o)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .env("GLASS_TEST", "from env")
+       .assert()
+       .failure()
+       .stdout("");
+    assert!(assert.get_output().stderr.starts_with(b"Error: name (GLASS_TEST) has not been set\n"));
+
+    Ok(())
+}
+
+#[test]
+fn env_globals_keep_classes() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("\"ok\"(_o)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--env")
+       .arg(file.path())
+       .env("O", "not a class")
+       .env("A", "not a class")
+       .assert()
+       .success()
+       .stdout("ok");
+
+    Ok(())
+}
+
+#[test]
+fn env_lookup() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(_e)(Env)!\
+                                      \"glass test\"(_e)e.?(_o)(on).?\"glass test\"(_e)g.?(_o)o.?\
+                                      \"GLASS_UNSET\"(_e)e.?(_o)(on).?\"GLASS_UNSET\"(_e)g.?(_o)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--env")
+       .arg(file.path())
+       .env("glass test", "value")
+       .env_remove("GLASS_UNSET")
+       .assert()
+       .success()
+       .stdout("1value0");
+
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .env("glass test", "value")
+       .assert()
+       .success()
+       .stdout("00");
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn json_parse_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m#]}\n{C[c\"abc]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--error-format=json")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout("")
+       .stderr(format!(
+           "{{\"type\":\"parse_error\",\"message\":\"invalid character `#` in a function body, expected a command or `]` to end the function\",\
+            \"location\":{{\"file\":\"{path}\",\"line\":1,\"column\":5}},\"related\":null}}\n\
+            {{\"type\":\"parse_error\",\"message\":\"unended string, expected `\\\"` before the end of the file\",\
+            \"location\":{{\"file\":\"{path}\",\"line\":2,\"column\":11}},\
+            \"related\":{{\"message\":\"string starts here\",\"location\":{{\"file\":\"{path}\",\"line\":2,\"column\":5}}}}}}\n"));
+
+    Ok(())
+}
+
+#[test]
+fn json_missing_main_class() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{C[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--error-format")
+       .arg("json")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr("{\"type\":\"parse_error\",\"message\":\"missing main class\",\"location\":null,\"related\":null}\n");
+
+    Ok(())
+}
+
+#[test]
+fn json_runtime_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\n(_c)C!(_c)c.?]}\n{C[c\"x\"<1>(_a)A!(_a)a.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--error-format=json")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{{\"type\":\"runtime_error\",\"message\":\"in builtin A.a: expected number, got string \\\"x\\\"\",\"builtin\":\"A.a\",\
+            \"traceback\":[{{\"builtin\":\"A.a\"}},{{\"file\":\"{path}\",\"line\":3,\"column\":23}},{{\"file\":\"{path}\",\"line\":2,\"column\":13}}]}}\n"));
+
+    Ok(())
+}
+
+#[test]
+fn json_load_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m]}")?;
+    let output = assert_fs::NamedTempFile::new("program.glassc")?;
+
+    Command::cargo_bin("glass")?
+        .arg("--compile")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .success();
+
+    Command::cargo_bin("glass")?
+        .arg("--error-format=json")
+        .arg(output.path())
+        .arg(file.path())
+        .assert()
+        .failure()
+        .stderr("{\"type\":\"load_error\",\"message\":\"a compiled program can't be combined with other files\",\"file\":null}\n");
+
+    let mut data = std::fs::read(output.path())?;
+    data.truncate(data.len() - 1);
+    std::fs::write(output.path(), &data)?;
+
+    Command::cargo_bin("glass")?
+        .arg("--error-format=json")
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stderr(format!("{{\"type\":\"load_error\",\"message\":\"compiled program is truncated\",\"file\":\"{}\"}}\n",
+                        output.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn json_file_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.path().join("missing.glass");
+    let message = std::fs::File::open(&path).unwrap_err().to_string();
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--error-format=json")
+       .arg(&path)
+       .assert()
+       .failure()
+       .stdout("")
+       .stderr(format!("{{\"type\":\"file_error\",\"message\":\"{}\",\"file\":\"{}\"}}\n", message, path.display()));
+
+    Ok(())
+}
+
+#[test]
+fn human_file_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.path().join("missing.glass");
+    let message = std::fs::File::open(&path).unwrap_err().to_string();
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(&path)
+       .assert()
+       .failure()
+       .stderr(format!("{}: error: {}\n", path.display(), message));
+
+    Ok(())
+}
+
+#[test]
+fn human_error_format() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_x)*]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--error-format=human")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!("Error: name (_x) has not been set\nTraceback:\n  In file {} on line 1, column 9\n", file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_error_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--error-format=xml")
+       .arg("file.glass")
+       .assert()
+       .failure();
+
+    assert!(assert.get_output().stderr.starts_with(b"Invalid value xml for --error-format\n"));
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use crate::common::glass_expression_file;
+
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+
+// Glass code for a string, escaping the characters that can't appear in one as they are
+fn glass_string(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[test]
+fn file_write_and_append() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("out.txt");
+    let path_string = glass_string(&path.path().display().to_string());
+
+    let file = glass_expression_file(&format!(
+        "(_f)(File)!{path}\"w\"(_f)o.?\"one\\n\"(_f)w.?(_f)(cl).?\
+         {path}\"a\"(_f)o.?\"two\\n\"(_f)w.?(_f)(cl).?", path = path_string))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("");
+
+    path.assert("one\ntwo\n");
+
+    Ok(())
+}
+
+#[test]
+fn file_read() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("in.txt");
+    path.write_str("ab\ncd")?;
+
+    let file = glass_expression_file(&format!(
+        "(_f)(File)!{}\"r\"(_f)o.?\
+         (_f)c.?(_o)o.?\"|\"(_o)o.?(_f)l.?(_o)o.?(_f)e.?(_o)(on).?\
+         (_f)l.?(_o)o.?(_f)e.?(_o)(on).?(_f)c.?(_o)o.?(_f)(cl).?",
+        glass_string(&path.path().display().to_string())))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("a|b\n0cd1");
+
+    Ok(())
+}
+
+#[test]
+fn file_open_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("missing.txt").path().display().to_string();
+
+    let file = glass_expression_file(&format!("(_f)(File)!{}\"r\"(_f)o.?", glass_string(&path)))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .assert()
+       .failure();
+    let expected = format!("Error: in builtin File.o: file error: {}: No such file or directory", path);
+    assert!(assert.get_output().stderr.starts_with(expected.as_bytes()));
+
+    Ok(())
+}
+
+#[test]
+fn file_not_open() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(_f)(File)!(_f)l.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .assert()
+       .failure();
+    assert!(assert.get_output().stderr.starts_with(b"Error: in builtin File.l: file error: no file is open\n"));
+
+    let file = glass_expression_file("(_f)(File)!\"x\"\"rw\"(_f)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .assert()
+       .failure();
+    assert!(assert.get_output().stderr.starts_with(b"Error: in builtin File.o: file error: invalid mode \"rw\", expected r, w or a\n"));
+
+    Ok(())
+}
+
+#[test]
+fn file_wrong_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("out.txt").path().display().to_string();
+
+    let file = glass_expression_file(&format!("(_f)(File)!{}\"w\"(_f)o.?(_f)c.?", glass_string(&path)))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .assert()
+       .failure();
+    let expected = format!("Error: in builtin File.c: file error: {}: not open for reading\n", path);
+    assert!(assert.get_output().stderr.starts_with(expected.as_bytes()));
+
+    Ok(())
+}
+
+#[test]
+fn file_closed_when_collected() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("log.txt");
+    let path_string = glass_string(&path.path().display().to_string());
+
+    // Opens a new file to append to on each iteration without closing it, then reads back
+    // everything that the collected instances wrote
+    let file = glass_expression_file(&format!(
+        "(_n)<0>=(_c)<1>=\
+         /(_c)(_f)(File)!{path}\"a\"(_f)o.?\"x\"(_f)w.?\
+         (_n)(_n)*<1>(_a)a.?=(_c)(_n)*<3000>(_a)(lt).?=\\\
+         (_f)(File)!{path}\"r\"(_f)o.?(_f)l.?(_s)l.?(_o)(on).?", path = path_string))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("2040");
+
+    path.assert("x".repeat(3000));
+
+    Ok(())
+}
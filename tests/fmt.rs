@@ -0,0 +1,148 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+use glass::bytecode::BytecodeProgram;
+use glass::format::format_code;
+use glass::parser::parse_program;
+
+const UNFORMATTED: &str = "'Counts down' {M[m(_o)O!(_n)<3>=/(_n)(_n)*(_o)(on).?'next' (_n)(_n)*<1>(_a)s.?=\\]\
+                           [(c__)(_a)A!]} {C[c1=,][d]}";
+
+const FORMATTED: &str = "\
+'Counts down'
+{M
+  [m
+    (_o)O!
+    (_n)<3>=
+    /(_n)
+      (_n)*(_o)(on).? 'next' (_n)(_n)*<1>(_a)s.?=
+    \\
+  ]
+
+  [(c__) (_a)A!]
+}
+
+{C
+  [c 1=,]
+  [d]
+}
+";
+
+#[test]
+fn formats_layout_and_keeps_comments() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(format_code("count.glass", UNFORMATTED.as_bytes()).unwrap(), FORMATTED.as_bytes());
+    Ok(())
+}
+
+#[test]
+fn formatting_is_idempotent() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(format_code("count.glass", FORMATTED.as_bytes()).unwrap(), FORMATTED.as_bytes());
+    Ok(())
+}
+
+#[test]
+fn comments_stay_next_to_code() -> Result<(), Box<dyn std::error::Error>> {
+    let code = "{M\n'main'\n[m 'start'\n(_o)O!'output'\n\"hi\"(_o)o.?]}";
+    let expected = "{M\n  'main'\n  [m\n    'start'\n    (_o)O! 'output'\n    \"hi\"(_o)o.?\n  ]\n}\n";
+    assert_eq!(String::from_utf8(format_code("comments.glass", code.as_bytes()).unwrap())?, expected);
+    Ok(())
+}
+
+#[test]
+fn loop_name_comments_stay_on_its_line() -> Result<(), Box<dyn std::error::Error>> {
+    let code = "{M[m/'c'a'after'\n'body'(_x)*\\]}";
+    let expected = "{M\n  [m\n    /a 'c' 'after'\n      'body'\n      (_x)*\n    \\\n  ]\n}\n";
+    let formatted = format_code("loop.glass", code.as_bytes()).unwrap();
+    assert_eq!(String::from_utf8(formatted.clone())?, expected);
+    assert_eq!(format_code("loop.glass", &formatted).unwrap(), formatted);
+    Ok(())
+}
+
+fn assert_same_bytecode(original: &BytecodeProgram, formatted: &BytecodeProgram) {
+    assert_eq!(original.instructions, formatted.instructions);
+    assert_eq!(original.strings, formatted.strings);
+    assert_eq!(original.numbers.iter().map(|n| n.to_bits()).collect::<Vec<_>>(), formatted.numbers.iter().map(|n| n.to_bits()).collect::<Vec<_>>());
+    assert_eq!(original.class_names, formatted.class_names);
+    assert_eq!(original.global_names, formatted.global_names);
+    assert_eq!(original.member_names, formatted.member_names);
+    assert_eq!(original.local_names, formatted.local_names);
+    assert_eq!((original.main_class, original.main_func), (formatted.main_class, formatted.main_func));
+
+    for (original_class, formatted_class) in original.classes.iter().zip(formatted.classes.iter()) {
+        assert_eq!(original_class.funcs, formatted_class.funcs);
+        assert_eq!(original_class.constructor, formatted_class.constructor);
+    }
+}
+
+#[test]
+fn examples_compile_to_the_same_bytecode() -> Result<(), Box<dyn std::error::Error>> {
+    for entry in std::fs::read_dir("examples")? {
+        let path = entry?.path();
+        let filename = path.display().to_string();
+        let code = std::fs::read(&path)?;
+
+        let formatted = format_code(&filename, &code).unwrap();
+        assert_eq!(format_code(&filename, &formatted).unwrap(), formatted, "{} isn't stable", filename);
+
+        let original = parse_program(&[(filename.clone(), code)]).ok().unwrap();
+        let reformatted = parse_program(&[(filename.clone(), formatted)]).ok().unwrap();
+        assert_same_bytecode(&original, &reformatted);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn fmt_rewrites_files() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(UNFORMATTED)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("fmt")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("");
+
+    assert_eq!(std::fs::read_to_string(file.path())?, FORMATTED);
+
+    Ok(())
+}
+
+#[test]
+fn fmt_check() -> Result<(), Box<dyn std::error::Error>> {
+    let unformatted = glass_file(UNFORMATTED)?;
+    let formatted = glass_file(FORMATTED)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("fmt")
+       .arg("--check")
+       .arg(unformatted.path())
+       .arg(formatted.path())
+       .assert()
+       .failure()
+       .stdout(format!("{}\n", unformatted.path().display()));
+
+    assert_eq!(std::fs::read_to_string(unformatted.path())?, UNFORMATTED);
+
+    Ok(())
+}
+
+#[test]
+fn fmt_parse_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m#]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("fmt")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stdout("")
+       .stderr(format!("{}:1:5: error: invalid character `#` in a function body, expected a command or `]` to end the function\n\
+                        {{M[m#]}}\n    ^\n", file.path().display()));
+
+    assert_eq!(std::fs::read_to_string(file.path())?, "{M[m#]}");
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn gc_frees_unreachable_strings() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o)O!(_s)S!(_a)A!(_n)<0>=(_c)<1>=/(_c)(_x)\"ab\"\"cd\"(_s)a.?=(_n)(_n)*<1>(_a)a.?=(_c)(_n)*<5000>(_a)(lt).?=\\(_x)*(_o)o.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--heap-stats")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("abcd")
+       .stderr("Garbage collections: 4\n\
+                Instances: 4 allocated, 0 freed, 4 live, 4 peak\n\
+                Strings: 5000 allocated, 4090 freed, 910 live (3640 bytes), 1026 peak\n");
+
+    Ok(())
+}
+
+#[test]
+fn gc_frees_unreachable_instances() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_a)A!(_n)<0>=(_c)<1>=/(_c)(_t)A!(_n)(_n)*<1>(_a)a.?=(_c)(_n)*<3000>(_a)(lt).?=\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--heap-stats")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stderr("Garbage collections: 2\n\
+                Instances: 3002 allocated, 2044 freed, 958 live, 1028 peak\n\
+                Strings: 0 allocated, 0 freed, 0 live (0 bytes), 0 peak\n");
+
+    Ok(())
+}
+
+#[test]
+fn gc_keeps_reachable_values() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(r#"
+        {M[m(_a)A!(_s)S!(_o)O!(G)K!(_n)<0>=(_c)<1>=/(_c)(_x)"ab""cd"(_s)a.?=(_n)(_n)*<1>(_a)a.?=(_c)(_n)*<3000>(_a)(lt).?=\(G)v.(_o)o.?]}
+        {K[(c__)(_s)S!v"ke""ep"(_s)a.?=]}
+    "#)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("keep");
+
+    Ok(())
+}
@@ -19,7 +19,7 @@ fn input_eof_success() -> Result<(), Box<dyn std::error::Error>> {
        .write_stdin("ab")
        .assert()
        .success()
-       .stdout("0a0b0\01\01\0");
+       .stdout("0a0b0\x001\x001\0");
 
     Ok(())
 }
@@ -0,0 +1,70 @@
+use glass::bytecode::BytecodeProgram;
+use glass::interpreter::{Interpreter, RuntimeError};
+use glass::parser::parse_program;
+
+fn compile(code: &str) -> BytecodeProgram {
+    let files = vec![("test.glass".to_owned(), code.as_bytes().to_vec())];
+    match parse_program(&files) {
+        Ok(program) => program,
+        Err(_) => panic!("test program failed to parse"),
+    }
+}
+
+#[test]
+fn output_to_buffer() {
+    let program = compile("{M[m(_o)O!\"Hello\"(_o)o.?<42>(_o)(on).?]}");
+    let mut output = Vec::new();
+
+    let result = Interpreter::new(program).output(&mut output).run();
+
+    assert!(result.is_ok());
+    assert_eq!(output, b"Hello42");
+}
+
+#[test]
+fn input_from_buffer() {
+    let program = compile("{M[m(_i)I!(_o)O!(_i)l.?(_o)o.?(_i)c.?(_o)o.?(_i)e.?(_o)(on).?(_i)c.?(_i)e.?(_o)(on).?]}");
+    let mut output = Vec::new();
+
+    let result = Interpreter::new(program)
+        .input(&b"line\nx"[..])
+        .output(&mut output)
+        .run();
+
+    assert!(result.is_ok());
+    assert_eq!(output, b"line\nx01");
+}
+
+#[test]
+fn output_flushed_on_error() {
+    let program = compile("{M[m(_o)O!\"before\"(_o)o.?(_x)*]}");
+    let mut output = Vec::new();
+
+    let result = Interpreter::new(program).output(&mut output).run();
+
+    match result {
+        Err(err) => assert!(matches!(err.error, RuntimeError::UnsetName(_))),
+        Ok(_) => panic!("program should have failed"),
+    }
+    assert_eq!(output, b"before");
+}
+
+#[test]
+fn separate_runs_are_independent() {
+    let program = compile("{M[m(_o)O!(_i)I!(_i)l.?(_o)o.?]}");
+
+    for input in ["first\n", "second\n"] {
+        let mut output = Vec::new();
+        let mut error_output = Vec::new();
+
+        let result = Interpreter::new(program.clone())
+            .input(input.as_bytes())
+            .output(&mut output)
+            .error_output(&mut error_output)
+            .run();
+
+        assert!(result.is_ok());
+        assert_eq!(output, input.as_bytes());
+        assert!(error_output.is_empty());
+    }
+}
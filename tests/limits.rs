@@ -0,0 +1,144 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn limit_instructions() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)<1>=/(_c)\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-instructions")
+       .arg("1000")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: exceeded the limit of 1000 executed instructions\nTraceback:\n  In file {} on line 1, column 18\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn limit_stack_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)<1>=/(_c)<1>(_c)\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-stack")
+       .arg("100")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: exceeded the limit of 100 values on the stack\nTraceback:\n  In file {} on line 1, column 25\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn limit_call_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_s)$(_s)m.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--max-call-depth")
+       .arg("3")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: exceeded the limit of 3 nested function calls\nTraceback:\n  \
+            In file {path} on line 1, column 16\n  \
+            In file {path} on line 1, column 16\n  \
+            In file {path} on line 1, column 16\n"));
+
+    Ok(())
+}
+
+#[test]
+fn limit_instances() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)<1>=/(_c)(_t)M!(_t)*\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-instances")
+       .arg("50")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: exceeded the limit of 50 live instances\nTraceback:\n  In file {} on line 1, column 23\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn limit_instances_ignores_garbage() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_a)A!(_n)<0>=(_c)<1>=/(_c)(_t)M!(_n)(_n)*<1>(_a)a.?=(_c)(_n)*<500>(_a)(lt).?=\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-instances")
+       .arg("50")
+       .arg(file.path())
+       .assert()
+       .success();
+
+    Ok(())
+}
+
+#[test]
+fn limit_string_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_s)S!(_x)\"ab\"=(_c)<1>=/(_c)(_x)(_x)*(_x)*(_s)a.?=\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-string-bytes")
+       .arg("1000")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: in builtin S.a: exceeded the limit of 1000 bytes of strings\nTraceback:\n  \
+            In builtin S.a\n  In file {} on line 1, column 53\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn limit_invalid_value() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let output = cmd.arg("--max-stack")
+       .arg("lots")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .get_output()
+       .stderr
+       .clone();
+
+    assert!(String::from_utf8(output)?.starts_with("Invalid value lots for --max-stack\n"));
+
+    Ok(())
+}
+
+#[test]
+fn limit_no_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.path().join("out.txt");
+    let file = glass_file(&format!("{{M[m(_f)(File)!\"{}\"\"w\"(_f)o.?]}}", path.display()))?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--no-files")
+       .arg(file.path())
+       .assert()
+       .failure();
+    assert!(assert.get_output().stderr.starts_with(b"Error: in builtin File.o: opening files is disabled\n"));
+    assert!(!path.exists());
+
+    Ok(())
+}
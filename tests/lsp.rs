@@ -0,0 +1,162 @@
+use glass::json::JsonValue;
+use glass::lsp::run_lsp;
+
+use assert_cmd::Command;
+
+const URI: &str = "file:///main.glass";
+
+fn frame(message: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+}
+
+fn open_message(uri: &str, text: &str) -> String {
+    let text = JsonValue::string(text);
+    frame(&format!("{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/didOpen\",\"params\":{{\"textDocument\":{{\"uri\":\"{}\",\"languageId\":\"glass\",\"version\":1,\"text\":{}}}}}}}", uri, text))
+}
+
+fn position_request(id: usize, method: &str, line: usize, character: usize) -> String {
+    frame(&format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{{\"textDocument\":{{\"uri\":\"{}\"}},\"position\":{{\"line\":{},\"character\":{}}}}}}}", id, method, URI, line, character))
+}
+
+// Runs the server on some messages, giving the messages it sent back
+fn lsp_session(messages: &[String]) -> Vec<JsonValue> {
+    let input = messages.concat();
+    let mut output = Vec::new();
+
+    run_lsp(input.as_bytes(), &mut output).expect("language server failed to write its output");
+
+    let output = String::from_utf8(output).unwrap();
+    let mut replies = Vec::new();
+    let mut rest = output.as_str();
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let length: usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        replies.push(JsonValue::parse(&body[..length]).unwrap());
+        rest = &body[length..];
+    }
+    replies
+}
+
+fn json(text: &str) -> JsonValue {
+    JsonValue::parse(text).unwrap()
+}
+
+#[test]
+fn lsp_initialize_and_shutdown() {
+    let replies = lsp_session(&[
+        frame("{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{}}"),
+        frame("{\"jsonrpc\":\"2.0\",\"method\":\"initialized\",\"params\":{}}"),
+        frame("{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"shutdown\"}"),
+        frame("{\"jsonrpc\":\"2.0\",\"method\":\"exit\"}"),
+        frame("{\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"shutdown\"}"),
+    ]);
+
+    assert_eq!(replies, vec![
+        json("{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"capabilities\":{\"textDocumentSync\":1,\"definitionProvider\":true,\
+              \"documentSymbolProvider\":true,\"hoverProvider\":true},\"serverInfo\":{\"name\":\"glass\"}}}"),
+        json("{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":null}"),
+    ]);
+}
+
+#[test]
+fn lsp_diagnostics() {
+    let replies = lsp_session(&[
+        open_message(URI, "{M[m\n  \"abc]}"),
+        frame(&format!("{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/didChange\",\"params\":{{\"textDocument\":{{\"uri\":\"{}\",\"version\":2}},\
+                        \"contentChanges\":[{{\"text\":\"{{M[m\\\"abc\\\"]}}\"}}]}}}}", URI)),
+    ]);
+
+    assert_eq!(replies, vec![
+        json(&format!("{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/publishDiagnostics\",\"params\":{{\"uri\":\"{URI}\",\"diagnostics\":[\
+                       {{\"range\":{{\"start\":{{\"line\":1,\"character\":8}},\"end\":{{\"line\":1,\"character\":8}}}},\"severity\":1,\"source\":\"glass\",\
+                       \"message\":\"unended string, expected `\\\"` before the end of the file\",\"relatedInformation\":[\
+                       {{\"location\":{{\"uri\":\"{URI}\",\"range\":{{\"start\":{{\"line\":1,\"character\":2}},\"end\":{{\"line\":1,\"character\":3}}}}}},\
+                       \"message\":\"string starts here\"}}]}}]}}}}")),
+        json(&format!("{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/publishDiagnostics\",\"params\":{{\"uri\":\"{URI}\",\"diagnostics\":[]}}}}")),
+    ]);
+}
+
+#[test]
+fn lsp_document_symbols() {
+    let replies = lsp_session(&[
+        open_message(URI, "{M\n[m]\n[(foo)<1>]}"),
+        frame(&format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"textDocument/documentSymbol\",\"params\":{{\"textDocument\":{{\"uri\":\"{}\"}}}}}}", URI)),
+    ]);
+
+    let range = |start_line, start, end_line, end| format!(
+        "{{\"start\":{{\"line\":{},\"character\":{}}},\"end\":{{\"line\":{},\"character\":{}}}}}", start_line, start, end_line, end);
+
+    assert_eq!(replies[1], json(&format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[{{\"name\":\"M\",\"kind\":5,\"range\":{},\"selectionRange\":{},\"children\":[\
+         {{\"name\":\"m\",\"kind\":6,\"range\":{},\"selectionRange\":{},\"children\":[]}},\
+         {{\"name\":\"foo\",\"kind\":6,\"range\":{},\"selectionRange\":{},\"children\":[]}}]}}]}}",
+        range(0, 0, 2, 11), range(0, 1, 0, 2),
+        range(1, 0, 1, 3), range(1, 1, 1, 2),
+        range(2, 0, 2, 10), range(2, 1, 2, 6))));
+}
+
+#[test]
+fn lsp_go_to_definition() {
+    let other_uri = "file:///counter.glass";
+    let replies = lsp_session(&[
+        open_message(other_uri, "{(Counter)\n[c]\n[(inc)]}"),
+        open_message(URI, "{M[m(_c)(Counter)!(_c)(inc).?]}"),
+        position_request(1, "textDocument/definition", 0, 10),
+        position_request(2, "textDocument/definition", 0, 24),
+        position_request(3, "textDocument/definition", 0, 5),
+    ]);
+
+    assert_eq!(replies[2], json(&format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[{{\"uri\":\"{}\",\"range\":{{\"start\":{{\"line\":0,\"character\":1}},\"end\":{{\"line\":0,\"character\":10}}}}}}]}}",
+        other_uri)));
+    assert_eq!(replies[3], json(&format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":[{{\"uri\":\"{}\",\"range\":{{\"start\":{{\"line\":2,\"character\":1}},\"end\":{{\"line\":2,\"character\":6}}}}}}]}}",
+        other_uri)));
+    assert_eq!(replies[4], json("{\"jsonrpc\":\"2.0\",\"id\":3,\"result\":null}"));
+}
+
+#[test]
+fn lsp_hover_builtins() {
+    let replies = lsp_session(&[
+        open_message(URI, "{M[m(_o)O!\"hi\"(_o)o.?]}"),
+        position_request(1, "textDocument/hover", 0, 8),
+        position_request(2, "textDocument/hover", 0, 18),
+    ]);
+
+    let class_hover = replies[1].get("result").unwrap().get("contents").unwrap().get("value").unwrap().as_str().unwrap();
+    assert_eq!(class_hover, "**O**: Writing to the standard output and standard error\n\n\
+                              - **e** `s --` Writes a string to the standard error\n\
+                              - **en** `n --` Writes a number to the standard error\n\
+                              - **f** `--` Flushes whatever has been written to the standard output\n\
+                              - **o** `s --` Writes a string\n\
+                              - **on** `n --` Writes a number");
+
+    let method_hover = replies[2].get("result").unwrap().get("contents").unwrap().get("value").unwrap().as_str().unwrap();
+    assert_eq!(method_hover, "**File.o** `path mode --` Opens a file to read (`r`), write (`w`) or append to (`a`)\n\n\
+                              **O.o** `s --` Writes a string");
+}
+
+#[test]
+fn lsp_unknown_method() {
+    let replies = lsp_session(&[
+        frame("{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"workspace/unknown\"}"),
+        frame("]"),
+    ]);
+
+    assert_eq!(replies, vec![
+        json("{\"jsonrpc\":\"2.0\",\"id\":1,\"error\":{\"code\":-32601,\"message\":\"unknown method workspace/unknown\"}}"),
+        json("{\"jsonrpc\":\"2.0\",\"id\":null,\"error\":{\"code\":-32700,\"message\":\"unexpected ']'\"}}"),
+    ]);
+}
+
+#[test]
+fn lsp_command() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("lsp")
+       .write_stdin(frame("{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"shutdown\"}") + &frame("{\"jsonrpc\":\"2.0\",\"method\":\"exit\"}"))
+       .assert()
+       .success()
+       .stdout(frame("{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":null}"));
+
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use byte_string::ByteString;
+
+use glass::bytecode::BytecodeProgram;
+use glass::interpreter::{Interpreter, RuntimeError, Value};
+use glass::native::{NativeClass, NativeClasses, NativeContext, RegisterError};
+use glass::parser::parse_program_with_natives;
+
+struct Reverse;
+
+impl NativeClass for Reverse {
+    fn name(&self) -> &str {
+        "R"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &["r"]
+    }
+
+    fn call(&mut self, _method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        let mut string = context.pop_string()?.clone();
+        string.reverse();
+        context.push_string(string);
+        Ok(())
+    }
+}
+
+struct Tally {
+    count: f64,
+}
+
+impl NativeClass for Tally {
+    fn name(&self) -> &str {
+        "Tally"
+    }
+
+    fn methods(&self) -> &[&str] {
+        &["add", "get"]
+    }
+
+    fn call(&mut self, method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+        if method == 0 {
+            self.count += context.pop_number()?;
+        }
+        else {
+            context.push_number(self.count);
+        }
+        Ok(())
+    }
+}
+
+fn natives() -> NativeClasses {
+    let mut natives = NativeClasses::standard();
+    natives.register(Reverse).unwrap();
+    natives.register(Tally { count: 0.0 }).unwrap();
+    natives
+}
+
+fn compile(code: &str) -> BytecodeProgram {
+    let files = vec![("test.glass".to_owned(), code.as_bytes().to_vec())];
+    match parse_program_with_natives(&files, &natives()) {
+        Ok(program) => program,
+        Err(_) => panic!("test program failed to parse"),
+    }
+}
+
+fn run(program: BytecodeProgram, natives: NativeClasses) -> (Result<(), RuntimeError>, Vec<u8>) {
+    let mut output = Vec::new();
+    let result = Interpreter::new(program)
+        .native_classes(natives)
+        .output(&mut output)
+        .run();
+    (result.map_err(|err| err.error), output)
+}
+
+#[test]
+fn native_class_single_letter() {
+    let program = compile("{M[m(_o)O!(_r)R!\"abc\"(_r)r.?(_o)o.?]}");
+    let (result, output) = run(program, natives());
+
+    assert!(result.is_ok());
+    assert_eq!(output, b"cba");
+}
+
+#[test]
+fn native_class_with_state() {
+    let program = compile("{M[m(_o)O!(_t)(Tally)!<2>(_t)(add).?<3>(_t)(add).?(_t)(get).?(_o)(on).?]}");
+    let (result, output) = run(program, natives());
+
+    assert!(result.is_ok());
+    assert_eq!(output, b"5");
+}
+
+#[test]
+fn native_class_replaces_standard_class() {
+    struct Shout;
+
+    impl NativeClass for Shout {
+        fn name(&self) -> &str {
+            "O"
+        }
+
+        fn methods(&self) -> &[&str] {
+            &["o"]
+        }
+
+        fn call(&mut self, _method: usize, context: &mut NativeContext) -> Result<(), RuntimeError> {
+            let string = context.pop_string()?.to_ascii_uppercase();
+            context.output().write_all(&string).map_err(|err| RuntimeError::IOError(err.to_string()))
+        }
+    }
+
+    let mut natives = NativeClasses::standard();
+    natives.register(Shout).unwrap();
+
+    let files = vec![("test.glass".to_owned(), b"{M[m(_o)O!\"hi\"(_o)o.?]}".to_vec())];
+    let program = parse_program_with_natives(&files, &natives).unwrap();
+    let (result, output) = run(program, natives);
+
+    assert!(result.is_ok());
+    assert_eq!(output, b"HI");
+}
+
+#[test]
+fn native_class_wrong_type() {
+    let program = compile("{M[m(_r)R!<1>(_r)r.?]}");
+    let mut interpreter = Interpreter::new(program).native_classes(natives());
+
+    match interpreter.run() {
+        Err(err) => assert_eq!(err.to_string(), "in builtin R.r: expected string, got number 1"),
+        Ok(_) => panic!("reversing a number should fail"),
+    }
+}
+
+#[test]
+fn native_class_not_registered() {
+    let program = compile("{M[m(_r)R!\"abc\"(_r)r.?]}");
+    let (result, _) = run(program, NativeClasses::standard());
+
+    match result {
+        Err(RuntimeError::MissingNative(name)) => assert_eq!(name, "R.r"),
+        _ => panic!("calling an unregistered native method should fail"),
+    }
+}
+
+#[test]
+fn native_string_result() {
+    let program = compile("{M[m(_r)R!\"abc\"(_r)r.?]}");
+    let mut interpreter = Interpreter::new(program).native_classes(natives());
+    let main = interpreter.instantiate("M").unwrap();
+
+    let results = interpreter.call(main, "m", &[]).unwrap();
+    assert_eq!(results, vec![Value::String(ByteString::new(b"cba".to_vec()))]);
+}
+
+#[test]
+fn vars_class_without_standard_library() {
+    let files = vec![("test.glass".to_owned(), b"{M[m(_v)V!(_v)n.?<1>=(_v)n.?(_v)d.?]}".to_vec())];
+
+    for natives in [NativeClasses::new(), NativeClasses::default()] {
+        let program = parse_program_with_natives(&files, &natives).unwrap();
+        let mut interpreter = Interpreter::new(program).native_classes(natives);
+        let main = interpreter.instantiate("M").unwrap();
+
+        assert!(interpreter.call(main, "m", &[]).unwrap().is_empty());
+    }
+}
+
+struct Named {
+    name: &'static str,
+
+    methods: &'static [&'static str],
+}
+
+impl NativeClass for Named {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn methods(&self) -> &[&str] {
+        self.methods
+    }
+
+    fn call(&mut self, _method: usize, _context: &mut NativeContext) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn native_class_invalid_names() {
+    let mut natives = NativeClasses::standard();
+
+    for name in ["", "(Foo)", "Two words", "1st"] {
+        assert_eq!(natives.register(Named { name, methods: &["a"] }), Err(RegisterError::InvalidClassName(name.to_owned())));
+    }
+
+    assert_eq!(natives.register(Named { name: "V", methods: &["a"] }), Err(RegisterError::ReservedClassName("V".to_owned())));
+
+    let err = natives.register(Named { name: "Foo", methods: &["a", "b c"] }).unwrap_err();
+    assert_eq!(err, RegisterError::InvalidMethodName { class: "Foo".to_owned(), method: "b c".to_owned() });
+    assert_eq!(err.to_string(), "invalid method name \"b c\" in class Foo, expected letters, digits and _ not starting with a digit");
+
+    assert!(natives.register(Named { name: "Foo_2", methods: &["a", "bar"] }).is_ok());
+}
+
+#[test]
+fn native_class_duplicate_methods() {
+    let mut natives = NativeClasses::standard();
+    natives.register(Named { name: "D", methods: &["a", "a"] }).unwrap();
+
+    let files = vec![("test.glass".to_owned(), b"{M[m]}".to_vec())];
+    match parse_program_with_natives(&files, &natives) {
+        Err(errors) => assert_eq!(errors[0].to_string(), "<builtin>:0:0: error: duplicate function name"),
+        Ok(_) => panic!("a native class with two methods named a should fail to parse"),
+    }
+}
+
+#[test]
+fn native_class_too_many_methods() {
+    let methods: Vec<&'static str> = (0..1000).map(|index| &*format!("m{}", index).leak()).collect();
+    let methods = methods.leak();
+
+    let mut natives = NativeClasses::standard();
+    for index in 0..70 {
+        natives.register(Named { name: format!("N{}", index).leak(), methods }).unwrap();
+    }
+
+    let files = vec![("test.glass".to_owned(), b"{M[m]}".to_vec())];
+    match parse_program_with_natives(&files, &natives) {
+        Err(errors) => assert_eq!(errors[0].to_string(), "<builtin>:0:0: error: too many native methods"),
+        Ok(_) => panic!("more native methods than fit in a CallNative instruction should fail to parse"),
+    }
+}
+
+#[test]
+fn standard_classes_are_documented() {
+    for native in NativeClasses::standard().iter() {
+        assert!(!native.docs().is_empty(), "class {} has no docs", native.name());
+        assert_eq!(native.method_docs().len(), native.methods().len(), "class {} has a method without docs", native.name());
+    }
+}
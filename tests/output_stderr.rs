@@ -0,0 +1,64 @@
+mod common;
+
+use crate::common::glass_expression_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn output_stderr_string() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("\"out\"(_o)o.?\"Oh no!\\n\"(_o)e.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("out")
+       .stderr("Oh no!\n");
+
+    Ok(())
+}
+
+#[test]
+fn output_stderr_number() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("<-2.5>(_o)(en).?<10>(_o)(en).?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("")
+       .stderr("-2.510");
+
+    Ok(())
+}
+
+#[test]
+fn output_stderr_wrong_type() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("<1>(_o)e.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure();
+
+    let file = glass_expression_file("\"1\"(_o)(en).?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure();
+
+    Ok(())
+}
+
+#[test]
+fn output_stderr_empty_stack() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(_o)e.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure();
+
+    Ok(())
+}
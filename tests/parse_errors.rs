@@ -0,0 +1,227 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn parse_error_invalid_char() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\n  (_o)O!\t#]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stdout("")
+       .stderr(format!(
+           "{}:2:10: error: invalid character `#` in a function body, expected a command or `]` to end the function\n  (_o)O!\t#]}}\n        \t^\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_unended_string() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\"abc]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:1:11: error: unended string, expected `\"` before the end of the file\n{{M[m\"abc]}}\n          ^\n\
+            {path}:1:5: note: string starts here\n{{M[m\"abc]}}\n    ^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_second_file() -> Result<(), Box<dyn std::error::Error>> {
+    let main_file = glass_file("{M[m(_c)C!]}")?;
+    let class_file = glass_file("{C\n[(c__)]\n[(c__)]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(main_file.path())
+       .arg(class_file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:3:2: error: duplicate function name\n[(c__)]}}\n ^\n\
+            {path}:2:2: note: first defined here\n[(c__)]\n ^\n",
+           path = class_file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_missing_main_class() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{C[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr("error: missing main class\n");
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_multiple_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m#][a(_o)O!]}\n{C[c%]}\n}{D}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:1:5: error: invalid character `#` in a function body, expected a command or `]` to end the function\n{{M[m#][a(_o)O!]}}\n    ^\n\
+            {path}:2:5: error: invalid character `%` in a function body, expected a command or `]` to end the function\n{{C[c%]}}\n    ^\n\
+            {path}:3:1: error: invalid character `}}` outside of a class, expected `{{` to start a class\n}}{{D}}\n^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_unended_string_no_cascade() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\"abc]}\n{C[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:2:7: error: unended string, expected `\"` before the end of the file\n{{C[c]}}\n      ^\n\
+            {path}:1:5: note: string starts here\n{{M[m\"abc]}}\n    ^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_unended_parentheses_no_cascade() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o O!]}\n{C[c<1]}\n{D[d]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:1:11: error: unended parentheses, expected `)` before `]`\n{{M[m(_o O!]}}\n          ^\n\
+            {path}:1:5: note: parentheses open here\n{{M[m(_o O!]}}\n    ^\n\
+            {path}:2:7: error: unended number, expected `>` before `]`\n{{C[c<1]}}\n      ^\n\
+            {path}:2:5: note: number starts here\n{{C[c<1]}}\n    ^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_unended_function() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o)O!}\n{C[c(_o)O!\n[d]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:1:3: error: unended function\n{{M[m(_o)O!}}\n  ^\n\
+            {path}:2:3: error: unended function\n{{C[c(_o)O!\n  ^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_main_class_only_referenced() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{C[cM]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr("error: missing main class\n");
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_invalid_char_in_class_body() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m]\n  m[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{}:2:3: error: invalid character `m` in a class body, expected `[` to start a function or `}}` to end the class\n  m[c]}}\n  ^\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_loop_end_without_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m<1>,\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{}:1:9: error: invalid character `\\`, there's no loop for it to end\n{{M[m<1>,\\]}}\n        ^\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_unended_parentheses_at_end_of_line() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_o\n]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{path}:1:8: error: unended parentheses, expected `)` before the end of the line\n{{M[m(_o\n       ^\n\
+            {path}:1:5: note: parentheses open here\n{{M[m(_o\n    ^\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_duplicate_class_name() -> Result<(), Box<dyn std::error::Error>> {
+    let main_file = glass_file("{M[m]}\n{C[c]}")?;
+    let class_file = glass_file("{C[d]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(main_file.path())
+       .arg(class_file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{}:1:2: error: duplicate class name\n{{C[d]}}\n ^\n\
+            {}:2:2: note: first defined here\n{{C[c]}}\n ^\n",
+           class_file.path().display(), main_file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_missing_main_func() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[a]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "{}:1:2: error: missing main function, class M needs a method m for the program to start from\n{{M[a]}}\n ^\n",
+           file.path().display()));
+
+    Ok(())
+}
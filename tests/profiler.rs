@@ -0,0 +1,96 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+const PROGRAM: &str = "{M[m(_c)C!<1>(_c)f.?<2>(_c)f.?]}\n{C[f(_a)A!<1>(_a)a.?,]}";
+
+// Gets the name, calls, instructions and percentage from each row of a profile, leaving
+// out the times that change from run to run
+fn profile_counts(report: &[u8]) -> Vec<Vec<String>> {
+    String::from_utf8_lossy(report)
+        .lines()
+        .map(|line| line.split_whitespace().take(4).map(|column| column.to_owned()).collect())
+        .collect()
+}
+
+#[test]
+fn profile_report() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--profile")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("");
+
+    assert_eq!(profile_counts(&assert.get_output().stderr), vec![
+        vec!["Function", "Calls", "Instructions", "%"],
+        vec!["C.f", "2", "26", "55.32%"],
+        vec!["M.m", "1", "17", "36.17%"],
+        vec!["A.a", "2", "4", "8.51%"],
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn profile_recursive_calls() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)C!<3>(_c)f.?]}\n{C[f(_n)1=,/(_n)(_n)*<1>(_a)A!(_a)s.?(_c)C!(_c)f.?(_n)<0>=\\]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--profile")
+       .arg(file.path())
+       .assert()
+       .success();
+
+    let counts = profile_counts(&assert.get_output().stderr);
+    let calls: Vec<_> = counts.iter().skip(1).map(|row| (row[0].as_str(), row[1].as_str())).collect();
+
+    assert_eq!(calls, vec![("C.f", "4"), ("M.m", "1"), ("A.s", "3")]);
+
+    Ok(())
+}
+
+#[test]
+fn profile_after_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)C!(_c)f.?]}\n{C[f(_x)*]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--profile")
+       .arg(file.path())
+       .assert()
+       .failure();
+
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    let (report, error) = stderr.split_at(stderr.find("Error:").unwrap());
+
+    assert_eq!(profile_counts(report.as_bytes()), vec![
+        vec!["Function", "Calls", "Instructions", "%"],
+        vec!["M.m", "1", "10", "83.33%"],
+        vec!["C.f", "1", "2", "16.67%"],
+    ]);
+    assert!(error.starts_with("Error: name (_x) has not been set\n"));
+
+    Ok(())
+}
+
+#[test]
+fn profile_collapsed_stacks() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file(PROGRAM)?;
+    let stacks_file = assert_fs::NamedTempFile::new("stacks.txt")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--profile-stacks")
+       .arg(stacks_file.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stderr("");
+
+    assert_eq!(std::fs::read_to_string(stacks_file.path())?, "M.m 17\nM.m;C.f 26\nM.m;C.f;A.a 4\n");
+
+    Ok(())
+}
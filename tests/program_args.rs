@@ -0,0 +1,79 @@
+mod common;
+
+use crate::common::glass_expression_file;
+
+use assert_cmd::Command;
+
+const PRINT_ARGS: &str = "(_g)(Args)!(_g)l.?(_o)(on).?(_n)<0>=(_c)(_n)*(_g)l.?(_a)(lt).?=\
+                          /(_c)\" \"(_o)o.?(_n)*(_g)i.?(_o)o.?(_n)(_n)*<1>(_a)a.?=(_c)(_n)*(_g)l.?(_a)(lt).?=\\";
+
+#[test]
+fn program_args() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file(PRINT_ARGS)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .arg("--")
+       .arg("first")
+       .arg("--trace")
+       .arg("")
+       .assert()
+       .success()
+       .stdout("3 first --trace ")
+       .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn program_args_none_given() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file(PRINT_ARGS)?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .success()
+       .stdout("0");
+
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .arg("--")
+       .assert()
+       .success()
+       .stdout("0");
+
+    Ok(())
+}
+
+#[test]
+fn program_args_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(_g)(Args)!<1>(_g)i.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg(file.path())
+       .arg("--")
+       .arg("only")
+       .assert()
+       .failure();
+    assert!(assert.get_output().stderr.starts_with(b"Error: in builtin Args.i: there's no argument at index 1, the program was given 1 arguments\n"));
+
+    Ok(())
+}
+
+#[test]
+fn program_args_after_options() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_expression_file("(_g)(Args)!<0>(_g)i.?(_o)o.?")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("--max-instructions")
+       .arg("1000")
+       .arg(file.path())
+       .arg("--")
+       .arg("x.glass")
+       .assert()
+       .success()
+       .stdout("x.glass");
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use glass::interpreter::Limits;
+use glass::repl::run_repl;
+
+use assert_cmd::Command;
+
+// Runs a REPL session on some input, giving what it wrote to its output and error output
+fn repl_session(input: &str) -> (String, String) {
+    let mut output = Vec::new();
+    let mut error_output = Vec::new();
+
+    run_repl(input.as_bytes(), &mut output, &mut error_output, Limits::default())
+        .expect("REPL failed to write its output");
+
+    (String::from_utf8(output).unwrap(), String::from_utf8(error_output).unwrap())
+}
+
+#[test]
+fn repl_stack_kept_between_entries() {
+    let (output, error_output) = repl_session("<1><2>\n\"a\"\n,\n");
+
+    assert_eq!(output, "glass> Stack: number 1, number 2\n\
+                        glass> Stack: number 1, number 2, string \"a\"\n\
+                        glass> Stack: number 1, number 2\n\
+                        glass> \n");
+    assert_eq!(error_output, "");
+}
+
+#[test]
+fn repl_define_and_use_class() {
+    let (output, error_output) = repl_session("{C[c<3><4>]}\nc C!\nc c.?\n");
+
+    assert_eq!(output, "glass> glass> Stack: empty\n\
+                        glass> Stack: number 3, number 4\n\
+                        glass> \n");
+    assert_eq!(error_output, "");
+}
+
+#[test]
+fn repl_members_kept_between_entries() {
+    let (output, _) = repl_session("x<5>=\nx*\n");
+
+    assert_eq!(output, "glass> Stack: empty\nglass> Stack: number 5\nglass> \n");
+}
+
+#[test]
+fn repl_multiline_entry() {
+    let (output, error_output) = repl_session("{C\n[c\"a\"]\n}\nc C!\nc c.?\n");
+
+    assert_eq!(output, "glass>   ...>   ...> glass> Stack: empty\n\
+                        glass> Stack: string \"a\"\n\
+                        glass> \n");
+    assert_eq!(error_output, "");
+}
+
+#[test]
+fn repl_survives_runtime_error() {
+    let (output, error_output) = repl_session("x<1>=<2>\n(_y)*\nx*\n");
+
+    assert_eq!(output, "glass> Stack: number 2\n\
+                        glass> Stack: number 2\n\
+                        glass> Stack: number 2, number 1\n\
+                        glass> \n");
+    assert_eq!(error_output, "Error: name (_y) has not been set\n\
+                              Traceback:\n  In file <repl 2> on line 1, column 5\n");
+}
+
+#[test]
+fn repl_survives_parse_error() {
+    let (output, error_output) = repl_session("{C[c<1>]}\n{C[d]}\n<1>#\nc C!c c.?\n");
+
+    assert_eq!(output, "glass> glass> glass> glass> Stack: number 1\nglass> \n");
+    assert_eq!(error_output, "<repl 2>:1:2: error: duplicate class name\n{C[d]}\n ^\n\
+                              <repl 3>:1:4: error: invalid character `#` where a command was expected\n<1>#\n   ^\n");
+}
+
+#[test]
+fn repl_program_reads_from_same_input() {
+    let (output, _) = repl_session("(_i)I!(_i)l.?\ntyped line\n(_o)O!(_o)o.?\n");
+
+    assert_eq!(output, "glass> Stack: string \"typed line\\n\"\n\
+                        glass> typed line\nStack: empty\n\
+                        glass> \n");
+}
+
+#[test]
+fn repl_command() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg("repl")
+       .write_stdin("<1><2>\n(_a)A!(_a)a.?\n")
+       .assert()
+       .success()
+       .stdout("glass> Stack: number 1, number 2\nglass> Stack: number 3\nglass> \n")
+       .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn repl_command_with_files() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("repl")
+       .arg("program.glass")
+       .assert()
+       .failure();
+
+    assert!(assert.get_output().stderr.starts_with(b"The REPL doesn't take input files\n"));
+
+    Ok(())
+}
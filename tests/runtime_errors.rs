@@ -0,0 +1,65 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn runtime_error_unset_name() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_foo)*]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: name (_foo) has not been set\nTraceback:\n  In file {} on line 1, column 11\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn runtime_error_wrong_type() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_a)A!(_c)C!<1>(_c)*(_a)a.?]}{C[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: in builtin A.a: expected number, got instance of class C\nTraceback:\n  In builtin A.a\n  In file {} on line 1, column 31\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn runtime_error_invalid_index() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_s)S!\"abc\"<5>(_s)i.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: in builtin S.i: index 5 is out of range for a string of length 3\nTraceback:\n  In builtin S.i\n  In file {} on line 1, column 25\n",
+           file.path().display()));
+
+    Ok(())
+}
+
+#[test]
+fn runtime_error_missing_member() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_c)(Foo)!(_c)(bar).?]}{(Foo)[c]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    cmd.arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "Error: instance of class (Foo) has no member or method (bar)\nTraceback:\n  In file {} on line 1, column 24\n",
+           file.path().display()));
+
+    Ok(())
+}
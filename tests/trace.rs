@@ -0,0 +1,122 @@
+mod common;
+
+use crate::common::glass_file;
+
+use assert_cmd::Command;
+
+#[test]
+fn trace_to_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m<1>,\n(_x)\"ab\"=]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--trace")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("")
+       .stderr(format!(
+           "000164  PushNumber    <1>               {path}:1:5  stack: empty\n\
+            000167  Pop                             {path}:1:8  stack: number 1\n\
+            000168  PushLocal     (_x)              {path}:2:1  stack: empty\n\
+            000171  PushString    \"ab\"              {path}:2:5  stack: name (_x)\n\
+            000174  Store                           {path}:2:9  stack: name (_x), string \"ab\"\n\
+            000175  Return                          {path}:2:10  stack: empty\n"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_shows_top_of_stack() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m<1><2><3><4>]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--trace")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stderr(format!(
+           "000164  PushNumber    <1>               {path}:1:5  stack: empty\n\
+            000167  PushNumber    <2>               {path}:1:8  stack: number 1\n\
+            000170  PushNumber    <3>               {path}:1:11  stack: number 1, number 2\n\
+            000173  PushNumber    <4>               {path}:1:14  stack: number 1, number 2, number 3\n\
+            000176  Return                          {path}:1:17  stack: ..., number 2, number 3, number 4\n"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_into_builtins() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m\"a\"(_o)O!(_o)o.?]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let output = cmd.arg("--trace")
+       .arg(file.path())
+       .assert()
+       .success()
+       .stdout("a")
+       .get_output()
+       .stderr
+       .clone();
+
+    let trace = String::from_utf8(output)?;
+    assert!(trace.contains("Call                            "));
+    assert!(trace.contains("CallNative    O.o               <builtin>:0:0  stack: string \"a\"\n"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_to_file() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m<1>,]}")?;
+    let trace_file = assert_fs::NamedTempFile::new("trace.txt")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--trace-file")
+       .arg(trace_file.path())
+       .arg(file.path())
+       .assert()
+       .success()
+       .stderr("");
+
+    assert_eq!(std::fs::read_to_string(trace_file.path())?, format!(
+        "000164  PushNumber    <1>               {path}:1:5  stack: empty\n\
+         000167  Pop                             {path}:1:8  stack: number 1\n\
+         000168  Return                          {path}:1:9  stack: empty\n"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_until_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = glass_file("{M[m(_x)*]}")?;
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let path = file.path().display();
+    cmd.arg("--trace")
+       .arg(file.path())
+       .assert()
+       .failure()
+       .stderr(format!(
+           "000164  PushLocal     (_x)              {path}:1:5  stack: empty\n\
+            000167  Load                            {path}:1:9  stack: name (_x)\n\
+            Error: name (_x) has not been set\n\
+            Traceback:\n  In file {path} on line 1, column 9\n"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_file_missing_name() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("glass")?;
+
+    let assert = cmd.arg("--trace-file")
+       .assert()
+       .failure();
+
+    assert!(assert.get_output().stderr.starts_with(b"Missing output file for --trace-file\n"));
+
+    Ok(())
+}
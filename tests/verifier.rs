@@ -0,0 +1,104 @@
+use glass::bytecode::{BytecodeProgram, OpCode};
+use glass::parser::parse_program;
+use glass::verifier::{verify, VerifyErrorKind};
+
+fn compile(code: &str) -> BytecodeProgram {
+    let files = vec![("test.glass".to_owned(), code.as_bytes().to_vec())];
+    match parse_program(&files) {
+        Ok(program) => program,
+        Err(_) => panic!("test program failed to parse"),
+    }
+}
+
+fn main_func_index(program: &BytecodeProgram) -> usize {
+    let class_index = program.class_names.iter().position(|name| *name == program.main_class).unwrap();
+    program.classes[class_index].funcs[&program.main_func]
+}
+
+fn verify_kind(program: &BytecodeProgram) -> Option<VerifyErrorKind> {
+    verify(program).err().map(|err| err.kind)
+}
+
+#[test]
+fn verify_examples() -> Result<(), Box<dyn std::error::Error>> {
+    for entry in std::fs::read_dir("examples")? {
+        let path = entry?.path();
+        let files = vec![(path.display().to_string(), std::fs::read(&path)?)];
+        let program = match parse_program(&files) {
+            Ok(program) => program,
+            Err(_) => panic!("{} failed to parse", path.display()),
+        };
+
+        assert_eq!(verify(&program), Ok(()), "{} failed to verify", path.display());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn verify_invalid_opcode() {
+    let mut program = compile("{M[m<1>,]}");
+    let index = main_func_index(&program);
+    program.instructions[index] = 250;
+
+    let err = verify(&program).unwrap_err();
+    assert_eq!(err.kind, VerifyErrorKind::InvalidOpcode(250));
+    assert_eq!(err.index, Some(index));
+}
+
+#[test]
+fn verify_truncated_instruction() {
+    let mut program = compile("{M[m\"a\"]}");
+    let index = main_func_index(&program);
+    program.instructions.truncate(index + 2);
+
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::TruncatedInstruction));
+}
+
+#[test]
+fn verify_missing_return() {
+    let mut program = compile("{M[m<1>,]}");
+    let last = program.instructions.len() - 1;
+    program.instructions[last] = OpCode::Pop as u8;
+
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::MissingReturn));
+}
+
+#[test]
+fn verify_invalid_constants() {
+    let mut program = compile("{M[m\"a\"]}");
+    program.strings.clear();
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::InvalidString));
+
+    let mut program = compile("{M[m<1>]}");
+    program.numbers.clear();
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::InvalidNumber));
+
+    let mut program = compile("{M[m(_a)]}");
+    program.local_names.clear();
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::InvalidLocalName));
+}
+
+#[test]
+fn verify_invalid_jump() {
+    let mut program = compile("{M[m/a<1>,\\]}");
+    let jump_index = main_func_index(&program) + 4;
+    assert_eq!(program.instructions[jump_index], OpCode::JumpIfNot as u8);
+
+    program.instructions[jump_index + 2] += 1;
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::InvalidJumpTarget));
+
+    program.instructions[jump_index + 1] = 0xff;
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::InvalidJumpTarget));
+}
+
+#[test]
+fn verify_missing_main() {
+    let mut program = compile("{M[m]}{C[c]}");
+    program.main_func = program.member_names.iter().position(|name| name.as_slice() == b"c").unwrap() as u16;
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::MissingMainFunc));
+
+    let mut program = compile("{M[m]}");
+    program.main_class = program.global_names.len() as u16;
+    assert_eq!(verify_kind(&program), Some(VerifyErrorKind::MissingMainClass));
+}